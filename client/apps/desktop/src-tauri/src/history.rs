@@ -0,0 +1,121 @@
+//! Undo and redo for library edits made from the app.
+//!
+//! Each edit records a snapshot of the library as it was before the change.
+//! Undoing swaps the library for the latest snapshot and keeps the replaced
+//! state for redo.
+
+use std::collections::VecDeque;
+
+use promptgen_core::Library;
+
+/// How many edits can be undone per library.
+pub const UNDO_LIMIT: usize = 20;
+
+/// Snapshots of one library around its recent edits.
+#[derive(Debug, Default)]
+pub struct EditHistory {
+    /// States before each edit, oldest first
+    undo: VecDeque<Library>,
+    /// States replaced by undo, most recent last
+    redo: Vec<Library>,
+}
+
+impl EditHistory {
+    /// Record the state of a library before an edit. Clears the redo steps,
+    /// and forgets the oldest step once there are more than [`UNDO_LIMIT`].
+    pub fn record(&mut self, before: Library) {
+        self.undo.push_back(before);
+        if self.undo.len() > UNDO_LIMIT {
+            self.undo.pop_front();
+        }
+        self.redo.clear();
+    }
+
+    /// The state before the latest edit, if any. `current` becomes
+    /// available to [`EditHistory::redo`].
+    pub fn undo(&mut self, current: &Library) -> Option<Library> {
+        let previous = self.undo.pop_back()?;
+        self.redo.push(current.clone());
+        Some(previous)
+    }
+
+    /// The state replaced by the latest undo, if any. `current` can be
+    /// undone again.
+    pub fn redo(&mut self, current: &Library) -> Option<Library> {
+        let next = self.redo.pop()?;
+        self.undo.push_back(current.clone());
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use promptgen_core::PromptGroup;
+
+    fn library(options: &[&str]) -> Library {
+        let mut lib = Library::with_id("lib", "Test");
+        lib.groups
+            .push(PromptGroup::with_options("Hair", options.to_vec()));
+        lib
+    }
+
+    fn hair(lib: &Library) -> Vec<String> {
+        lib.find_group("Hair").unwrap().options.clone()
+    }
+
+    #[test]
+    fn test_undo_restores_previous_groups() {
+        let mut history = EditHistory::default();
+        let first = library(&["red hair"]);
+        let second = library(&["red hair", "black hair"]);
+        history.record(first);
+
+        let restored = history.undo(&second).unwrap();
+        assert_eq!(hair(&restored), vec!["red hair"]);
+        assert!(history.undo(&restored).is_none());
+    }
+
+    #[test]
+    fn test_redo_reapplies_undone_edit() {
+        let mut history = EditHistory::default();
+        let first = library(&["red hair"]);
+        let second = library(&["black hair"]);
+        history.record(first);
+
+        let restored = history.undo(&second).unwrap();
+        let redone = history.redo(&restored).unwrap();
+        assert_eq!(hair(&redone), vec!["black hair"]);
+        assert!(history.redo(&redone).is_none());
+
+        // The redone edit can be undone again
+        assert_eq!(hair(&history.undo(&redone).unwrap()), vec!["red hair"]);
+    }
+
+    #[test]
+    fn test_new_edit_clears_redo() {
+        let mut history = EditHistory::default();
+        history.record(library(&["a"]));
+        let restored = history.undo(&library(&["b"])).unwrap();
+
+        history.record(restored);
+        assert!(history.redo(&library(&["c"])).is_none());
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut history = EditHistory::default();
+        for i in 0..UNDO_LIMIT + 5 {
+            history.record(library(&[&i.to_string()]));
+        }
+
+        let mut current = library(&["latest"]);
+        let mut steps = 0;
+        while let Some(previous) = history.undo(&current) {
+            current = previous;
+            steps += 1;
+        }
+        assert_eq!(steps, UNDO_LIMIT);
+        assert_eq!(hair(&current), vec!["5"]);
+    }
+}
@@ -3,15 +3,27 @@
 //! This module exposes promptgen-core functionality to the frontend via Tauri commands.
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::PathBuf;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
 use std::sync::Mutex;
 
 use promptgen_core::{
-    load_library as core_load_library, parse_template, render, save_library as core_save_library,
-    EvalContext, Library, ParseError, PromptTemplate,
+    ambiguous_group_names, load_library as core_load_library, parse_template,
+    parse_template_recover, render, save_library as core_save_library, template_to_source,
+    DiagnosticError, EvalContext, Library, MissingRefPolicy, ParseResult, PromptTemplate,
+    RenderResult, Template,
 };
+use tauri::Emitter;
+
+mod history;
+#[cfg(feature = "watch")]
+mod watcher;
+
+use history::EditHistory;
 
 // ============================================================================
 // State management
@@ -23,6 +35,13 @@ pub struct AppState {
     libraries: Mutex<HashMap<String, (Library, PathBuf)>>,
     /// Current library home directory
     library_home: Mutex<Option<PathBuf>>,
+    /// Tells the file watcher, if running, which directory to watch
+    home_watch: Mutex<Option<Sender<PathBuf>>>,
+    /// Undo and redo snapshots by library ID
+    history: Mutex<HashMap<String, EditHistory>>,
+    /// Hash of each library file's contents as the app last saved it, so
+    /// the file watcher can skip the app's own writes
+    own_writes: Mutex<HashMap<PathBuf, u64>>,
 }
 
 impl Default for AppState {
@@ -30,10 +49,96 @@ impl Default for AppState {
         Self {
             libraries: Mutex::new(HashMap::new()),
             library_home: Mutex::new(None),
+            home_watch: Mutex::new(None),
+            history: Mutex::new(HashMap::new()),
+            own_writes: Mutex::new(HashMap::new()),
         }
     }
 }
 
+/// Point the file watcher at a new library home.
+fn watch_library_home(state: &AppState, path: &Path) {
+    if let Some(tx) = state.home_watch.lock().unwrap().as_ref() {
+        let _ = tx.send(path.to_path_buf());
+    }
+}
+
+/// Whether `path` has a library file extension.
+fn is_library_file(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext == "yml" || ext == "yaml")
+        .unwrap_or(false)
+}
+
+/// Save a library and remember the write, so the file watcher doesn't
+/// reload it as an external change.
+fn save_library_file(state: &AppState, lib: &Library, path: &Path) -> Result<(), String> {
+    core_save_library(lib, path).map_err(|e| e.to_string())?;
+    if let Ok(contents) = fs::read(path) {
+        state
+            .own_writes
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), content_hash(&contents));
+    }
+    Ok(())
+}
+
+/// Hash of a file's contents, to tell the app's own saves from other writes.
+fn content_hash(contents: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Reload the library stored at `path` after its file changed outside the
+/// app. Its undo history is dropped, since it describes the old contents.
+///
+/// Returns the id of the library that was reloaded, added or removed.
+/// Returns `None` for non-library files, for the app's own saves, and for
+/// files that fail to load, such as one caught halfway through a write; the
+/// loaded copy is kept.
+fn reload_library_at(state: &AppState, path: &Path) -> Option<String> {
+    if !is_library_file(path) {
+        return None;
+    }
+    // Compare contents rather than modified times, which can be too coarse
+    // to tell a write made just after a save
+    if let Ok(contents) = fs::read(path) {
+        if state.own_writes.lock().unwrap().get(path) == Some(&content_hash(&contents)) {
+            return None;
+        }
+    }
+
+    // Load before locking, so other commands don't wait on the disk
+    let loaded = if path.exists() {
+        Some(core_load_library(path).ok()?)
+    } else {
+        None
+    };
+
+    let mut libs = state.libraries.lock().unwrap();
+    let mut history = state.history.lock().unwrap();
+    // The id may have been edited, so drop whatever was loaded from this path
+    let previous: Vec<String> = libs
+        .iter()
+        .filter(|(_, (_, lib_path))| lib_path == path)
+        .map(|(id, _)| id.clone())
+        .collect();
+    for id in &previous {
+        libs.remove(id);
+        history.remove(id);
+    }
+
+    let Some(lib) = loaded else {
+        return previous.into_iter().next();
+    };
+    let id = lib.id.clone();
+    history.remove(&id);
+    libs.insert(id.clone(), (lib, path.to_path_buf()));
+    Some(id)
+}
+
 // ============================================================================
 // Config persistence
 // ============================================================================
@@ -80,11 +185,33 @@ fn save_config(config: &AppConfig) -> Result<(), String> {
 pub struct LibrarySummary {
     pub id: String,
     pub name: String,
+    pub description: String,
     pub path: String,
     pub template_count: usize,
     pub last_modified: String,
 }
 
+/// A group name defined in several loaded libraries.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AmbiguousGroupDto {
+    pub name: String,
+    pub libraries: Vec<String>,
+}
+
+/// Counts for a library info panel.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryStatsDto {
+    pub group_count: usize,
+    pub option_count: usize,
+    /// Mean options per group; 0 for a library without groups.
+    pub average_options: f64,
+    pub template_count: usize,
+    /// Groups that no template uses, directly or through other groups.
+    pub unused_group_count: usize,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LibraryDto {
@@ -92,7 +219,23 @@ pub struct LibraryDto {
     pub name: String,
     pub path: String,
     pub templates: Vec<TemplateDto>,
-    pub wildcards: HashMap<String, Vec<String>>,
+    /// Group options by name, sorted so the JSON comes out the same each time.
+    pub wildcards: BTreeMap<String, Vec<String>>,
+    /// Read-only here: saving from the UI keeps the library's existing value.
+    #[serde(default)]
+    pub default_seed: Option<u64>,
+    /// Option notes by group, then option text. Read-only, like `default_seed`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub option_meta: BTreeMap<String, BTreeMap<String, OptionMetaDto>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OptionMetaDto {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -101,6 +244,17 @@ pub struct TemplateDto {
     pub id: String,
     pub name: String,
     pub content: String,
+    #[serde(default)]
+    pub description: String,
+    /// Read-only here: saving from the UI keeps the template's existing defaults.
+    #[serde(default)]
+    pub default_slots: HashMap<String, String>,
+    /// Read-only here, like `default_slots`.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// References to unknown groups found when the template was saved.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<ParseErrorDto>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -116,6 +270,7 @@ pub struct ParseResultDto {
 pub struct ParseErrorDto {
     pub message: String,
     pub span: SpanDto,
+    pub suggestion: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -131,13 +286,48 @@ pub struct RenderInput {
     pub library_id: String,
     pub bindings: Option<HashMap<String, String>>,
     pub seed: Option<u64>,
+    /// Per-group salts that re-roll one group's pick without changing the others.
+    #[serde(default)]
+    pub group_salts: HashMap<String, u64>,
+    /// Render references to missing groups as written instead of failing,
+    /// so a preview still shows the rest of the template.
+    #[serde(default)]
+    pub keep_missing_refs: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct RenderResultDto {
     pub success: bool,
     pub output: Option<String>,
     pub error: Option<String>,
+    /// Seed the render used: the requested one or the library's default.
+    pub seed: Option<u64>,
+    pub chosen_options: Vec<ChosenOptionDto>,
+    /// A problem that didn't stop the render, such as references left as
+    /// written under `keep_missing_refs`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notice: Option<String>,
+}
+
+/// Payload of the `render-progress` event sent during a batch render.
+#[derive(Debug, Clone, Serialize)]
+pub struct RenderProgressDto {
+    pub done: u64,
+    pub total: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChosenOptionDto {
+    pub group: String,
+    pub library: Option<String>,
+    pub option: String,
+    /// The alias that was picked, when it rendered as `option`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    /// Index of the choice whose option held this reference.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<usize>,
 }
 
 // ============================================================================
@@ -156,6 +346,44 @@ impl From<&Library> for LibraryDto {
                 .iter()
                 .map(|g| (g.name.clone(), g.options.clone()))
                 .collect(),
+            default_seed: lib.default_seed,
+            option_meta: lib
+                .groups
+                .iter()
+                .filter(|g| !g.option_meta.is_empty())
+                .map(|g| {
+                    let meta = g
+                        .option_meta
+                        .iter()
+                        .map(|(option, meta)| {
+                            let dto = OptionMetaDto {
+                                note: meta.note.clone(),
+                                source: meta.source.clone(),
+                            };
+                            (option.clone(), dto)
+                        })
+                        .collect();
+                    (g.name.clone(), meta)
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<&Library> for LibraryStatsDto {
+    fn from(lib: &Library) -> Self {
+        let group_count = lib.groups.len();
+        let option_count: usize = lib.groups.iter().map(|g| g.options.len()).sum();
+        LibraryStatsDto {
+            group_count,
+            option_count,
+            average_options: if group_count == 0 {
+                0.0
+            } else {
+                option_count as f64 / group_count as f64
+            },
+            template_count: lib.templates.len(),
+            unused_group_count: lib.find_unused_groups().len(),
         }
     }
 }
@@ -165,52 +393,75 @@ impl From<&PromptTemplate> for TemplateDto {
         TemplateDto {
             id: template.id.clone(),
             name: template.name.clone(),
-            content: template
-                .ast
-                .nodes
-                .iter()
-                .map(|(node, _)| node_to_string(node))
-                .collect::<String>(),
+            content: template_to_source(&template.ast),
+            description: template.description.clone(),
+            default_slots: template.default_slots.clone(),
+            tags: template.tags.clone(),
+            warnings: Vec::new(),
         }
     }
 }
 
-fn node_to_string(node: &promptgen_core::Node) -> String {
-    match node {
-        promptgen_core::Node::Text(s) => s.clone(),
-        promptgen_core::Node::Comment(s) => format!("# {}", s),
-        promptgen_core::Node::Slot(name) => format!("{{{{ {} }}}}", name),
-        promptgen_core::Node::LibraryRef(lib_ref) => {
-            if let Some(lib) = &lib_ref.library {
-                format!("@\"{}:{}\"", lib, lib_ref.group)
-            } else if lib_ref.group.contains(' ') {
-                format!("@\"{}\"", lib_ref.group)
-            } else {
-                format!("@{}", lib_ref.group)
-            }
-        }
-        promptgen_core::Node::InlineOptions(opts) => {
-            let inner: Vec<String> = opts
-                .iter()
-                .map(|opt| match opt {
-                    promptgen_core::OptionItem::Text(s) => s.clone(),
-                    promptgen_core::OptionItem::Nested(nodes) => {
-                        nodes.iter().map(|(n, _)| node_to_string(n)).collect()
-                    }
+impl RenderResultDto {
+    fn rendered(result: RenderResult, seed: Option<u64>) -> Self {
+        RenderResultDto {
+            success: true,
+            output: Some(result.text),
+            error: None,
+            seed,
+            chosen_options: result
+                .chosen_options
+                .into_iter()
+                .map(|c| ChosenOptionDto {
+                    group: c.group_name,
+                    library: c.library_name,
+                    option: c.option_text,
+                    alias: c.alias,
+                    parent: c.parent,
                 })
-                .collect();
-            format!("{{{}}}", inner.join("|"))
+                .collect(),
+            notice: None,
+        }
+    }
+
+    fn failed(error: String, seed: Option<u64>) -> Self {
+        RenderResultDto {
+            success: false,
+            output: None,
+            error: Some(error),
+            seed,
+            chosen_options: Vec::new(),
+            notice: None,
         }
     }
 }
 
-fn parse_error_to_dto(err: &ParseError) -> ParseErrorDto {
-    ParseErrorDto {
-        message: err.to_string(),
-        span: SpanDto {
-            start: 0, // ParseError doesn't expose span currently
-            end: 0,
-        },
+impl From<&DiagnosticError> for ParseErrorDto {
+    fn from(err: &DiagnosticError) -> Self {
+        ParseErrorDto {
+            message: err.message.clone(),
+            span: SpanDto {
+                start: err.span.start,
+                end: err.span.end,
+            },
+            suggestion: err.suggestion.clone(),
+        }
+    }
+}
+
+impl From<ParseResult> for ParseResultDto {
+    fn from(result: ParseResult) -> Self {
+        ParseResultDto {
+            success: result.is_ok(),
+            ast: result
+                .template
+                .and_then(|ast| serde_json::to_value(&ast).ok()),
+            errors: if result.errors.is_empty() {
+                None
+            } else {
+                Some(result.errors.iter().map(ParseErrorDto::from).collect())
+            },
+        }
     }
 }
 
@@ -240,9 +491,11 @@ fn set_library_home(path: String, state: tauri::State<AppState>) -> Result<(), S
     {
         let mut libs = state.libraries.lock().unwrap();
         libs.clear();
+        state.history.lock().unwrap().clear();
     }
 
     // Set the new home in state
+    watch_library_home(&state, &lib_path);
     {
         let mut home = state.library_home.lock().unwrap();
         *home = Some(lib_path);
@@ -273,6 +526,7 @@ fn get_library_home_cmd(state: tauri::State<AppState>) -> Option<String> {
         // Verify the directory still exists
         if path.exists() && path.is_dir() {
             // Update state with the loaded value
+            watch_library_home(&state, &path);
             let mut home = state.library_home.lock().unwrap();
             *home = Some(path);
             return Some(path_str.clone());
@@ -300,11 +554,7 @@ fn list_libraries(state: tauri::State<AppState>) -> Result<Vec<LibrarySummary>,
 
     for entry in entries.flatten() {
         let path = entry.path();
-        if path
-            .extension()
-            .map(|ext| ext == "yml" || ext == "yaml")
-            .unwrap_or(false)
-        {
+        if is_library_file(&path) {
             if let Ok(lib) = core_load_library(&path) {
                 let metadata = fs::metadata(&path).ok();
                 let last_modified = metadata
@@ -325,6 +575,7 @@ fn list_libraries(state: tauri::State<AppState>) -> Result<Vec<LibrarySummary>,
                 summaries.push(LibrarySummary {
                     id: lib.id.clone(),
                     name: lib.name.clone(),
+                    description: lib.description.clone(),
                     path: path.to_string_lossy().to_string(),
                     template_count: lib.templates.len(),
                     last_modified,
@@ -336,6 +587,85 @@ fn list_libraries(state: tauri::State<AppState>) -> Result<Vec<LibrarySummary>,
     Ok(summaries)
 }
 
+/// Search the libraries in the library home by name and description.
+///
+/// Results are ranked best match first; an empty query returns every
+/// library in name order.
+#[tauri::command]
+fn search_libraries(
+    query: String,
+    state: tauri::State<AppState>,
+) -> Result<Vec<LibrarySummary>, String> {
+    let summaries = list_libraries(state)?;
+    Ok(rank_libraries(&query, summaries))
+}
+
+/// List group names defined in more than one loaded library, so the UI can
+/// suggest qualifying those references.
+#[tauri::command]
+fn find_ambiguous_groups(state: tauri::State<AppState>) -> Vec<AmbiguousGroupDto> {
+    let libs = state.libraries.lock().unwrap();
+    let mut libraries: Vec<&Library> = libs.values().map(|(lib, _)| lib).collect();
+    libraries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    ambiguous_group_names(libraries)
+        .into_iter()
+        .map(|(name, libraries)| AmbiguousGroupDto { name, libraries })
+        .collect()
+}
+
+/// Order library summaries by how well they match `query`, dropping those
+/// that don't match at all. Name matches count double.
+fn rank_libraries(query: &str, summaries: Vec<LibrarySummary>) -> Vec<LibrarySummary> {
+    let query = query.trim();
+    let mut ranked: Vec<(i64, LibrarySummary)> = summaries
+        .into_iter()
+        .filter_map(|summary| {
+            if query.is_empty() {
+                return Some((0, summary));
+            }
+            let name_score = fuzzy_score(query, &summary.name).map(|s| s * 2);
+            let description_score = fuzzy_score(query, &summary.description);
+            name_score
+                .max(description_score)
+                .map(|score| (score, summary))
+        })
+        .collect();
+
+    ranked.sort_by(|(a_score, a), (b_score, b)| {
+        b_score
+            .cmp(a_score)
+            .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+    });
+    ranked.into_iter().map(|(_, summary)| summary).collect()
+}
+
+/// Case-insensitive subsequence match of `query` in `text`.
+///
+/// Returns `None` if not every query character appears in order. Matches
+/// that run together or start a word score higher.
+fn fuzzy_score(query: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut pos = 0;
+    let mut prev_match: Option<usize> = None;
+
+    for q in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = (pos..text.len()).find(|&i| text[i] == q)?;
+        score += 1;
+        if prev_match.is_some_and(|prev| prev + 1 == found) {
+            score += 4;
+        }
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        prev_match = Some(found);
+        pos = found + 1;
+    }
+
+    Some(score)
+}
+
 /// Load a specific library by ID.
 #[tauri::command]
 fn load_library(id: String, state: tauri::State<AppState>) -> Result<LibraryDto, String> {
@@ -350,36 +680,81 @@ fn load_library(id: String, state: tauri::State<AppState>) -> Result<LibraryDto,
     }
 }
 
+/// Group, option and template counts for a loaded library.
+#[tauri::command]
+fn library_stats(
+    library_id: String,
+    state: tauri::State<AppState>,
+) -> Result<LibraryStatsDto, String> {
+    let libs = state.libraries.lock().unwrap();
+    let (lib, _) = libs
+        .get(&library_id)
+        .ok_or_else(|| format!("Library not found: {}", library_id))?;
+    Ok(LibraryStatsDto::from(lib))
+}
+
+/// The groups to save for the UI's `wildcards`, taking aliases and option
+/// notes from `previous`. Groups keep their place in the library and new
+/// ones follow by name, so saving doesn't reorder the file.
+fn groups_from_wildcards(
+    previous: Vec<promptgen_core::PromptGroup>,
+    mut wildcards: BTreeMap<String, Vec<String>>,
+) -> Vec<promptgen_core::PromptGroup> {
+    let mut groups = Vec::new();
+    for mut group in previous {
+        if let Some(options) = wildcards.remove(&group.name) {
+            group.options = options;
+            groups.push(group);
+        }
+    }
+    groups.extend(
+        wildcards
+            .into_iter()
+            .map(|(name, options)| promptgen_core::PromptGroup::new(name, options)),
+    );
+    groups
+}
+
 /// Save a library to disk.
 #[tauri::command]
 fn save_library(lib: LibraryDto, state: tauri::State<AppState>) -> Result<(), String> {
     let mut libs = state.libraries.lock().unwrap();
 
     if let Some((existing_lib, path)) = libs.get_mut(&lib.id) {
+        let before = existing_lib.clone();
+
         // Update the existing library
         existing_lib.name = lib.name;
 
-        // Update templates
-        existing_lib.templates.clear();
+        // Update templates, keeping metadata the UI doesn't edit
+        // (default slots, tags)
+        let mut previous: HashMap<String, PromptTemplate> = existing_lib
+            .templates
+            .drain(..)
+            .map(|t| (t.id.clone(), t))
+            .collect();
         for template_dto in lib.templates {
             let ast = parse_template(&template_dto.content).map_err(|e| e.to_string())?;
-            existing_lib.templates.push(PromptTemplate::with_id(
-                template_dto.id,
-                template_dto.name,
-                ast,
-            ));
+            let mut template = match previous.remove(&template_dto.id) {
+                Some(mut template) => {
+                    template.name = template_dto.name;
+                    template.ast = ast;
+                    template
+                }
+                None => PromptTemplate::with_id(template_dto.id, template_dto.name, ast),
+            };
+            template.description = template_dto.description;
+            existing_lib.templates.push(template);
         }
 
-        // Update groups/wildcards
-        existing_lib.groups.clear();
-        for (name, options) in lib.wildcards {
-            existing_lib
-                .groups
-                .push(promptgen_core::PromptGroup::new(name, options));
-        }
+        // Update groups/wildcards, keeping aliases and option notes the UI
+        // doesn't edit
+        let previous_groups = std::mem::take(&mut existing_lib.groups);
+        existing_lib.groups = groups_from_wildcards(previous_groups, lib.wildcards);
 
         // Save to disk
-        core_save_library(existing_lib, path).map_err(|e| e.to_string())?;
+        save_library_file(&state, existing_lib, path)?;
+        record_edit(&state, &lib.id, before);
 
         Ok(())
     } else {
@@ -387,6 +762,58 @@ fn save_library(lib: LibraryDto, state: tauri::State<AppState>) -> Result<(), St
     }
 }
 
+/// Remember the state of a library before an edit, so the edit can be undone.
+fn record_edit(state: &AppState, library_id: &str, before: Library) {
+    let mut history = state.history.lock().unwrap();
+    history
+        .entry(library_id.to_string())
+        .or_default()
+        .record(before);
+}
+
+/// Swap a library for the state `step` returns from its history, and save it.
+fn step_history(
+    state: &AppState,
+    library_id: &str,
+    step: fn(&mut EditHistory, &Library) -> Option<Library>,
+    nothing_to_do: &str,
+) -> Result<LibraryDto, String> {
+    let mut libs = state.libraries.lock().unwrap();
+    let (lib, path) = libs
+        .get_mut(library_id)
+        .ok_or_else(|| format!("Library not found: {}", library_id))?;
+
+    let mut history = state.history.lock().unwrap();
+    let restored = history
+        .get_mut(library_id)
+        .and_then(|h| step(h, lib))
+        .ok_or_else(|| nothing_to_do.to_string())?;
+    save_library_file(state, &restored, path)?;
+    *lib = restored;
+
+    let mut dto = LibraryDto::from(&*lib);
+    dto.path = path.to_string_lossy().to_string();
+    Ok(dto)
+}
+
+/// Undo the latest edit made to a library from the app, and save the result.
+#[tauri::command]
+fn undo_library_edit(
+    library_id: String,
+    state: tauri::State<AppState>,
+) -> Result<LibraryDto, String> {
+    step_history(&state, &library_id, EditHistory::undo, "Nothing to undo")
+}
+
+/// Reapply the latest undone edit to a library, and save the result.
+#[tauri::command]
+fn redo_library_edit(
+    library_id: String,
+    state: tauri::State<AppState>,
+) -> Result<LibraryDto, String> {
+    step_history(&state, &library_id, EditHistory::redo, "Nothing to redo")
+}
+
 /// Create a new library in the library home directory.
 #[tauri::command]
 fn create_library(name: String, state: tauri::State<AppState>) -> Result<LibraryDto, String> {
@@ -405,7 +832,7 @@ fn create_library(name: String, state: tauri::State<AppState>) -> Result<Library
     }
 
     // Save the library
-    core_save_library(&lib, &lib_path).map_err(|e| e.to_string())?;
+    save_library_file(&state, &lib, &lib_path)?;
 
     // Store in state
     {
@@ -436,6 +863,7 @@ fn delete_library(id: String, state: tauri::State<AppState>) -> Result<(), Strin
     let mut libs = state.libraries.lock().unwrap();
 
     if let Some((_, path)) = libs.remove(&id) {
+        state.history.lock().unwrap().remove(&id);
         fs::remove_file(&path).map_err(|e| e.to_string())?;
         Ok(())
     } else {
@@ -444,22 +872,39 @@ fn delete_library(id: String, state: tauri::State<AppState>) -> Result<(), Strin
 }
 
 /// Parse a template string and return the result.
+///
+/// On syntax errors the AST still holds every node that could be parsed, so
+/// the editor can keep highlighting while the user types.
 #[tauri::command]
 fn parse_template_cmd(text: String) -> ParseResultDto {
-    match parse_template(&text) {
-        Ok(_ast) => ParseResultDto {
-            success: true,
-            ast: None, // TODO: Serialize AST if needed
-            errors: None,
-        },
-        Err(err) => ParseResultDto {
-            success: false,
-            ast: None,
-            errors: Some(vec![parse_error_to_dto(&err)]),
+    let (ast, errors) = parse_template_recover(&text);
+    ParseResultDto {
+        success: errors.is_empty(),
+        ast: serde_json::to_value(&ast).ok(),
+        errors: if errors.is_empty() {
+            None
+        } else {
+            Some(errors.iter().map(ParseErrorDto::from).collect())
         },
     }
 }
 
+/// Parse a template and check its references against a loaded library.
+#[tauri::command]
+fn analyze_template(
+    library_id: String,
+    content: String,
+    state: tauri::State<AppState>,
+) -> Result<ParseResultDto, String> {
+    let libs = state.libraries.lock().unwrap();
+
+    let (library, _) = libs
+        .get(&library_id)
+        .ok_or_else(|| format!("Library not found: {}", library_id))?;
+
+    Ok(library.analyze_template(&content).into())
+}
+
 /// Render a template with the given bindings.
 #[tauri::command]
 fn render_template(
@@ -478,30 +923,164 @@ fn render_template(
         .find(|t| t.id == input.template_id)
         .ok_or_else(|| format!("Template not found: {}", input.template_id))?;
 
-    let mut ctx = match input.seed {
+    // Slot bindings override the template's default slots
+    let slots = template.slots_with_defaults(&input.bindings.unwrap_or_default());
+    let seed = library.resolve_seed(input.seed);
+    let on_missing_ref = if input.keep_missing_refs {
+        MissingRefPolicy::KeepLiteral
+    } else {
+        MissingRefPolicy::Error
+    };
+    let mut result = render_with_seed(
+        library,
+        template,
+        &slots,
+        &input.group_salts,
+        seed,
+        on_missing_ref,
+    );
+    if input.keep_missing_refs && result.success {
+        result.notice = missing_refs_notice(library, template);
+    }
+    Ok(result)
+}
+
+/// Explain which of a template's references name no group, if any.
+fn missing_refs_notice(library: &Library, template: &PromptTemplate) -> Option<String> {
+    let checked = library.analyze_template(&template_to_source(&template.ast));
+    if checked.errors.is_empty() {
+        return None;
+    }
+    let problems: Vec<&str> = checked.errors.iter().map(|e| e.message.as_str()).collect();
+    Some(format!("Shown as written: {}", problems.join("; ")))
+}
+
+/// Render once, with a random seed if `seed` is `None`.
+fn render_with_seed(
+    library: &Library,
+    template: &PromptTemplate,
+    slots: &HashMap<String, String>,
+    group_salts: &HashMap<String, u64>,
+    seed: Option<u64>,
+    on_missing_ref: MissingRefPolicy,
+) -> RenderResultDto {
+    let mut ctx = match seed {
         Some(seed) => EvalContext::with_seed(library, seed),
         None => EvalContext::new(library),
     };
+    ctx.set_slots(slots.clone());
+    ctx.group_salts = group_salts.clone();
+    ctx.on_missing_ref = on_missing_ref;
+
+    match render(template, &mut ctx) {
+        Ok(result) => RenderResultDto::rendered(result, seed),
+        Err(err) => RenderResultDto::failed(err.to_string(), seed),
+    }
+}
+
+/// Event sent while [`render_batch`] runs.
+const RENDER_PROGRESS: &str = "render-progress";
+
+/// How many renders [`render_batch`] completes between progress events.
+const RENDER_PROGRESS_INTERVAL: u64 = 25;
+
+/// The most variations one [`render_batch`] call renders.
+const MAX_BATCH_COUNT: u64 = 10_000;
 
-    // Add slot bindings if provided
-    if let Some(bindings) = input.bindings {
-        for (name, value) in bindings {
-            ctx.set_slot(&name, &value);
+/// Seed for render `index` of a batch. Consecutive seeds, as in the CLI's
+/// `export`, so any one variation can be reproduced on its own.
+fn batch_seed(base: u64, index: u64) -> u64 {
+    base.wrapping_add(index)
+}
+
+/// Render `count` variations with seeds from [`batch_seed`], calling
+/// `on_progress` with the number done every [`RENDER_PROGRESS_INTERVAL`]
+/// renders and once at the end.
+fn render_variations(
+    library: &Library,
+    template: &PromptTemplate,
+    slots: &HashMap<String, String>,
+    group_salts: &HashMap<String, u64>,
+    base_seed: u64,
+    count: u64,
+    mut on_progress: impl FnMut(u64),
+) -> Vec<RenderResultDto> {
+    let mut results = Vec::new();
+    for index in 0..count {
+        let seed = batch_seed(base_seed, index);
+        results.push(render_with_seed(
+            library,
+            template,
+            slots,
+            group_salts,
+            Some(seed),
+            MissingRefPolicy::Error,
+        ));
+
+        let done = index + 1;
+        if done % RENDER_PROGRESS_INTERVAL == 0 || done == count {
+            on_progress(done);
         }
     }
+    results
+}
 
-    match render(template, &mut ctx) {
-        Ok(result) => Ok(RenderResultDto {
-            success: true,
-            output: Some(result.text),
-            error: None,
-        }),
-        Err(err) => Ok(RenderResultDto {
-            success: false,
-            output: None,
-            error: Some(err.to_string()),
-        }),
+/// Render `count` variations of a template, emitting `render-progress`
+/// events to the window as they complete.
+///
+/// The batch starts from the requested seed, or the library's default,
+/// or a random one; the seed of each result is reported with it.
+///
+/// The renders run on a blocking thread with their own copy of the library,
+/// so other commands aren't held up while the batch runs.
+#[tauri::command]
+async fn render_batch(
+    input: RenderInput,
+    count: u64,
+    window: tauri::Window,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<RenderResultDto>, String> {
+    if count > MAX_BATCH_COUNT {
+        return Err(format!(
+            "At most {} variations can be rendered at once",
+            MAX_BATCH_COUNT
+        ));
     }
+
+    let (library, template) = {
+        let libs = state.libraries.lock().unwrap();
+        let (library, _) = libs
+            .get(&input.library_id)
+            .ok_or_else(|| format!("Library not found: {}", input.library_id))?;
+        let template = library
+            .templates
+            .iter()
+            .find(|t| t.id == input.template_id)
+            .ok_or_else(|| format!("Template not found: {}", input.template_id))?;
+        (library.clone(), template.clone())
+    };
+
+    let slots = template.slots_with_defaults(&input.bindings.unwrap_or_default());
+    let base_seed = library
+        .resolve_seed(input.seed)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().as_u64_pair().0);
+    let group_salts = input.group_salts;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        render_variations(
+            &library,
+            &template,
+            &slots,
+            &group_salts,
+            base_seed,
+            count,
+            |done| {
+                let _ = window.emit(RENDER_PROGRESS, RenderProgressDto { done, total: count });
+            },
+        )
+    })
+    .await
+    .map_err(|e| e.to_string())
 }
 
 /// Open a library file from disk.
@@ -542,6 +1121,7 @@ fn create_prompt_group(
     let mut libs = state.libraries.lock().unwrap();
 
     if let Some((lib, path)) = libs.get_mut(&library_id) {
+        let before = lib.clone();
         // Check if group already exists
         if lib.find_group(&name).is_some() {
             return Err(format!("A group named '{}' already exists", name));
@@ -552,7 +1132,8 @@ fn create_prompt_group(
         lib.groups.push(group);
 
         // Save to disk
-        core_save_library(lib, path).map_err(|e| e.to_string())?;
+        save_library_file(&state, lib, path)?;
+        record_edit(&state, &library_id, before);
 
         Ok(PromptGroupDto {
             name,
@@ -563,23 +1144,52 @@ fn create_prompt_group(
     }
 }
 
+/// Tidy options from the UI before they are saved.
+///
+/// Options are trimmed and repeats dropped, keeping the first. Blank
+/// options are rejected, as is an empty list when `require_options` is set.
+fn clean_options(options: Vec<String>, require_options: bool) -> Result<Vec<String>, String> {
+    let mut cleaned: Vec<String> = Vec::with_capacity(options.len());
+    for (i, option) in options.iter().enumerate() {
+        let option = option.trim();
+        if option.is_empty() {
+            return Err(format!("Option {} is empty", i + 1));
+        }
+        if !cleaned.iter().any(|o| o == option) {
+            cleaned.push(option.to_string());
+        }
+    }
+
+    if require_options && cleaned.is_empty() {
+        return Err("A group needs at least one option".to_string());
+    }
+    Ok(cleaned)
+}
+
 /// Update a prompt group's options.
+///
+/// The options are cleaned up with `clean_options`, and the cleaned list is
+/// returned.
 #[tauri::command]
 fn update_prompt_group(
     library_id: String,
     name: String,
     options: Vec<String>,
+    require_options: Option<bool>,
     state: tauri::State<AppState>,
 ) -> Result<PromptGroupDto, String> {
+    let options = clean_options(options, require_options.unwrap_or(false))?;
     let mut libs = state.libraries.lock().unwrap();
 
     if let Some((lib, path)) = libs.get_mut(&library_id) {
+        let before = lib.clone();
         // Find and update the group
         if let Some(group) = lib.groups.iter_mut().find(|g| g.name == name) {
             group.options = options.clone();
 
             // Save to disk
-            core_save_library(lib, path).map_err(|e| e.to_string())?;
+            save_library_file(&state, lib, path)?;
+            record_edit(&state, &library_id, before);
 
             Ok(PromptGroupDto { name, options })
         } else {
@@ -590,7 +1200,8 @@ fn update_prompt_group(
     }
 }
 
-/// Rename a prompt group.
+/// Rename a prompt group, updating the references to it in templates and
+/// other groups' options.
 #[tauri::command]
 fn rename_prompt_group(
     library_id: String,
@@ -601,18 +1212,22 @@ fn rename_prompt_group(
     let mut libs = state.libraries.lock().unwrap();
 
     if let Some((lib, path)) = libs.get_mut(&library_id) {
+        let before = lib.clone();
         // Check if new name already exists
         if lib.find_group(&new_name).is_some() {
             return Err(format!("A group named '{}' already exists", new_name));
         }
 
-        // Find and rename the group
-        if let Some(group) = lib.groups.iter_mut().find(|g| g.name == old_name) {
-            group.name = new_name.clone();
-            let options = group.options.clone();
+        if lib.rename_group(&old_name, &new_name).is_some() {
+            // A group that references itself has new options too
+            let options = lib
+                .find_group(&new_name)
+                .map(|g| g.options.clone())
+                .unwrap_or_default();
 
             // Save to disk
-            core_save_library(lib, path).map_err(|e| e.to_string())?;
+            save_library_file(&state, lib, path)?;
+            record_edit(&state, &library_id, before);
 
             Ok(PromptGroupDto {
                 name: new_name,
@@ -636,6 +1251,7 @@ fn delete_prompt_group(
     let mut libs = state.libraries.lock().unwrap();
 
     if let Some((lib, path)) = libs.get_mut(&library_id) {
+        let before = lib.clone();
         let initial_len = lib.groups.len();
         lib.groups.retain(|g| g.name != name);
 
@@ -644,7 +1260,8 @@ fn delete_prompt_group(
         }
 
         // Save to disk
-        core_save_library(lib, path).map_err(|e| e.to_string())?;
+        save_library_file(&state, lib, path)?;
+        record_edit(&state, &library_id, before);
 
         Ok(())
     } else {
@@ -656,61 +1273,124 @@ fn delete_prompt_group(
 // Template Commands
 // ============================================================================
 
+/// Parse template content and check its references against the library.
+///
+/// Syntax errors are returned as an error; unknown groups are returned as
+/// warnings so the template can still be saved.
+fn check_template_content(
+    lib: &Library,
+    content: &str,
+) -> Result<(Template, Vec<ParseErrorDto>), String> {
+    let result = lib.analyze_template(content);
+    match result.template {
+        Some(ast) => Ok((ast, result.errors.iter().map(ParseErrorDto::from).collect())),
+        None => Err(result
+            .errors
+            .iter()
+            .map(|e| e.message.as_str())
+            .collect::<Vec<_>>()
+            .join("; ")),
+    }
+}
+
+/// A new template with an optional description.
+fn new_template(name: &str, ast: Template, description: Option<String>) -> PromptTemplate {
+    let mut template = PromptTemplate::new(name, ast);
+    template.description = description.unwrap_or_default();
+    template
+}
+
+/// Apply an edit from the UI. Without a description the template keeps
+/// its current one.
+fn edit_template(
+    template: &mut PromptTemplate,
+    name: String,
+    ast: Template,
+    description: Option<String>,
+) {
+    template.name = name;
+    template.ast = ast;
+    if let Some(description) = description {
+        template.description = description;
+    }
+}
+
 /// Create a new template in a library.
 #[tauri::command]
 fn create_template(
     library_id: String,
     name: String,
     content: String,
+    description: Option<String>,
     state: tauri::State<AppState>,
 ) -> Result<TemplateDto, String> {
     let mut libs = state.libraries.lock().unwrap();
 
     if let Some((lib, path)) = libs.get_mut(&library_id) {
+        let before = lib.clone();
         // Parse the content
-        let ast = parse_template(&content).map_err(|e| e.to_string())?;
+        let (ast, warnings) = check_template_content(lib, &content)?;
 
         // Create new template
-        let template = PromptTemplate::new(&name, ast);
+        let template = new_template(&name, ast, description);
         let id = template.id.clone();
+        let description = template.description.clone();
         lib.templates.push(template);
 
         // Save to disk
-        core_save_library(lib, path).map_err(|e| e.to_string())?;
+        save_library_file(&state, lib, path)?;
+        record_edit(&state, &library_id, before);
 
-        Ok(TemplateDto { id, name, content })
+        Ok(TemplateDto {
+            id,
+            name,
+            content,
+            description,
+            default_slots: HashMap::new(),
+            tags: Vec::new(),
+            warnings,
+        })
     } else {
         Err(format!("Library not found: {}", library_id))
     }
 }
 
-/// Update a template's content.
+/// Update a template's name, content and, if given, description.
 #[tauri::command]
 fn update_template(
     library_id: String,
     template_id: String,
     name: String,
     content: String,
+    description: Option<String>,
     state: tauri::State<AppState>,
 ) -> Result<TemplateDto, String> {
     let mut libs = state.libraries.lock().unwrap();
 
     if let Some((lib, path)) = libs.get_mut(&library_id) {
+        let before = lib.clone();
         // Parse the content
-        let ast = parse_template(&content).map_err(|e| e.to_string())?;
+        let (ast, warnings) = check_template_content(lib, &content)?;
 
         // Find and update the template
         if let Some(template) = lib.templates.iter_mut().find(|t| t.id == template_id) {
-            template.name = name.clone();
-            template.ast = ast;
+            edit_template(template, name.clone(), ast, description);
+            let description = template.description.clone();
+            let default_slots = template.default_slots.clone();
+            let tags = template.tags.clone();
 
             // Save to disk
-            core_save_library(lib, path).map_err(|e| e.to_string())?;
+            save_library_file(&state, lib, path)?;
+            record_edit(&state, &library_id, before);
 
             Ok(TemplateDto {
                 id: template_id,
                 name,
                 content,
+                description,
+                default_slots,
+                tags,
+                warnings,
             })
         } else {
             Err(format!("Template not found: {}", template_id))
@@ -730,6 +1410,7 @@ fn delete_template(
     let mut libs = state.libraries.lock().unwrap();
 
     if let Some((lib, path)) = libs.get_mut(&library_id) {
+        let before = lib.clone();
         let initial_len = lib.templates.len();
         lib.templates.retain(|t| t.id != template_id);
 
@@ -738,7 +1419,8 @@ fn delete_template(
         }
 
         // Save to disk
-        core_save_library(lib, path).map_err(|e| e.to_string())?;
+        save_library_file(&state, lib, path)?;
+        record_edit(&state, &library_id, before);
 
         Ok(())
     } else {
@@ -757,16 +1439,28 @@ pub fn run() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
         .manage(AppState::default())
+        .setup(|_app| {
+            #[cfg(feature = "watch")]
+            watcher::start(_app.handle().clone());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             set_library_home,
             get_library_home_cmd,
             list_libraries,
+            search_libraries,
+            find_ambiguous_groups,
             load_library,
+            library_stats,
             save_library,
+            undo_library_edit,
+            redo_library_edit,
             create_library,
             delete_library,
             parse_template_cmd,
+            analyze_template,
             render_template,
+            render_batch,
             open_file,
             // Prompt group commands
             create_prompt_group,
@@ -781,3 +1475,500 @@ pub fn run() {
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_template_cmd_returns_ast_json() {
+        let result = parse_template_cmd("@Hair with {red|@Eyes} {{ Scene }} # note".to_string());
+        assert!(result.success);
+
+        let ast = result.ast.expect("AST should be serialized");
+        let nodes = ast["nodes"].as_array().unwrap();
+        let kinds: Vec<&str> = nodes.iter().map(|n| n["kind"].as_str().unwrap()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                "LibraryRef",
+                "Text",
+                "InlineOptions",
+                "Text",
+                "Slot",
+                "Text",
+                "Comment"
+            ]
+        );
+
+        assert_eq!(
+            nodes[0],
+            serde_json::json!({
+                "kind": "LibraryRef",
+                "value": { "library": null, "group": "Hair" },
+                "span": { "start": 0, "end": 5 },
+            })
+        );
+        assert_eq!(
+            nodes[2]["value"],
+            serde_json::json!([
+                { "kind": "Text", "value": "red" },
+                {
+                    "kind": "Nested",
+                    "value": [{
+                        "kind": "LibraryRef",
+                        "value": { "library": null, "group": "Eyes" },
+                        "span": { "start": 16, "end": 21 },
+                    }],
+                },
+            ])
+        );
+        assert_eq!(
+            nodes[4]["value"],
+            serde_json::json!({ "name": "Scene", "required": false })
+        );
+        assert_eq!(nodes[6]["value"], "note");
+    }
+
+    #[test]
+    fn test_parse_template_cmd_reports_error_span() {
+        let result = parse_template_cmd("@Hair {red".to_string());
+        assert!(!result.success);
+
+        let errors = result.errors.unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span.start, 10);
+
+        // The valid prefix is still returned
+        let ast = result.ast.unwrap();
+        assert_eq!(ast["nodes"][0]["value"]["group"], "Hair");
+    }
+
+    #[test]
+    fn test_analyze_unknown_ref() {
+        let mut library = Library::new("Test");
+        library
+            .groups
+            .push(promptgen_core::PromptGroup::new("Hair", vec![]));
+
+        let result = ParseResultDto::from(library.analyze_template("@Hiar, @Hair"));
+        assert!(!result.success);
+        assert!(result.ast.is_some());
+
+        let errors = result.errors.unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "unknown group 'Hiar'");
+        assert_eq!((errors[0].span.start, errors[0].span.end), (0, 5));
+        assert_eq!(errors[0].suggestion.as_deref(), Some("Hair"));
+    }
+
+    #[test]
+    fn test_render_result_dto_includes_chosen_options() {
+        let mut library = Library::new("Test");
+        library.groups.push(promptgen_core::PromptGroup::new(
+            "Hair",
+            vec!["red hair".to_string()],
+        ));
+        let template = PromptTemplate::new("Portrait", parse_template("@Hair portrait").unwrap());
+
+        let mut ctx = EvalContext::with_seed(&library, 42);
+        let result = render(&template, &mut ctx).unwrap();
+        let json = serde_json::to_value(RenderResultDto::rendered(result, Some(42))).unwrap();
+
+        assert_eq!(json["output"], "red hair portrait");
+        assert_eq!(json["seed"], 42);
+        assert_eq!(
+            json["chosenOptions"],
+            serde_json::json!([{ "group": "Hair", "library": null, "option": "red hair" }])
+        );
+    }
+
+    #[test]
+    fn test_check_template_content_warns_on_unknown_ref() {
+        let mut library = Library::new("Test");
+        library
+            .groups
+            .push(promptgen_core::PromptGroup::new("Hair", vec![]));
+
+        let (ast, warnings) = check_template_content(&library, "@Hiar portrait").unwrap();
+        assert_eq!(ast.nodes.len(), 2);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].message, "unknown group 'Hiar'");
+        assert_eq!(warnings[0].suggestion.as_deref(), Some("Hair"));
+
+        let (_, warnings) = check_template_content(&library, "@Hair portrait").unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_check_template_content_rejects_syntax_errors() {
+        let library = Library::new("Test");
+        assert!(check_template_content(&library, "{red|blue").is_err());
+    }
+
+    #[test]
+    fn test_clean_options_trims_and_dedupes() {
+        let options = vec![
+            "  red hair ".to_string(),
+            "black hair".to_string(),
+            "red hair".to_string(),
+        ];
+        assert_eq!(
+            clean_options(options, false).unwrap(),
+            vec!["red hair", "black hair"]
+        );
+    }
+
+    #[test]
+    fn test_clean_options_rejects_empty_options() {
+        let options = vec!["red hair".to_string(), "   ".to_string()];
+        assert_eq!(
+            clean_options(options, false).unwrap_err(),
+            "Option 2 is empty"
+        );
+
+        assert!(clean_options(Vec::new(), false).unwrap().is_empty());
+        assert!(clean_options(Vec::new(), true).is_err());
+    }
+
+    #[test]
+    fn test_reload_library_at_follows_file_changes() {
+        let dir = std::env::temp_dir().join(format!("promptgen-reload-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("lib.yml");
+        let state = AppState::default();
+
+        let mut library = Library::with_id("lib", "Before");
+        core_save_library(&library, &path).unwrap();
+        assert_eq!(reload_library_at(&state, &path).as_deref(), Some("lib"));
+
+        // An edited id replaces the entry loaded from the same path
+        library.id = "renamed".to_string();
+        library.name = "After".to_string();
+        core_save_library(&library, &path).unwrap();
+        assert_eq!(reload_library_at(&state, &path).as_deref(), Some("renamed"));
+        {
+            let libs = state.libraries.lock().unwrap();
+            assert_eq!(libs.len(), 1);
+            assert_eq!(libs["renamed"].0.name, "After");
+        }
+
+        // A half-written file keeps the loaded copy
+        fs::write(&path, "groups: [").unwrap();
+        assert_eq!(reload_library_at(&state, &path), None);
+        assert!(state.libraries.lock().unwrap().contains_key("renamed"));
+
+        // Other files are ignored
+        let notes = dir.join("notes.txt");
+        fs::write(&notes, "hello").unwrap();
+        assert_eq!(reload_library_at(&state, &notes), None);
+
+        // The app's own saves are skipped
+        let saved = state.libraries.lock().unwrap()["renamed"].0.clone();
+        save_library_file(&state, &saved, &path).unwrap();
+        assert_eq!(reload_library_at(&state, &path), None);
+
+        // An external change drops the undo history
+        record_edit(&state, "renamed", saved.clone());
+        let mut external = saved;
+        external.description = "edited elsewhere".to_string();
+        core_save_library(&external, &path).unwrap();
+        assert_eq!(reload_library_at(&state, &path).as_deref(), Some("renamed"));
+        assert!(state.history.lock().unwrap().is_empty());
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(reload_library_at(&state, &path).as_deref(), Some("renamed"));
+        assert!(state.libraries.lock().unwrap().is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_library_dto_includes_option_meta() {
+        let mut library = Library::with_id("lib", "Test");
+        let mut hair = promptgen_core::PromptGroup::new("Hair", vec!["auburn hair".to_string()]);
+        hair.option_meta.insert(
+            "auburn hair".to_string(),
+            promptgen_core::OptionMeta {
+                note: Some("reads as red".to_string()),
+                source: None,
+            },
+        );
+        library.groups.push(hair);
+        library.groups.push(promptgen_core::PromptGroup::new(
+            "Eyes",
+            vec!["blue eyes".to_string()],
+        ));
+
+        let json = serde_json::to_value(LibraryDto::from(&library)).unwrap();
+        assert_eq!(
+            json["optionMeta"],
+            serde_json::json!({ "Hair": { "auburn hair": { "note": "reads as red" } } })
+        );
+    }
+
+    #[test]
+    fn test_groups_from_wildcards_keeps_library_order() {
+        let mut zebra = promptgen_core::PromptGroup::new("Zebra", vec!["striped".to_string()]);
+        zebra
+            .aliases
+            .insert("stripy".to_string(), "striped".to_string());
+        let previous = vec![
+            zebra,
+            promptgen_core::PromptGroup::new("Apple", vec!["red".to_string()]),
+            promptgen_core::PromptGroup::new("Gone", vec!["old".to_string()]),
+        ];
+        let wildcards = BTreeMap::from([
+            ("Apple".to_string(), vec!["green".to_string()]),
+            ("New B".to_string(), vec!["b".to_string()]),
+            ("New A".to_string(), vec!["a".to_string()]),
+            ("Zebra".to_string(), vec!["plain".to_string()]),
+        ]);
+
+        let groups = groups_from_wildcards(previous, wildcards);
+        let names: Vec<&str> = groups.iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(names, ["Zebra", "Apple", "New A", "New B"]);
+        assert_eq!(groups[0].options, ["plain"]);
+        assert_eq!(groups[0].aliases["stripy"], "striped");
+        assert_eq!(groups[1].options, ["green"]);
+    }
+
+    #[test]
+    fn test_library_dto_json_is_stable() {
+        let mut library = Library::with_id("lib", "Test");
+        for name in ["Hair", "Eyes", "Mood", "Pose", "Light"] {
+            library.groups.push(promptgen_core::PromptGroup::new(
+                name,
+                vec![format!("{name} a")],
+            ));
+        }
+
+        let first = serde_json::to_string(&LibraryDto::from(&library)).unwrap();
+        let second = serde_json::to_string(&LibraryDto::from(&library.clone())).unwrap();
+        assert_eq!(first, second);
+        assert!(first.find("\"Eyes\"").unwrap() < first.find("\"Pose\"").unwrap());
+    }
+
+    #[test]
+    fn test_library_stats_counts() {
+        let mut library = Library::with_id("lib", "Test");
+        library
+            .groups
+            .push(promptgen_core::PromptGroup::with_options(
+                "Hair",
+                vec!["red", "black", "grey"],
+            ));
+        library
+            .groups
+            .push(promptgen_core::PromptGroup::with_options(
+                "Eyes",
+                vec!["blue"],
+            ));
+        library.templates.push(PromptTemplate::new(
+            "Portrait",
+            parse_template("@Hair").unwrap(),
+        ));
+
+        assert_eq!(
+            LibraryStatsDto::from(&library),
+            LibraryStatsDto {
+                group_count: 2,
+                option_count: 4,
+                average_options: 2.0,
+                template_count: 1,
+                unused_group_count: 1,
+            }
+        );
+        assert_eq!(
+            LibraryStatsDto::from(&Library::with_id("empty", "Empty")).average_options,
+            0.0
+        );
+    }
+
+    fn summary(name: &str, description: &str) -> LibrarySummary {
+        LibrarySummary {
+            id: name.to_lowercase(),
+            name: name.to_string(),
+            description: description.to_string(),
+            path: format!("/libs/{}.yml", name.to_lowercase()),
+            template_count: 0,
+            last_modified: String::new(),
+        }
+    }
+
+    fn ranked_names(query: &str) -> Vec<String> {
+        let summaries = vec![
+            summary("Portraits", "People and faces"),
+            summary("Landscapes", "Mountains, forests and a few portraits"),
+            summary("Animals", "Pets and wildlife"),
+        ];
+        rank_libraries(query, summaries)
+            .into_iter()
+            .map(|s| s.name)
+            .collect()
+    }
+
+    #[test]
+    fn test_rank_libraries_empty_query_sorts_by_name() {
+        assert_eq!(
+            ranked_names("  "),
+            vec!["Animals", "Landscapes", "Portraits"]
+        );
+    }
+
+    #[test]
+    fn test_rank_libraries_prefers_name_matches() {
+        assert_eq!(ranked_names("portrait"), vec!["Portraits", "Landscapes"]);
+    }
+
+    #[test]
+    fn test_rank_libraries_fuzzy_matches_and_drops_misses() {
+        assert_eq!(ranked_names("wldlf"), vec!["Animals"]);
+        assert!(ranked_names("zebra").is_empty());
+    }
+
+    #[test]
+    fn test_template_dto_content_matches_saved_source() {
+        let source = r#"# note
+@Hair ?? "bald" with @{"Eye Color" | upper}, {red|blue} {{ Scene! }}"#;
+        let mut library = Library::with_id("lib", "Test");
+        library.templates.push(PromptTemplate::new(
+            "Portrait",
+            parse_template(source).unwrap(),
+        ));
+
+        let content = TemplateDto::from(&library.templates[0]).content;
+        let saved = promptgen_core::serialize_pack(&library).unwrap();
+        let reloaded = promptgen_core::parse_pack(&saved).unwrap();
+
+        assert_eq!(content, source);
+        assert_eq!(content, template_to_source(&reloaded.templates[0].ast));
+    }
+
+    #[test]
+    fn test_batch_seeds_are_consecutive() {
+        assert_eq!(batch_seed(40, 0), 40);
+        assert_eq!(batch_seed(40, 2), 42);
+        assert_eq!(batch_seed(u64::MAX, 1), 0);
+    }
+
+    #[test]
+    fn test_render_variations_length_and_progress() {
+        let mut library = Library::with_id("lib", "Test");
+        library
+            .groups
+            .push(promptgen_core::PromptGroup::with_options(
+                "Hair",
+                vec!["red hair", "black hair", "blonde hair"],
+            ));
+        let template = PromptTemplate::new("Portrait", parse_template("@Hair").unwrap());
+        let no_slots = HashMap::new();
+        let no_salts = HashMap::new();
+
+        let mut progress = Vec::new();
+        let results = render_variations(&library, &template, &no_slots, &no_salts, 7, 60, |done| {
+            progress.push(done)
+        });
+
+        assert_eq!(results.len(), 60);
+        assert_eq!(progress, vec![25, 50, 60]);
+        let seeds: Vec<Option<u64>> = results.iter().map(|r| r.seed).collect();
+        assert_eq!(seeds, (7..67).map(Some).collect::<Vec<_>>());
+
+        // Each item matches a single render with its seed
+        let single = render_with_seed(
+            &library,
+            &template,
+            &no_slots,
+            &no_salts,
+            Some(9),
+            MissingRefPolicy::Error,
+        );
+        assert_eq!(results[2].output, single.output);
+    }
+
+    #[test]
+    fn test_keep_missing_refs_renders_with_notice() {
+        let mut library = Library::with_id("lib", "Test");
+        library
+            .groups
+            .push(promptgen_core::PromptGroup::with_options(
+                "Hair",
+                vec!["red hair"],
+            ));
+        let template =
+            PromptTemplate::new("Portrait", parse_template("@Hair and @Missing").unwrap());
+        let no_slots = HashMap::new();
+        let no_salts = HashMap::new();
+
+        let failed = render_with_seed(
+            &library,
+            &template,
+            &no_slots,
+            &no_salts,
+            Some(1),
+            MissingRefPolicy::Error,
+        );
+        assert!(!failed.success);
+
+        let kept = render_with_seed(
+            &library,
+            &template,
+            &no_slots,
+            &no_salts,
+            Some(1),
+            MissingRefPolicy::KeepLiteral,
+        );
+        assert!(kept.success);
+        assert_eq!(kept.output.as_deref(), Some("red hair and @Missing"));
+
+        let notice = missing_refs_notice(&library, &template).unwrap();
+        assert!(notice.starts_with("Shown as written: "));
+        assert!(notice.contains("Missing"));
+    }
+
+    #[test]
+    fn test_create_template_with_description() {
+        let ast = parse_template("@Hair").unwrap();
+        let mut library = Library::with_id("lib", "Test");
+        library.templates.push(new_template(
+            "Portrait",
+            ast,
+            Some("Head and shoulders".to_string()),
+        ));
+
+        let dto = TemplateDto::from(&library.templates[0]);
+        assert_eq!(dto.description, "Head and shoulders");
+
+        let saved = promptgen_core::serialize_pack(&library).unwrap();
+        let reloaded = promptgen_core::parse_pack(&saved).unwrap();
+        assert_eq!(reloaded.templates[0].description, "Head and shoulders");
+
+        // Left out, the description stays empty
+        let plain = new_template("Plain", parse_template("@Hair").unwrap(), None);
+        assert_eq!(plain.description, "");
+    }
+
+    #[test]
+    fn test_update_template_preserves_description() {
+        let ast = parse_template("@Hair").unwrap();
+        let mut template = new_template("Portrait", ast, Some("Head and shoulders".to_string()));
+
+        edit_template(
+            &mut template,
+            "Close-up".to_string(),
+            parse_template("@Eyes").unwrap(),
+            None,
+        );
+        assert_eq!(template.name, "Close-up");
+        assert_eq!(template.description, "Head and shoulders");
+
+        edit_template(
+            &mut template,
+            "Close-up".to_string(),
+            parse_template("@Eyes").unwrap(),
+            Some(String::new()),
+        );
+        assert_eq!(template.description, "");
+    }
+}
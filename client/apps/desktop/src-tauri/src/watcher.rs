@@ -0,0 +1,70 @@
+//! Reloads libraries when their files are changed outside the app.
+
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use notify_debouncer_mini::notify::RecursiveMode;
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::{reload_library_at, AppState};
+
+/// Event sent to the frontend with the ids of the libraries that changed.
+pub const LIBRARIES_CHANGED: &str = "libraries-changed";
+
+/// How long to wait for a burst of writes to settle before reloading.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Start watching the library home.
+///
+/// The watched directory follows the library home as it is set; see
+/// `watch_library_home`.
+pub fn start(app: AppHandle) {
+    let handle = app.clone();
+    let debouncer = new_debouncer(DEBOUNCE, move |result: DebounceEventResult| {
+        let Ok(events) = result else {
+            return;
+        };
+        let state = handle.state::<AppState>();
+        let mut ids: Vec<String> = events
+            .iter()
+            .filter_map(|event| reload_library_at(&state, &event.path))
+            .collect();
+        ids.sort();
+        ids.dedup();
+        if !ids.is_empty() {
+            let _ = handle.emit(LIBRARIES_CHANGED, ids);
+        }
+    });
+    let mut debouncer = match debouncer {
+        Ok(debouncer) => debouncer,
+        Err(e) => {
+            eprintln!("Failed to start library watcher: {}", e);
+            return;
+        }
+    };
+
+    let (tx, rx) = mpsc::channel::<PathBuf>();
+    let state = app.state::<AppState>();
+    if let Some(home) = state.library_home.lock().unwrap().clone() {
+        let _ = tx.send(home);
+    }
+    *state.home_watch.lock().unwrap() = Some(tx);
+
+    std::thread::spawn(move || {
+        let mut watched: Option<PathBuf> = None;
+        for home in rx {
+            if let Some(old) = watched.take() {
+                let _ = debouncer.watcher().unwatch(&old);
+            }
+            match debouncer
+                .watcher()
+                .watch(&home, RecursiveMode::NonRecursive)
+            {
+                Ok(()) => watched = Some(home),
+                Err(e) => eprintln!("Failed to watch {}: {}", home.display(), e),
+            }
+        }
+    });
+}
@@ -1,15 +1,19 @@
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import { open } from "@tauri-apps/plugin-dialog";
 import {
   BackendProvider,
   type PromptgenBackend,
   type Library,
   type LibrarySummary,
+  type AmbiguousGroup,
+  type LibraryStats,
   type Template,
   type PromptGroup,
   type ParseResult,
   type RenderInput,
   type RenderResult,
+  type RenderProgress,
 } from "@promptgen/backend";
 import type { ReactNode } from "react";
 
@@ -34,20 +38,49 @@ const desktopBackend: PromptgenBackend = {
   // Library operations
   listLibraries: () => invoke<LibrarySummary[]>("list_libraries"),
 
+  searchLibraries: (query) =>
+    invoke<LibrarySummary[]>("search_libraries", { query }),
+
+  findAmbiguousGroups: () =>
+    invoke<AmbiguousGroup[]>("find_ambiguous_groups"),
+
   loadLibrary: (id) => invoke<Library>("load_library", { id }),
 
+  getLibraryStats: (libraryId) =>
+    invoke<LibraryStats>("library_stats", { libraryId }),
+
   saveLibrary: (lib) => invoke<void>("save_library", { lib }),
 
   createLibrary: (name) => invoke<Library>("create_library", { name }),
 
   deleteLibrary: (id) => invoke<void>("delete_library", { id }),
 
+  undoLibraryEdit: (libraryId) =>
+    invoke<Library>("undo_library_edit", { libraryId }),
+
+  redoLibraryEdit: (libraryId) =>
+    invoke<Library>("redo_library_edit", { libraryId }),
+
+  onLibrariesChanged: (callback) => {
+    const unlisten = listen<string[]>("libraries-changed", (event) =>
+      callback(event.payload)
+    );
+    return () => {
+      unlisten.then((stop) => stop());
+    };
+  },
+
   // Prompt group operations
   createPromptGroup: (libraryId, name) =>
     invoke<PromptGroup>("create_prompt_group", { libraryId, name }),
 
-  updatePromptGroup: (libraryId, name, options) =>
-    invoke<PromptGroup>("update_prompt_group", { libraryId, name, options }),
+  updatePromptGroup: (libraryId, name, options, requireOptions) =>
+    invoke<PromptGroup>("update_prompt_group", {
+      libraryId,
+      name,
+      options,
+      requireOptions,
+    }),
 
   renamePromptGroup: (libraryId, oldName, newName) =>
     invoke<PromptGroup>("rename_prompt_group", { libraryId, oldName, newName }),
@@ -56,11 +89,17 @@ const desktopBackend: PromptgenBackend = {
     invoke<void>("delete_prompt_group", { libraryId, name }),
 
   // Template CRUD operations
-  createTemplate: (libraryId, name, content) =>
-    invoke<Template>("create_template", { libraryId, name, content }),
-
-  updateTemplate: (libraryId, templateId, name, content) =>
-    invoke<Template>("update_template", { libraryId, templateId, name, content }),
+  createTemplate: (libraryId, name, content, description) =>
+    invoke<Template>("create_template", { libraryId, name, content, description }),
+
+  updateTemplate: (libraryId, templateId, name, content, description) =>
+    invoke<Template>("update_template", {
+      libraryId,
+      templateId,
+      name,
+      content,
+      description,
+    }),
 
   deleteTemplate: (libraryId, templateId) =>
     invoke<void>("delete_template", { libraryId, templateId }),
@@ -68,9 +107,25 @@ const desktopBackend: PromptgenBackend = {
   // Template parsing/rendering
   parseTemplate: (text) => invoke<ParseResult>("parse_template_cmd", { text }),
 
+  analyzeTemplate: (libraryId, content) =>
+    invoke<ParseResult>("analyze_template", { libraryId, content }),
+
   renderTemplate: (input: RenderInput) =>
     invoke<RenderResult>("render_template", { input }),
 
+  renderBatch: async (input, count, onProgress) => {
+    const unlisten = onProgress
+      ? await listen<RenderProgress>("render-progress", (event) =>
+          onProgress(event.payload)
+        )
+      : undefined;
+    try {
+      return await invoke<RenderResult[]>("render_batch", { input, count });
+    } finally {
+      unlisten?.();
+    }
+  },
+
   // Desktop-specific file operations
   openFile: (path) => invoke<Library>("open_file", { path }),
 };
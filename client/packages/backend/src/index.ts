@@ -1,16 +1,25 @@
 // Types
 export type {
   LibrarySummary,
+  AmbiguousGroup,
   Library,
+  LibraryStats,
   Template,
   PromptGroup,
+  OptionMeta,
   BindingValue,
   ParseResult,
+  TemplateAst,
   AstNode,
+  LibraryRefAst,
+  SlotAst,
+  OptionItemAst,
   Span,
   ParseError,
   RenderInput,
   RenderResult,
+  RenderProgress,
+  ChosenOption,
   AuthState,
   User,
   FeatureFlags,
@@ -1,11 +1,14 @@
 import type {
   LibrarySummary,
+  AmbiguousGroup,
   Library,
+  LibraryStats,
   Template,
   PromptGroup,
   ParseResult,
   RenderInput,
   RenderResult,
+  RenderProgress,
   AuthState,
   LoginInput,
   FeatureFlags,
@@ -23,25 +26,53 @@ export interface PromptgenBackend {
 
   // Library operations
   listLibraries(): Promise<LibrarySummary[]>;
+  searchLibraries?(query: string): Promise<LibrarySummary[]>;
+  findAmbiguousGroups?(): Promise<AmbiguousGroup[]>;
   loadLibrary(id: string): Promise<Library>;
+  getLibraryStats?(libraryId: string): Promise<LibraryStats>;
   saveLibrary(lib: Library): Promise<void>;
   createLibrary(name: string): Promise<Library>;
   deleteLibrary(id: string): Promise<void>;
+  /** Undo the latest edit made from the app; resolves to the restored library. */
+  undoLibraryEdit?(libraryId: string): Promise<Library>;
+  redoLibraryEdit?(libraryId: string): Promise<Library>;
+  /** Subscribe to libraries changed on disk; returns an unsubscribe function. */
+  onLibrariesChanged?(callback: (ids: string[]) => void): () => void;
 
   // Prompt group operations
   createPromptGroup?(libraryId: string, name: string): Promise<PromptGroup>;
-  updatePromptGroup?(libraryId: string, name: string, options: string[]): Promise<PromptGroup>;
+  /** Options are trimmed and deduped; blank options are rejected. */
+  updatePromptGroup?(
+    libraryId: string,
+    name: string,
+    options: string[],
+    requireOptions?: boolean
+  ): Promise<PromptGroup>;
   renamePromptGroup?(libraryId: string, oldName: string, newName: string): Promise<PromptGroup>;
   deletePromptGroup?(libraryId: string, name: string): Promise<void>;
 
   // Template CRUD operations
-  createTemplate?(libraryId: string, name: string, content: string): Promise<Template>;
-  updateTemplate?(libraryId: string, templateId: string, name: string, content: string): Promise<Template>;
+  createTemplate?(libraryId: string, name: string, content: string, description?: string): Promise<Template>;
+  /** Leaving out `description` keeps the template's current one. */
+  updateTemplate?(
+    libraryId: string,
+    templateId: string,
+    name: string,
+    content: string,
+    description?: string
+  ): Promise<Template>;
   deleteTemplate?(libraryId: string, templateId: string): Promise<void>;
 
   // Template parsing/rendering
   parseTemplate(text: string): Promise<ParseResult>;
+  analyzeTemplate?(libraryId: string, text: string): Promise<ParseResult>;
   renderTemplate(input: RenderInput): Promise<RenderResult>;
+  /** Render `count` variations from consecutive seeds, reporting progress as they complete. */
+  renderBatch?(
+    input: RenderInput,
+    count: number,
+    onProgress?: (progress: RenderProgress) => void
+  ): Promise<RenderResult[]>;
 
   // File operations (desktop only)
   openFile?(path: string): Promise<Library>;
@@ -2,24 +2,54 @@
 export interface LibrarySummary {
   id: string;
   name: string;
+  description: string;
   path: string;
   templateCount: number;
   lastModified: string;
 }
 
+/** A group name defined in more than one loaded library. */
+export interface AmbiguousGroup {
+  name: string;
+  libraries: string[];
+}
+
+/** Counts shown in a library's info panel. */
+export interface LibraryStats {
+  groupCount: number;
+  optionCount: number;
+  averageOptions: number;
+  templateCount: number;
+  unusedGroupCount: number;
+}
+
 export interface Library {
   id: string;
   name: string;
   path: string;
   templates: Template[];
   wildcards: Record<string, string[]>;
+  defaultSeed?: number | null;
+  /** Option notes by group name, then option text. */
+  optionMeta?: Record<string, Record<string, OptionMeta>>;
+}
+
+/** Author annotations on a group option; never rendered. */
+export interface OptionMeta {
+  note?: string;
+  source?: string;
 }
 
 export interface Template {
   id: string;
   name: string;
   content: string;
+  description?: string;
   bindings?: Record<string, BindingValue>;
+  defaultSlots?: Record<string, string>;
+  tags?: string[];
+  /** Unknown group references found when the template was last saved. */
+  warnings?: ParseError[];
 }
 
 export interface PromptGroup {
@@ -35,17 +65,46 @@ export type BindingValue =
 // Parse types
 export interface ParseResult {
   success: boolean;
-  ast?: AstNode;
+  ast?: TemplateAst;
   errors?: ParseError[];
 }
 
-export interface AstNode {
-  kind: string;
-  span: Span;
-  children?: AstNode[];
-  value?: string;
+export interface TemplateAst {
+  nodes: AstNode[];
+}
+
+export type AstNode = (
+  | { kind: "Text"; value: string }
+  | { kind: "Comment"; value: string }
+  | { kind: "Slot"; value: SlotAst }
+  | { kind: "SlotRef"; value: string }
+  | { kind: "LibraryRef"; value: LibraryRefAst }
+  | { kind: "InlineOptions"; value: OptionItemAst[] }
+  | { kind: "MaybeOption"; value: MaybeOptionAst }
+) & { span: Span };
+
+export interface MaybeOptionAst {
+  text: string;
+  /** Percent chance, 0 to 100, that the text is rendered. */
+  chance: number;
+}
+
+export interface SlotAst {
+  name: string;
+  required: boolean;
+}
+
+export interface LibraryRefAst {
+  library: string | null;
+  group: string;
+  transform?: "upper" | "lower" | "title";
+  fallback?: string;
 }
 
+export type OptionItemAst =
+  | { kind: "Text"; value: string }
+  | { kind: "Nested"; value: AstNode[] };
+
 export interface Span {
   start: number;
   end: number;
@@ -54,6 +113,7 @@ export interface Span {
 export interface ParseError {
   message: string;
   span: Span;
+  suggestion?: string | null;
 }
 
 // Render types
@@ -62,12 +122,34 @@ export interface RenderInput {
   libraryId: string;
   bindings?: Record<string, string>;
   seed?: number;
+  groupSalts?: Record<string, number>;
+  /** Render references to missing variables as written instead of failing */
+  keepMissingRefs?: boolean;
 }
 
 export interface RenderResult {
   success: boolean;
   output?: string;
   error?: string;
+  seed?: number | null;
+  chosenOptions?: ChosenOption[];
+  /** A problem that didn't stop the render */
+  notice?: string;
+}
+
+/** Payload of the `render-progress` event sent during a batch render. */
+export interface RenderProgress {
+  done: number;
+  total: number;
+}
+
+export interface ChosenOption {
+  group: string;
+  library: string | null;
+  option: string;
+  alias?: string;
+  /** Index of the choice whose option held this reference */
+  parent?: number;
 }
 
 // Auth types (for future cloud features)
@@ -1,8 +1,20 @@
+import { useEffect } from "react";
+import { Moon, Sun } from "lucide-react";
 import { WorkspaceSidebar } from "./components/WorkspaceSidebar";
 import { TemplateEditor } from "./components/TemplateEditor";
 import { PromptPreview } from "./components/PromptPreview";
+import { Button } from "./components/ui/button";
+import { useUIStore } from "./stores/useUIStore";
 
 export function App() {
+  const { theme, setTheme } = useUIStore();
+
+  // Colors come from the CSS variables in index.css; the `dark` class
+  // swaps in the dark set.
+  useEffect(() => {
+    document.documentElement.classList.toggle("dark", theme === "dark");
+  }, [theme]);
+
   return (
     <div className="flex h-screen bg-background text-foreground">
       {/* Sidebar */}
@@ -11,8 +23,17 @@ export function App() {
       {/* Main content area */}
       <div className="flex flex-1 flex-col">
         {/* Header */}
-        <header className="flex h-12 items-center border-b px-4">
+        <header className="flex h-12 items-center justify-between border-b px-4">
           <h1 className="text-lg font-semibold">PromptGen</h1>
+          <Button
+            variant="ghost"
+            size="icon"
+            className="h-7 w-7"
+            onClick={() => setTheme(theme === "dark" ? "light" : "dark")}
+            title={theme === "dark" ? "Switch to light theme" : "Switch to dark theme"}
+          >
+            {theme === "dark" ? <Sun className="h-4 w-4" /> : <Moon className="h-4 w-4" />}
+          </Button>
         </header>
 
         {/* Editor and Preview */}
@@ -1,30 +1,104 @@
-import { RefreshCw, Copy, Check } from "lucide-react";
-import { useState } from "react";
+import {
+  RefreshCw,
+  Copy,
+  Check,
+  Braces,
+  Pin,
+  PinOff,
+  Trash2,
+  Dices,
+} from "lucide-react";
+import { useEffect, useRef, useState } from "react";
 import { Button } from "./ui/button";
 import { ScrollArea } from "./ui/scroll-area";
 import { useTemplateEditor } from "../hooks/useTemplateEditor";
+import { useLibraryStore } from "../stores/useLibraryStore";
 import { cn } from "../lib/utils";
 
 export function PromptPreview() {
-  const { renderedOutput, isRendering, render } = useTemplateEditor();
-  const [copied, setCopied] = useState(false);
+  const {
+    renderedOutput,
+    lastRenderResult,
+    previewSeed,
+    seedPinned,
+    isRendering,
+    render,
+    rerollGroup,
+    pinSeed,
+    unpinSeed,
+    renderHistory,
+    clearHistory,
+  } = useTemplateEditor();
+  const { activeLibrary } = useLibraryStore();
+  const [copied, setCopied] = useState<"text" | "json" | null>(null);
+
+  // With the seed pinned, re-render when the library changes (e.g. a
+  // variable's options were edited) so the effect of the edit is isolated.
+  const renderedLibraryRef = useRef(activeLibrary);
+  useEffect(() => {
+    if (renderedLibraryRef.current === activeLibrary) return;
+    renderedLibraryRef.current = activeLibrary;
+    if (seedPinned && lastRenderResult) {
+      render();
+    }
+  }, [activeLibrary, seedPinned, lastRenderResult, render]);
 
   const handleCopy = async () => {
     if (!renderedOutput) return;
     await navigator.clipboard.writeText(renderedOutput);
-    setCopied(true);
-    setTimeout(() => setCopied(false), 2000);
+    setCopied("text");
+    setTimeout(() => setCopied(null), 2000);
+  };
+
+  const handleCopyJson = async () => {
+    if (!lastRenderResult) return;
+    const json = {
+      text: lastRenderResult.output,
+      seed: lastRenderResult.seed ?? null,
+      chosenOptions: lastRenderResult.chosenOptions ?? [],
+    };
+    await navigator.clipboard.writeText(JSON.stringify(json, null, 2));
+    setCopied("json");
+    setTimeout(() => setCopied(null), 2000);
   };
 
   const handleReroll = () => {
-    render(Math.floor(Math.random() * 1000000));
+    render();
+  };
+
+  const choices = lastRenderResult?.chosenOptions ?? [];
+
+  const handleTogglePin = () => {
+    if (seedPinned) {
+      unpinSeed();
+    } else {
+      pinSeed();
+    }
   };
 
   return (
     <div className="flex h-full flex-col border-l">
       <div className="flex items-center justify-between border-b px-4 py-2">
         <h3 className="text-sm font-medium">Preview</h3>
-        <div className="flex gap-1">
+        <div className="flex items-center gap-1">
+          {previewSeed !== null && (
+            <span className="mr-1 text-xs text-muted-foreground">
+              seed {previewSeed}
+            </span>
+          )}
+          <Button
+            variant="ghost"
+            size="icon"
+            className="h-7 w-7"
+            onClick={handleTogglePin}
+            title={seedPinned ? "Unpin seed" : "Pin seed"}
+          >
+            {seedPinned ? (
+              <Pin className="h-4 w-4 text-primary" />
+            ) : (
+              <PinOff className="h-4 w-4" />
+            )}
+          </Button>
           <Button
             variant="ghost"
             size="icon"
@@ -42,25 +116,114 @@ export function PromptPreview() {
             className="h-7 w-7"
             onClick={handleCopy}
             disabled={!renderedOutput}
+            title="Copy"
           >
-            {copied ? (
-              <Check className="h-4 w-4 text-green-500" />
+            {copied === "text" ? (
+              <Check className="h-4 w-4 text-success" />
             ) : (
               <Copy className="h-4 w-4" />
             )}
           </Button>
+          <Button
+            variant="ghost"
+            size="icon"
+            className="h-7 w-7"
+            onClick={handleCopyJson}
+            disabled={!lastRenderResult}
+            title="Copy as JSON"
+          >
+            {copied === "json" ? (
+              <Check className="h-4 w-4 text-success" />
+            ) : (
+              <Braces className="h-4 w-4" />
+            )}
+          </Button>
         </div>
       </div>
       <ScrollArea className="flex-1">
         <div className="p-4">
           {renderedOutput ? (
-            <p className="whitespace-pre-wrap text-sm">{renderedOutput}</p>
+            <>
+              <p className="whitespace-pre-wrap text-sm">{renderedOutput}</p>
+              {lastRenderResult?.notice && (
+                <p className="mt-2 text-xs text-muted-foreground">
+                  {lastRenderResult.notice}
+                </p>
+              )}
+            </>
           ) : (
             <p className="text-sm text-muted-foreground">
               Select a template and click render to see output
             </p>
           )}
         </div>
+        {renderedOutput && choices.length > 0 && (
+          <div className="border-t p-4">
+            <h4 className="mb-2 text-xs font-medium text-muted-foreground">
+              Picks
+            </h4>
+            <ul className="space-y-1">
+              {choices.map((choice, index) => (
+                <li
+                  key={`${choice.group}-${index}`}
+                  className="flex items-center gap-2 text-xs"
+                >
+                  <span className="text-muted-foreground">@{choice.group}</span>
+                  <span className="line-clamp-1 flex-1">{choice.option}</span>
+                  <Button
+                    variant="ghost"
+                    size="icon"
+                    className="h-6 w-6"
+                    onClick={() => rerollGroup(choice.group)}
+                    disabled={isRendering}
+                    title={`Re-roll @${choice.group} only`}
+                  >
+                    <Dices className="h-3 w-3" />
+                  </Button>
+                </li>
+              ))}
+            </ul>
+          </div>
+        )}
+        {renderHistory.length > 0 && (
+          <div className="border-t p-4">
+            <div className="mb-2 flex items-center justify-between">
+              <h4 className="text-xs font-medium text-muted-foreground">
+                History
+              </h4>
+              <Button
+                variant="ghost"
+                size="icon"
+                className="h-6 w-6"
+                onClick={clearHistory}
+                title="Clear history"
+              >
+                <Trash2 className="h-3 w-3" />
+              </Button>
+            </div>
+            <ul className="space-y-1">
+              {renderHistory.map((entry, index) => (
+                <li key={`${entry.seed}-${index}`}>
+                  <button
+                    type="button"
+                    className={cn(
+                      "w-full rounded px-2 py-1 text-left text-xs hover:bg-accent",
+                      entry.seed === previewSeed && "bg-accent"
+                    )}
+                    onClick={() => render(entry.seed)}
+                    disabled={isRendering}
+                    title={`Recall seed ${entry.seed}`}
+                  >
+                    <span className="mr-2 text-muted-foreground">
+                      {entry.seed}
+                    </span>
+                    <span className="line-clamp-1">{entry.text}</span>
+                  </button>
+                </li>
+              ))}
+            </ul>
+          </div>
+        )}
       </ScrollArea>
     </div>
   );
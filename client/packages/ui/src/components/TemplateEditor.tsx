@@ -34,6 +34,7 @@ export function TemplateEditor() {
             {parseResult.errors.map((err, i) => (
               <p key={i} className="text-xs text-destructive">
                 Line {err.span.start}: {err.message}
+                {err.suggestion && ` (did you mean '${err.suggestion}'?)`}
               </p>
             ))}
           </div>
@@ -48,8 +48,11 @@ export function WorkspaceSidebar() {
     setLibraryHome,
     loadLibraries,
     loadLibrary,
+    watchLibraries,
     createLibrary,
     deleteLibrary,
+    undoEdit,
+    redoEdit,
     selectTemplate,
     createPromptGroup,
     updatePromptGroup,
@@ -81,6 +84,7 @@ export function WorkspaceSidebar() {
   const [editingTemplate, setEditingTemplate] = useState<{
     id: string;
     name: string;
+    description: string;
   } | null>(null);
   const [deleteTemplateDialogOpen, setDeleteTemplateDialogOpen] =
     useState(false);
@@ -109,6 +113,36 @@ export function WorkspaceSidebar() {
     }
   }, [libraryHome, loadLibraries]);
 
+  // Pick up library files edited outside the app
+  useEffect(() => watchLibraries(), [watchLibraries]);
+
+  // Ctrl+Z / Ctrl+Y (or Ctrl+Shift+Z) undo and redo library edits, except
+  // while typing, where they belong to the text field
+  useEffect(() => {
+    const handleKeyDown = (e: KeyboardEvent) => {
+      if (!(e.ctrlKey || e.metaKey)) return;
+      const target = e.target as HTMLElement | null;
+      if (
+        target?.isContentEditable ||
+        target?.tagName === "INPUT" ||
+        target?.tagName === "TEXTAREA"
+      ) {
+        return;
+      }
+      const key = e.key.toLowerCase();
+      if (key === "z" && !e.shiftKey) {
+        e.preventDefault();
+        undoEdit();
+      } else if (key === "y" || (key === "z" && e.shiftKey)) {
+        e.preventDefault();
+        redoEdit();
+      }
+    };
+
+    window.addEventListener("keydown", handleKeyDown);
+    return () => window.removeEventListener("keydown", handleKeyDown);
+  }, [undoEdit, redoEdit]);
+
   // Auto-load persisted library selection
   useEffect(() => {
     if (libraries.length > 0 && selectedLibraryId && !activeLibrary) {
@@ -179,14 +213,19 @@ export function WorkspaceSidebar() {
     e: React.MouseEvent
   ) => {
     e.stopPropagation();
-    setEditingTemplate({ id, name });
+    const template = activeLibrary?.templates.find((t) => t.id === id);
+    setEditingTemplate({ id, name, description: template?.description ?? "" });
     setEditTemplateDialogOpen(true);
   };
 
-  const handleSaveTemplate = async (id: string, name: string) => {
+  const handleSaveTemplate = async (
+    id: string,
+    name: string,
+    description: string
+  ) => {
     const template = activeLibrary?.templates.find((t) => t.id === id);
     if (template) {
-      await updateTemplate(id, name, template.content);
+      await updateTemplate(id, name, template.content, description);
     }
   };
 
@@ -298,6 +337,7 @@ export function WorkspaceSidebar() {
         {sidebarViewMode === "variables" && (
           <VariableList
             variables={activeLibrary.wildcards}
+            optionMeta={activeLibrary.optionMeta}
             onEditVariable={handleEditVariable}
             onCreateVariable={() => setCreateVariableDialogOpen(true)}
             searchQuery={searchQuery}
@@ -6,6 +6,7 @@ import {
   Pencil,
   Plus,
 } from "lucide-react";
+import type { OptionMeta } from "@promptgen/backend";
 import { Button } from "../ui/button";
 import {
   parseVariableQuery,
@@ -15,13 +16,23 @@ import {
 
 interface VariableListProps {
   variables: Record<string, string[]>;
+  optionMeta?: Record<string, Record<string, OptionMeta>>;
   onEditVariable: (name: string, options: string[], e: React.MouseEvent) => void;
   onCreateVariable: () => void;
   searchQuery?: string;
 }
 
+/** Most matching options listed per variable; the rest are counted. */
+const MAX_MATCHES_SHOWN = 50;
+
+/** Hover text for an option: the option itself plus any notes. */
+function optionTitle(option: string, meta?: OptionMeta): string {
+  return [option, meta?.note, meta?.source].filter(Boolean).join("\n");
+}
+
 export function VariableList({
   variables,
+  optionMeta = {},
   onEditVariable,
   onCreateVariable,
   searchQuery = "",
@@ -68,7 +79,8 @@ export function VariableList({
   return (
     <>
       {filteredVariables.map((variable) => {
-        const { name, options, matchingOptionIndices, showAllOptions } = variable;
+        const { name, options, matchingOptionIndices, optionMatches, showAllOptions } =
+          variable;
         const isExpanded = !collapsedVariables.has(name);
         const hasMatchingOptions = matchingOptionIndices.size > 0;
 
@@ -118,7 +130,7 @@ export function VariableList({
                       <div
                         key={idx}
                         className="px-2 py-0.5 text-xs text-muted-foreground truncate"
-                        title={option}
+                        title={optionTitle(option, optionMeta[name]?.[option])}
                       >
                         {option}
                       </div>
@@ -130,20 +142,25 @@ export function VariableList({
                     )}
                   </>
                 ) : (
-                  // Show only matching options, highlighted
+                  // Show only matching options, best match first, highlighted
                   <>
-                    {options.map((option, idx) => {
-                      if (!matchingOptionIndices.has(idx)) return null;
+                    {optionMatches.slice(0, MAX_MATCHES_SHOWN).map(({ index }) => {
+                      const option = options[index];
                       return (
                         <div
-                          key={idx}
+                          key={index}
                           className="px-2 py-0.5 text-xs text-primary font-medium truncate"
-                          title={option}
+                          title={optionTitle(option, optionMeta[name]?.[option])}
                         >
                           {option}
                         </div>
                       );
                     })}
+                    {optionMatches.length > MAX_MATCHES_SHOWN && (
+                      <p className="px-2 py-0.5 text-xs text-muted-foreground italic">
+                        +{optionMatches.length - MAX_MATCHES_SHOWN} more matches...
+                      </p>
+                    )}
                   </>
                 )}
               </div>
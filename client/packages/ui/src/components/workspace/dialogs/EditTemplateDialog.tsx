@@ -14,8 +14,8 @@ import {
 interface EditTemplateDialogProps {
   open: boolean;
   onOpenChange: (open: boolean) => void;
-  template: { id: string; name: string } | null;
-  onSaveTemplate: (id: string, name: string) => Promise<void>;
+  template: { id: string; name: string; description: string } | null;
+  onSaveTemplate: (id: string, name: string, description: string) => Promise<void>;
   onDeleteTemplate: (id: string) => void;
 }
 
@@ -27,16 +27,18 @@ export function EditTemplateDialog({
   onDeleteTemplate,
 }: EditTemplateDialogProps) {
   const [name, setName] = useState("");
+  const [description, setDescription] = useState("");
 
   useEffect(() => {
     if (template) {
       setName(template.name);
+      setDescription(template.description);
     }
   }, [template]);
 
   const handleSave = async () => {
     if (!template || !name.trim()) return;
-    await onSaveTemplate(template.id, name.trim());
+    await onSaveTemplate(template.id, name.trim(), description.trim());
     onOpenChange(false);
   };
 
@@ -50,9 +52,11 @@ export function EditTemplateDialog({
       <DialogContent>
         <DialogHeader>
           <DialogTitle>Edit Template</DialogTitle>
-          <DialogDescription>Rename this template.</DialogDescription>
+          <DialogDescription>
+            Rename this template or describe what it's for.
+          </DialogDescription>
         </DialogHeader>
-        <div className="py-4">
+        <div className="py-4 space-y-2">
           <Input
             placeholder="Template name"
             value={name}
@@ -64,6 +68,16 @@ export function EditTemplateDialog({
             }}
             autoFocus
           />
+          <Input
+            placeholder="Description (optional)"
+            value={description}
+            onChange={(e) => setDescription(e.target.value)}
+            onKeyDown={(e) => {
+              if (e.key === "Enter") {
+                handleSave();
+              }
+            }}
+          />
         </div>
         <DialogFooter className="flex-col sm:flex-row gap-2">
           <Button
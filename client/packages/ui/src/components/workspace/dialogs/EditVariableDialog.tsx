@@ -1,6 +1,6 @@
-import { useState, useEffect } from "react";
+import { useState, useEffect, useMemo, useRef } from "react";
 import * as YAML from "yaml";
-import { Trash2 } from "lucide-react";
+import { Search, Trash2 } from "lucide-react";
 import { Button } from "../../ui/button";
 import { Input } from "../../ui/input";
 import { Textarea } from "../../ui/textarea";
@@ -12,6 +12,7 @@ import {
   DialogHeader,
   DialogTitle,
 } from "../../ui/dialog";
+import { findOptionMatches } from "../../../lib/optionSearch";
 
 interface EditVariableDialogProps {
   open: boolean;
@@ -35,6 +36,9 @@ export function EditVariableDialog({
   const [name, setName] = useState("");
   const [yamlContent, setYamlContent] = useState("");
   const [error, setError] = useState<string | null>(null);
+  const [findQuery, setFindQuery] = useState("");
+  const [findIndex, setFindIndex] = useState(-1);
+  const textareaRef = useRef<HTMLTextAreaElement>(null);
 
   useEffect(() => {
     if (variable) {
@@ -45,9 +49,35 @@ export function EditVariableDialog({
           : "- option one\n- option two";
       setYamlContent(yaml);
       setError(null);
+      setFindQuery("");
     }
   }, [variable]);
 
+  const matches = useMemo(
+    () => findOptionMatches(yamlContent, findQuery),
+    [yamlContent, findQuery]
+  );
+
+  // Start over whenever the query or the text changes
+  useEffect(() => {
+    setFindIndex(-1);
+  }, [matches]);
+
+  // Select the next matching option and scroll its line into view
+  const handleFindNext = () => {
+    const textarea = textareaRef.current;
+    if (!textarea || matches.length === 0) return;
+
+    const next = (findIndex + 1) % matches.length;
+    const match = matches[next];
+    setFindIndex(next);
+
+    const lineHeight = parseFloat(getComputedStyle(textarea).lineHeight) || 20;
+    textarea.focus();
+    textarea.setSelectionRange(match.start, match.end);
+    textarea.scrollTop = Math.max(0, (match.line - 2) * lineHeight);
+  };
+
   const handleSave = async () => {
     if (!variable) return;
     setError(null);
@@ -98,7 +128,34 @@ export function EditVariableDialog({
           </div>
           <div className="space-y-2">
             <label className="text-sm font-medium">Options (YAML list)</label>
+            <div className="flex items-center gap-2">
+              <div className="relative flex-1">
+                <Search className="absolute left-2.5 top-1/2 -translate-y-1/2 h-3.5 w-3.5 text-muted-foreground pointer-events-none" />
+                <Input
+                  placeholder="Find option..."
+                  value={findQuery}
+                  onChange={(e) => setFindQuery(e.target.value)}
+                  onKeyDown={(e) => {
+                    if (e.key === "Enter") {
+                      e.preventDefault();
+                      handleFindNext();
+                    }
+                  }}
+                  className="h-8 pl-8 text-sm"
+                />
+              </div>
+              {findQuery.trim() && (
+                <span className="text-xs text-muted-foreground whitespace-nowrap">
+                  {matches.length === 0
+                    ? "No matches"
+                    : findIndex >= 0
+                      ? `${findIndex + 1} of ${matches.length} (line ${matches[findIndex].line + 1})`
+                      : `${matches.length} match${matches.length === 1 ? "" : "es"}`}
+                </span>
+              )}
+            </div>
             <Textarea
+              ref={textareaRef}
               placeholder="- option1&#10;- option2&#10;- option3"
               value={yamlContent}
               onChange={(e) => setYamlContent(e.target.value)}
@@ -1,5 +1,5 @@
 import { useCallback } from "react";
-import { useBackend } from "@promptgen/backend";
+import { useBackend, type Library } from "@promptgen/backend";
 import { useLibraryStore } from "../stores/useLibraryStore";
 import { useUIStore } from "../stores/useUIStore";
 
@@ -100,6 +100,28 @@ export function useLibraries() {
     [backend, setActiveLibrary, setSelectedLibraryId, setSelectedTemplateId, setLoading, setError]
   );
 
+  // Refresh libraries changed on disk, keeping the current selection
+  const refreshChangedLibraries = useCallback(
+    async (ids: string[]) => {
+      try {
+        const libs = await backend.listLibraries();
+        setLibraries(libs);
+        if (activeLibrary && ids.includes(activeLibrary.id)) {
+          const lib = await backend.loadLibrary(activeLibrary.id);
+          setActiveLibrary(lib);
+        }
+      } catch (e) {
+        console.error("Failed to refresh libraries:", e);
+      }
+    },
+    [backend, activeLibrary, setLibraries, setActiveLibrary]
+  );
+
+  const watchLibraries = useCallback(() => {
+    if (!backend.onLibrariesChanged) return () => {};
+    return backend.onLibrariesChanged(refreshChangedLibraries);
+  }, [backend, refreshChangedLibraries]);
+
   const createLibrary = useCallback(
     async (name: string) => {
       setLoading(true);
@@ -156,6 +178,42 @@ export function useLibraries() {
     [backend, activeLibrary, setActiveLibrary, setSelectedLibraryId, setSelectedTemplateId, setLoading, setError, loadLibraries]
   );
 
+  // Show a library restored by undo/redo, moving the selection off a
+  // template that no longer exists
+  const showRestoredLibrary = useCallback(
+    (lib: Library) => {
+      setActiveLibrary(lib);
+      if (selectedTemplateId && !lib.templates.some((t) => t.id === selectedTemplateId)) {
+        setSelectedTemplateId(lib.templates[0]?.id ?? null);
+      }
+    },
+    [selectedTemplateId, setActiveLibrary, setSelectedTemplateId]
+  );
+
+  // Undo/redo the latest edit to the active library. Having nothing to undo
+  // is not an error worth showing, but a failed save is.
+  const undoEdit = useCallback(async () => {
+    if (!activeLibrary || !backend.undoLibraryEdit) return;
+    try {
+      showRestoredLibrary(await backend.undoLibraryEdit(activeLibrary.id));
+    } catch (e) {
+      if (e === "Nothing to undo") return;
+      console.error("Failed to undo:", e);
+      setError(e instanceof Error ? e.message : "Failed to undo");
+    }
+  }, [backend, activeLibrary, showRestoredLibrary, setError]);
+
+  const redoEdit = useCallback(async () => {
+    if (!activeLibrary || !backend.redoLibraryEdit) return;
+    try {
+      showRestoredLibrary(await backend.redoLibraryEdit(activeLibrary.id));
+    } catch (e) {
+      if (e === "Nothing to redo") return;
+      console.error("Failed to redo:", e);
+      setError(e instanceof Error ? e.message : "Failed to redo");
+    }
+  }, [backend, activeLibrary, showRestoredLibrary, setError]);
+
   const selectTemplate = useCallback(
     (templateId: string | null) => {
       setSelectedTemplateId(templateId);
@@ -248,12 +306,17 @@ export function useLibraries() {
 
   // Template CRUD operations
   const createTemplate = useCallback(
-    async (name: string, content: string = "") => {
+    async (name: string, content: string = "", description?: string) => {
       if (!activeLibrary || !backend.createTemplate) return null;
       setLoading(true);
       setError(null);
       try {
-        const template = await backend.createTemplate(activeLibrary.id, name, content);
+        const template = await backend.createTemplate(
+          activeLibrary.id,
+          name,
+          content,
+          description
+        );
         // Reload the library to get updated templates
         const lib = await backend.loadLibrary(activeLibrary.id);
         setActiveLibrary(lib);
@@ -271,12 +334,18 @@ export function useLibraries() {
   );
 
   const updateTemplate = useCallback(
-    async (templateId: string, name: string, content: string) => {
+    async (templateId: string, name: string, content: string, description?: string) => {
       if (!activeLibrary || !backend.updateTemplate) return null;
       setLoading(true);
       setError(null);
       try {
-        const template = await backend.updateTemplate(activeLibrary.id, templateId, name, content);
+        const template = await backend.updateTemplate(
+          activeLibrary.id,
+          templateId,
+          name,
+          content,
+          description
+        );
         // Reload the library to get updated templates
         const lib = await backend.loadLibrary(activeLibrary.id);
         setActiveLibrary(lib);
@@ -332,9 +401,12 @@ export function useLibraries() {
     pickFolder,
     loadLibraries,
     loadLibrary,
+    watchLibraries,
     createLibrary,
     saveLibrary,
     deleteLibrary,
+    undoEdit,
+    redoEdit,
     selectTemplate,
     // Prompt Group CRUD
     createPromptGroup,
@@ -3,6 +3,10 @@ import { useBackend } from "@promptgen/backend";
 import { useTemplateStore } from "../stores/useTemplateStore";
 import { useLibraryStore } from "../stores/useLibraryStore";
 
+function randomSeed() {
+  return Math.floor(Math.random() * 1000000);
+}
+
 export function useTemplateEditor() {
   const backend = useBackend();
   const {
@@ -10,12 +14,24 @@ export function useTemplateEditor() {
     editorContent,
     parseResult,
     renderedOutput,
+    lastRenderResult,
+    previewSeed,
+    seedPinned,
+    groupSalts,
     isRendering,
     error,
     setActiveTemplate,
     setEditorContent,
     setParseResult,
     setRenderedOutput,
+    setLastRenderResult,
+    setPreviewSeed,
+    pinSeed,
+    unpinSeed,
+    setGroupSalts,
+    renderHistory,
+    pushHistory,
+    clearHistory,
     setRendering,
     setError,
   } = useTemplateStore();
@@ -36,7 +52,11 @@ export function useTemplateEditor() {
       }
 
       try {
-        const result = await backend.parseTemplate(editorContent);
+        // Check references against the active library when the backend can
+        const result =
+          activeLibrary && backend.analyzeTemplate
+            ? await backend.analyzeTemplate(activeLibrary.id, editorContent)
+            : await backend.parseTemplate(editorContent);
         setParseResult(result);
       } catch (e) {
         setError(e instanceof Error ? e.message : "Parse failed");
@@ -48,23 +68,29 @@ export function useTemplateEditor() {
         clearTimeout(parseTimeoutRef.current);
       }
     };
-  }, [editorContent, backend, setParseResult, setError]);
+  }, [editorContent, activeLibrary, backend, setParseResult, setError]);
 
-  const render = useCallback(
-    async (seed?: number) => {
+  const renderWith = useCallback(
+    async (renderSeed: number, salts: Record<string, number>) => {
       if (!activeTemplate || !activeLibrary) return;
 
+      setPreviewSeed(renderSeed);
+      setGroupSalts(salts);
       setRendering(true);
       setError(null);
       try {
         const result = await backend.renderTemplate({
           templateId: activeTemplate.id,
           libraryId: activeLibrary.id,
-          seed,
+          seed: renderSeed,
+          groupSalts: salts,
+          keepMissingRefs: true,
         });
 
         if (result.success && result.output) {
           setRenderedOutput(result.output);
+          setLastRenderResult(result);
+          pushHistory({ seed: renderSeed, text: result.output });
         } else {
           setError(result.error ?? "Render failed");
         }
@@ -78,12 +104,48 @@ export function useTemplateEditor() {
       backend,
       activeTemplate,
       activeLibrary,
+      setPreviewSeed,
+      setGroupSalts,
       setRenderedOutput,
+      setLastRenderResult,
+      pushHistory,
       setRendering,
       setError,
     ]
   );
 
+  // Without an explicit seed, a pinned seed is reused; the first render
+  // uses the library's default seed if it has one; otherwise a new seed is
+  // drawn, so every render can be reproduced later. Per-group re-rolls are
+  // kept only while the seed stays the same.
+  const render = useCallback(
+    async (seed?: number) => {
+      if (!activeLibrary) return;
+
+      let renderSeed = seed;
+      if (renderSeed === undefined) {
+        if (seedPinned && previewSeed !== null) {
+          renderSeed = previewSeed;
+        } else if (previewSeed === null && activeLibrary.defaultSeed != null) {
+          renderSeed = activeLibrary.defaultSeed;
+        } else {
+          renderSeed = randomSeed();
+        }
+      }
+      await renderWith(renderSeed, renderSeed === previewSeed ? groupSalts : {});
+    },
+    [activeLibrary, seedPinned, previewSeed, groupSalts, renderWith]
+  );
+
+  // Re-roll one group's pick, keeping the seed and every other pick
+  const rerollGroup = useCallback(
+    async (group: string) => {
+      if (previewSeed === null) return;
+      await renderWith(previewSeed, { ...groupSalts, [group]: randomSeed() });
+    },
+    [previewSeed, groupSalts, renderWith]
+  );
+
   const updateContent = useCallback(
     (content: string) => {
       setEditorContent(content);
@@ -96,10 +158,18 @@ export function useTemplateEditor() {
     editorContent,
     parseResult,
     renderedOutput,
+    lastRenderResult,
+    previewSeed,
+    seedPinned,
     isRendering,
     error,
     setActiveTemplate,
     updateContent,
     render,
+    rerollGroup,
+    pinSeed,
+    unpinSeed,
+    renderHistory,
+    clearHistory,
   };
 }
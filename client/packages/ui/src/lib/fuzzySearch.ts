@@ -71,6 +71,48 @@ export function fuzzySearchStrings(
   return new Set(results.map((r) => r.refIndex));
 }
 
+/**
+ * An option that matched a search, with its rank (lower is better)
+ */
+export interface OptionMatch {
+  index: number;
+  score: number;
+}
+
+/**
+ * Score how well an option matches a query; lower is better.
+ * A match at the start of the option beats one at the start of a later
+ * word, which beats one inside a word, which beats a fuzzy match.
+ * Shorter options rank first within each of those tiers.
+ */
+export function scoreOptionMatch(option: string, query: string): number {
+  const text = option.toLowerCase();
+  const needle = query.trim().toLowerCase();
+
+  let tier = 3;
+  if (text.startsWith(needle)) {
+    tier = 0;
+  } else if (text.split(/\s+/).some((word) => word.startsWith(needle))) {
+    tier = 1;
+  } else if (text.includes(needle)) {
+    tier = 2;
+  }
+
+  return tier * 10000 + Math.min(option.length, 9999);
+}
+
+/**
+ * Fuzzy search options, best matches first (ties keep option order)
+ */
+export function rankOptionMatches(
+  options: string[],
+  query: string
+): OptionMatch[] {
+  return [...fuzzySearchStrings(options, query)]
+    .map((index) => ({ index, score: scoreOptionMatch(options[index], query) }))
+    .sort((a, b) => a.score - b.score || a.index - b.index);
+}
+
 /**
  * Result of filtering variables
  */
@@ -78,6 +120,8 @@ export interface FilteredVariable {
   name: string;
   options: string[];
   matchingOptionIndices: Set<number>;
+  /** Matching options in relevance order; empty when showing all options */
+  optionMatches: OptionMatch[];
   showAllOptions: boolean; // true when group matched, false when filtering by options
 }
 
@@ -98,6 +142,7 @@ export function filterVariables(
       name,
       options,
       matchingOptionIndices: new Set(),
+      optionMatches: [],
       showAllOptions: true,
     }));
   }
@@ -108,12 +153,13 @@ export function filterVariables(
       const results: FilteredVariable[] = [];
 
       for (const [name, options] of entries) {
-        const matchingIndices = fuzzySearchStrings(options, query.optionQuery);
-        if (matchingIndices.size > 0) {
+        const optionMatches = rankOptionMatches(options, query.optionQuery);
+        if (optionMatches.length > 0) {
           results.push({
             name,
             options,
-            matchingOptionIndices: matchingIndices,
+            matchingOptionIndices: new Set(optionMatches.map((m) => m.index)),
+            optionMatches,
             showAllOptions: false,
           });
         }
@@ -136,6 +182,7 @@ export function filterVariables(
           name,
           options,
           matchingOptionIndices: new Set(),
+          optionMatches: [],
           showAllOptions: true,
         }));
     }
@@ -161,16 +208,18 @@ export function filterVariables(
             name,
             options,
             matchingOptionIndices: new Set(),
+            optionMatches: [],
             showAllOptions: true,
           });
         } else {
           // Filter options within matching groups
-          const matchingIndices = fuzzySearchStrings(options, query.optionQuery);
-          if (matchingIndices.size > 0) {
+          const optionMatches = rankOptionMatches(options, query.optionQuery);
+          if (optionMatches.length > 0) {
             results.push({
               name,
               options,
-              matchingOptionIndices: matchingIndices,
+              matchingOptionIndices: new Set(optionMatches.map((m) => m.index)),
+              optionMatches,
               showAllOptions: false,
             });
           }
@@ -0,0 +1,44 @@
+import { isNode, isSeq, parseDocument } from "yaml";
+import { rankOptionMatches } from "./fuzzySearch";
+
+/**
+ * Where an option is written in the variable editor's YAML list
+ */
+export interface OptionLocation {
+  /** Index of the option in the list */
+  index: number;
+  /** Zero-based line the option starts on */
+  line: number;
+  /** Character offsets of the option's value in the text */
+  start: number;
+  end: number;
+}
+
+/**
+ * Find the options in a YAML option list that match a query, best match
+ * first, using the same ranking as the sidebar search.
+ * Returns an empty list when the text isn't a YAML list.
+ */
+export function findOptionMatches(
+  yamlContent: string,
+  query: string
+): OptionLocation[] {
+  if (!query.trim()) return [];
+
+  const doc = parseDocument(yamlContent);
+  if (doc.errors.length > 0 || !isSeq(doc.contents)) return [];
+
+  const items = doc.contents.items;
+  // Read options the same way the dialog does when saving
+  const options = (doc.contents.toJSON() as unknown[]).map((item) =>
+    String(item)
+  );
+
+  return rankOptionMatches(options, query).flatMap(({ index }) => {
+    const item = items[index];
+    if (!isNode(item) || !item.range) return [];
+    const [start, end] = item.range;
+    const line = yamlContent.slice(0, start).split("\n").length - 1;
+    return [{ index, line, start, end }];
+  });
+}
@@ -1,5 +1,14 @@
 import { create } from "zustand";
-import type { Template, ParseResult } from "@promptgen/backend";
+import { persist } from "zustand/middleware";
+import type { Template, ParseResult, RenderResult } from "@promptgen/backend";
+
+/** A previous preview render that can be recalled by its seed. */
+export interface RenderHistoryEntry {
+  seed: number;
+  text: string;
+}
+
+const MAX_RENDER_HISTORY = 50;
 
 interface TemplateState {
   // State
@@ -7,6 +16,11 @@ interface TemplateState {
   editorContent: string;
   parseResult: ParseResult | null;
   renderedOutput: string | null;
+  lastRenderResult: RenderResult | null;
+  previewSeed: number | null;
+  seedPinned: boolean;
+  groupSalts: Record<string, number>;
+  renderHistory: RenderHistoryEntry[];
   isRendering: boolean;
   error: string | null;
 
@@ -15,6 +29,13 @@ interface TemplateState {
   setEditorContent: (content: string) => void;
   setParseResult: (result: ParseResult | null) => void;
   setRenderedOutput: (output: string | null) => void;
+  setLastRenderResult: (result: RenderResult | null) => void;
+  setPreviewSeed: (seed: number | null) => void;
+  pinSeed: () => void;
+  unpinSeed: () => void;
+  setGroupSalts: (salts: Record<string, number>) => void;
+  pushHistory: (entry: RenderHistoryEntry) => void;
+  clearHistory: () => void;
   setRendering: (rendering: boolean) => void;
   setError: (error: string | null) => void;
   reset: () => void;
@@ -25,22 +46,56 @@ const initialState = {
   editorContent: "",
   parseResult: null,
   renderedOutput: null,
+  lastRenderResult: null,
+  previewSeed: null,
+  seedPinned: false,
+  groupSalts: {} as Record<string, number>,
+  renderHistory: [] as RenderHistoryEntry[],
   isRendering: false,
   error: null,
 };
 
-export const useTemplateStore = create<TemplateState>((set) => ({
-  ...initialState,
+export const useTemplateStore = create<TemplateState>()(
+  persist(
+    (set) => ({
+      ...initialState,
 
-  setActiveTemplate: (template) =>
-    set({
-      activeTemplate: template,
-      editorContent: template?.content ?? "",
+      setActiveTemplate: (template) =>
+        set({
+          activeTemplate: template,
+          editorContent: template?.content ?? "",
+        }),
+      setEditorContent: (content) => set({ editorContent: content }),
+      setParseResult: (result) => set({ parseResult: result }),
+      setRenderedOutput: (output) => set({ renderedOutput: output }),
+      setLastRenderResult: (result) => set({ lastRenderResult: result }),
+      setPreviewSeed: (seed) => set({ previewSeed: seed }),
+      pinSeed: () => set({ seedPinned: true }),
+      unpinSeed: () => set({ seedPinned: false }),
+      setGroupSalts: (salts) => set({ groupSalts: salts }),
+      // Newest first, capped at MAX_RENDER_HISTORY entries. Recalling an entry
+      // moves it to the top instead of duplicating it.
+      pushHistory: (entry) =>
+        set((state) => ({
+          renderHistory: [
+            entry,
+            ...state.renderHistory.filter(
+              (e) => e.seed !== entry.seed || e.text !== entry.text
+            ),
+          ].slice(0, MAX_RENDER_HISTORY),
+        })),
+      clearHistory: () => set({ renderHistory: [] }),
+      setRendering: (rendering) => set({ isRendering: rendering }),
+      setError: (error) => set({ error }),
+      reset: () => set(initialState),
     }),
-  setEditorContent: (content) => set({ editorContent: content }),
-  setParseResult: (result) => set({ parseResult: result }),
-  setRenderedOutput: (output) => set({ renderedOutput: output }),
-  setRendering: (rendering) => set({ isRendering: rendering }),
-  setError: (error) => set({ error }),
-  reset: () => set(initialState),
-}));
+    {
+      name: "promptgen-preview-settings",
+      // Only the seed pin survives a restart, along with the seed it pins
+      partialize: (state) => ({
+        seedPinned: state.seedPinned,
+        previewSeed: state.seedPinned ? state.previewSeed : null,
+      }),
+    }
+  )
+);
@@ -2,6 +2,7 @@ import { create } from "zustand";
 import { persist } from "zustand/middleware";
 
 export type SidebarViewMode = "templates" | "variables";
+export type Theme = "light" | "dark";
 
 interface UIState {
   // Sidebar
@@ -12,6 +13,9 @@ interface UIState {
   // Selected library (persisted)
   selectedLibraryId: string | null;
   setSelectedLibraryId: (id: string | null) => void;
+  // Color theme (persisted)
+  theme: Theme;
+  setTheme: (theme: Theme) => void;
 }
 
 const MIN_SIDEBAR_WIDTH = 180;
@@ -30,6 +34,8 @@ export const useUIStore = create<UIState>()(
       setSidebarViewMode: (mode) => set({ sidebarViewMode: mode }),
       selectedLibraryId: null,
       setSelectedLibraryId: (id) => set({ selectedLibraryId: id }),
+      theme: "light" as Theme,
+      setTheme: (theme) => set({ theme }),
     }),
     {
       name: "promptgen-ui-settings",
@@ -37,6 +43,7 @@ export const useUIStore = create<UIState>()(
         sidebarWidth: state.sidebarWidth,
         sidebarViewMode: state.sidebarViewMode,
         selectedLibraryId: state.selectedLibraryId,
+        theme: state.theme,
       }),
     }
   )
@@ -4,21 +4,30 @@
 
 use clap::{Parser, Subcommand, ValueEnum};
 use promptgen_core::{
-    EvalContext, Library, PromptTemplate, RenderError,
-    io::parse_pack,
+    ConflictStrategy, DiagnosticError, EvalContext, Library, MergeConflicts, PromptGroup,
+    PromptTemplate,
+    RenderError, RenderResult, Template,
+    dependency_dot, enumerate,
+    interop::to_wildcards,
+    io::{load_pack, parse_bundle, parse_pack, save_pack, serialize_bundle},
     parser::parse_template,
-    render,
+    render, render_to_writer, seed_from_label,
 };
 use serde::Serialize;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 
 #[derive(Parser)]
 #[command(name = "promptgen")]
 #[command(about = "A modular prompt system for generative AI", long_about = None)]
 struct Cli {
+    /// Treat library warnings (such as unused groups) as errors
+    #[arg(long, global = true)]
+    strict: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -35,13 +44,13 @@ enum Commands {
         #[arg(short, long)]
         template: Option<String>,
 
-        /// Inline template string to parse
+        /// Inline template string to parse (`-` reads it from standard input)
         #[arg(short, long)]
         inline: Option<String>,
 
         /// Output format
         #[arg(short, long, value_enum, default_value = "text")]
-        format: OutputFormat,
+        format: ParseFormat,
     },
 
     /// List parts of the library
@@ -53,6 +62,10 @@ enum Commands {
         #[arg(short, long)]
         lib: PathBuf,
 
+        /// Only list templates with this tag (repeatable; matches any)
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+
         /// Output format
         #[arg(short, long, value_enum, default_value = "text")]
         format: OutputFormat,
@@ -60,15 +73,15 @@ enum Commands {
 
     /// Render a template to a final prompt string
     Render {
-        /// Path to the library file
+        /// Path to the library file (optional for inline templates without references)
         #[arg(short, long)]
-        lib: PathBuf,
+        lib: Option<PathBuf>,
 
         /// Name of the template to render
         #[arg(short, long)]
         template: Option<String>,
 
-        /// Inline template string to render
+        /// Inline template string to render (`-` reads it from standard input)
         #[arg(short, long)]
         inline: Option<String>,
 
@@ -76,14 +89,214 @@ enum Commands {
         #[arg(long)]
         slots: Option<String>,
 
-        /// Random seed for deterministic output
+        /// Random seed for deterministic output (defaults to the library's `default_seed`)
         #[arg(short, long)]
         seed: Option<u64>,
 
+        /// Seed from a word or phrase instead of a number; the same label gives the same output
+        #[arg(long, conflicts_with = "seed")]
+        seed_label: Option<String>,
+
+        /// In text mode, follow the prompt with a `# chosen: ...` line
+        #[arg(long)]
+        show_choices: bool,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "text")]
+        format: OutputFormat,
+
+        /// Write the result to this file instead of standard output, creating missing directories
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Render every template in the library to a file
+    Export {
+        /// Path to the library file
+        #[arg(short, long)]
+        lib: PathBuf,
+
+        /// Path of the file to write, creating missing directories
+        #[arg(short, long, visible_alias = "output")]
+        out: PathBuf,
+
+        /// Number of renders per template
+        #[arg(short, long, default_value_t = 1)]
+        count: usize,
+
+        /// Base seed; render `i` of each template uses `seed + i` (defaults to the library's `default_seed`)
+        #[arg(short, long)]
+        seed: Option<u64>,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "text")]
+        format: ExportFormat,
+    },
+
+    /// Combine several libraries into one file
+    Merge {
+        /// Library files to merge, in order; the first provides the id and name
+        #[arg(required = true, num_args = 2..)]
+        libs: Vec<PathBuf>,
+
+        /// Path of the merged library file to write
+        #[arg(short, long)]
+        out: PathBuf,
+
+        /// What to do when a group or template name is defined more than once
+        #[arg(long, value_enum, default_value = "error")]
+        on_conflict: OnConflict,
+    },
+
+    /// Remove duplicate options from every group and rewrite the library
+    Clean {
+        /// Path to the library file
+        #[arg(short, long)]
+        lib: PathBuf,
+
+        /// Only report what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// List every distinct render of a template, without randomness
+    Enumerate {
+        /// Path to the library file
+        #[arg(short, long)]
+        lib: PathBuf,
+
+        /// Name of the template to enumerate
+        #[arg(short, long)]
+        template: Option<String>,
+
+        /// Inline template string to enumerate (`-` reads it from standard input)
+        #[arg(short, long)]
+        inline: Option<String>,
+
+        /// Stop after this many prompts
+        #[arg(long, default_value_t = 100)]
+        limit: usize,
+
         /// Output format
         #[arg(short, long, value_enum, default_value = "text")]
         format: OutputFormat,
     },
+
+    /// Print a group's options in a shuffled order
+    Shuffle {
+        /// Path to the library file
+        #[arg(short, long)]
+        lib: PathBuf,
+
+        /// Name of the group to shuffle
+        #[arg(short, long)]
+        group: String,
+
+        /// Random seed; the same seed gives the same order (defaults to the library's `default_seed`)
+        #[arg(short, long)]
+        seed: Option<u64>,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+
+    /// Add options to a group from a text file, one per line
+    ImportOptions {
+        /// Path to the library file
+        #[arg(short, long)]
+        lib: PathBuf,
+
+        /// Name of the group to add to; it is created if missing
+        #[arg(short, long)]
+        group: String,
+
+        /// Text file with one option per line (`---` lines fence a multi-line option)
+        #[arg(long)]
+        from: PathBuf,
+
+        /// Replace the group's options instead of appending to them
+        #[arg(long)]
+        replace: bool,
+    },
+
+    /// Bundle several libraries into one pack file
+    Pack {
+        /// Library files to bundle, in order
+        #[arg(required = true)]
+        libs: Vec<PathBuf>,
+
+        /// Path of the bundle file to write
+        #[arg(short, long)]
+        out: PathBuf,
+    },
+
+    /// Extract the libraries from a bundle written by `pack`
+    Unpack {
+        /// Path to the bundle file
+        bundle: PathBuf,
+
+        /// Directory to write the library files to; it is created if missing
+        #[arg(long)]
+        out_dir: PathBuf,
+    },
+
+    /// Write each group to a wildcard file for the A1111 and ComfyUI dynamic prompt extensions
+    ExportWildcards {
+        /// Path to the library file
+        #[arg(short, long)]
+        lib: PathBuf,
+
+        /// Directory to write the `.txt` files to; it is created if missing
+        #[arg(long)]
+        out_dir: PathBuf,
+    },
+
+    /// Print how groups reference each other as a Graphviz DOT graph
+    Graph {
+        /// Path to the library file
+        #[arg(short, long)]
+        lib: PathBuf,
+    },
+
+    /// Render a template many times and count how often each option is chosen
+    Analyze {
+        /// Path to the library file
+        #[arg(short, long)]
+        lib: PathBuf,
+
+        /// Name of the template to analyze
+        #[arg(short, long)]
+        template: String,
+
+        /// Number of renders
+        #[arg(short, long, default_value_t = 1000)]
+        count: usize,
+
+        /// Base seed; render `i` uses `seed + i` (defaults to the library's `default_seed`)
+        #[arg(short, long)]
+        seed: Option<u64>,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+
+    /// Print the completions an editor would offer at a cursor, as JSON
+    #[command(hide = true)]
+    Complete {
+        /// Path to the library file
+        #[arg(short, long)]
+        lib: PathBuf,
+
+        /// Template source being edited (`-` reads it from standard input)
+        #[arg(short, long)]
+        inline: String,
+
+        /// Byte offset of the cursor in the source (defaults to the end)
+        #[arg(long)]
+        cursor: Option<usize>,
+    },
 }
 
 #[derive(Clone, ValueEnum)]
@@ -92,6 +305,32 @@ enum OutputFormat {
     Json,
 }
 
+#[derive(Clone, ValueEnum)]
+enum ParseFormat {
+    Text,
+    Json,
+    /// The syntax tree as nested parentheses, for debugging the grammar
+    Sexpr,
+}
+
+#[derive(Clone, ValueEnum)]
+enum ExportFormat {
+    /// Renders grouped under a `# <template name>` header
+    Text,
+    /// One `{prompt, text, seed}` JSON object per line
+    Jsonl,
+}
+
+#[derive(Clone, ValueEnum)]
+enum OnConflict {
+    /// Keep the definition from the earliest library
+    First,
+    /// Keep the definition from the latest library
+    Last,
+    /// Fail without writing anything
+    Error,
+}
+
 #[derive(Clone, ValueEnum)]
 enum ListTarget {
     Groups,
@@ -107,9 +346,11 @@ enum CliError {
     Io(std::io::Error),
     Parse(String),
     Yaml(String),
-    Render(RenderError),
+    /// A render failure, with a group name to suggest if one was missing
+    Render(RenderError, Option<String>),
     InvalidArgs(String),
     Json(serde_json::Error),
+    Strict(usize),
 }
 
 impl std::fmt::Display for CliError {
@@ -118,9 +359,15 @@ impl std::fmt::Display for CliError {
             CliError::Io(e) => write!(f, "I/O error: {e}"),
             CliError::Parse(e) => write!(f, "Parse error: {e}"),
             CliError::Yaml(e) => write!(f, "YAML error: {e}"),
-            CliError::Render(e) => write!(f, "Render error: {e}"),
+            CliError::Render(e, None) => write!(f, "Render error: {e}"),
+            CliError::Render(e, Some(suggestion)) => {
+                write!(f, "Render error: {e}; did you mean '{suggestion}'?")
+            }
             CliError::InvalidArgs(e) => write!(f, "Invalid arguments: {e}"),
             CliError::Json(e) => write!(f, "JSON error: {e}"),
+            CliError::Strict(count) => {
+                write!(f, "{count} warning(s) treated as errors (--strict)")
+            }
         }
     }
 }
@@ -131,9 +378,10 @@ impl CliError {
             CliError::Io(_) => ExitCode::from(1),
             CliError::Parse(_) => ExitCode::from(2),
             CliError::Yaml(_) => ExitCode::from(3),
-            CliError::Render(_) => ExitCode::from(4),
+            CliError::Render(..) => ExitCode::from(4),
             CliError::InvalidArgs(_) => ExitCode::from(5),
             CliError::Json(_) => ExitCode::from(6),
+            CliError::Strict(_) => ExitCode::from(7),
         }
     }
 }
@@ -158,7 +406,10 @@ impl From<promptgen_core::ParseError<'_>> for CliError {
 
 impl From<RenderError> for CliError {
     fn from(e: RenderError) -> Self {
-        CliError::Render(e)
+        match e {
+            RenderError::Write(e) => CliError::Io(e),
+            e => CliError::Render(e, None),
+        }
     }
 }
 
@@ -168,6 +419,22 @@ impl From<serde_json::Error> for CliError {
     }
 }
 
+/// The error for a template name that isn't in the library.
+fn template_not_found(library: &Library, name: &str) -> CliError {
+    CliError::InvalidArgs(with_suggestion(
+        format!("Template '{}' not found in library", name),
+        library.suggest_template(name),
+    ))
+}
+
+/// Add a "did you mean" hint to `message` if there is a suggestion.
+fn with_suggestion(message: String, suggestion: Option<String>) -> String {
+    match suggestion {
+        Some(suggestion) => format!("{}; did you mean '{}'?", message, suggestion),
+        None => message,
+    }
+}
+
 // ============================================================================
 // Main entry point
 // ============================================================================
@@ -187,14 +454,38 @@ fn main() -> ExitCode {
 fn run(cli: Cli) -> Result<(), CliError> {
     match cli.command {
         Commands::Parse { lib, template, inline, format } => {
-            cmd_parse(lib, template, inline, format)
+            cmd_parse(lib, template, inline, format, cli.strict)
+        }
+        Commands::List { what, lib, tags, format } => {
+            cmd_list(what, lib, &tags, format)
+        }
+        Commands::Render {
+            lib, template, inline, slots, seed, seed_label, show_choices, format, output,
+        } => {
+            let seed = seed.or(seed_label.map(|label| seed_from_label(&label)));
+            let rendered = cmd_render(lib, template, inline, slots, seed, show_choices, format)?;
+            write_output(output.as_deref(), &rendered)
         }
-        Commands::List { what, lib, format } => {
-            cmd_list(what, lib, format)
+        Commands::Export { lib, out, count, seed, format } => {
+            cmd_export(lib, out, count, seed, format)
         }
-        Commands::Render { lib, template, inline, slots, seed, format } => {
-            cmd_render(lib, template, inline, slots, seed, format)
+        Commands::Merge { libs, out, on_conflict } => cmd_merge(&libs, out, on_conflict),
+        Commands::Clean { lib, dry_run } => cmd_clean(lib, dry_run),
+        Commands::Enumerate { lib, template, inline, limit, format } => {
+            cmd_enumerate(lib, template, inline, limit, format)
         }
+        Commands::Shuffle { lib, group, seed, format } => cmd_shuffle(lib, &group, seed, format),
+        Commands::ImportOptions { lib, group, from, replace } => {
+            cmd_import_options(lib, &group, from, replace)
+        }
+        Commands::Pack { libs, out } => cmd_pack(&libs, out),
+        Commands::Unpack { bundle, out_dir } => cmd_unpack(bundle, out_dir),
+        Commands::ExportWildcards { lib, out_dir } => cmd_export_wildcards(lib, out_dir),
+        Commands::Graph { lib } => cmd_graph(lib),
+        Commands::Analyze { lib, template, count, seed, format } => {
+            cmd_analyze(lib, &template, count, seed, format)
+        }
+        Commands::Complete { lib, inline, cursor } => cmd_complete(lib, inline, cursor),
     }
 }
 
@@ -202,6 +493,65 @@ fn run(cli: Cli) -> Result<(), CliError> {
 // Parse command
 // ============================================================================
 
+/// Parse inline template source, reporting every error with its location.
+///
+/// Text mode prints each error with the offending source line; JSON mode
+/// prints the diagnostics as `{"errors": [...]}`.
+fn check_inline(source: &str, format: &OutputFormat) -> Result<Template, CliError> {
+    let errors = match parse_template(source) {
+        Ok(template) => return Ok(template),
+        Err(err) => err.diagnostics(),
+    };
+
+    match format {
+        OutputFormat::Text => {
+            for error in &errors {
+                eprintln!("{}", format_diagnostic(source, error));
+            }
+        }
+        OutputFormat::Json => {
+            let output = ParseErrorsOutput { errors: &errors };
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        }
+    }
+
+    Err(CliError::Parse(format!("{} error(s) in template", errors.len())))
+}
+
+/// Format a diagnostic with the source line it points at, underlined.
+///
+/// ```text
+/// error: unknown group 'Hiar'
+///  --> 1:1
+///   |
+/// 1 | @Hiar portrait
+///   | ^^^^^
+///   = help: did you mean 'Hair'?
+/// ```
+fn format_diagnostic(source: &str, error: &DiagnosticError) -> String {
+    let start = error.span.start.min(source.len());
+    let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[start..].find('\n').map_or(source.len(), |i| start + i);
+    let line = &source[line_start..line_end];
+    let line_number = source[..line_start].matches('\n').count() + 1;
+
+    // Columns count characters; the underline stops at the end of the line
+    let column = source[line_start..start].chars().count();
+    let end = error.span.end.clamp(start, line_end);
+    let width = source[start..end].chars().count().max(1);
+
+    let gutter = " ".repeat(line_number.to_string().len());
+    let mut out = format!("error: {}\n", error.message);
+    out.push_str(&format!("{gutter}--> {}:{}\n", line_number, column + 1));
+    out.push_str(&format!("{gutter} |\n"));
+    out.push_str(&format!("{line_number} | {line}\n"));
+    out.push_str(&format!("{gutter} | {}{}", " ".repeat(column), "^".repeat(width)));
+    if let Some(suggestion) = &error.suggestion {
+        out.push_str(&format!("\n{gutter} = help: did you mean '{suggestion}'?"));
+    }
+    out
+}
+
 #[derive(Serialize)]
 struct ParseOutput {
     nodes: Vec<NodeInfo>,
@@ -216,25 +566,66 @@ struct NodeInfo {
     content: String,
 }
 
+#[derive(Serialize)]
+struct ParseErrorsOutput<'a> {
+    errors: &'a [DiagnosticError],
+}
+
+/// Resolve an `--inline` value of `-` by reading the template from stdin.
+///
+/// A single trailing newline, as added by `echo` or an editor, is dropped.
+fn read_inline(inline: Option<String>) -> Result<Option<String>, CliError> {
+    if inline.as_deref() != Some("-") {
+        return Ok(inline);
+    }
+
+    let mut source = String::new();
+    std::io::stdin().read_to_string(&mut source)?;
+    let source = source
+        .strip_suffix('\n')
+        .map(|s| s.strip_suffix('\r').unwrap_or(s))
+        .unwrap_or(&source);
+    if source.trim().is_empty() {
+        return Err(CliError::InvalidArgs("no template on standard input".to_string()));
+    }
+    Ok(Some(source.to_string()))
+}
+
 fn cmd_parse(
     lib: Option<PathBuf>,
     template: Option<String>,
     inline: Option<String>,
-    format: OutputFormat,
+    format: ParseFormat,
+    strict: bool,
 ) -> Result<(), CliError> {
-    let ast = match (&lib, &template, &inline) {
-        (Some(lib_path), Some(template_name), None) => {
+    let inline = read_inline(inline)?;
+    // The library is only read for a named template or for --strict
+    let library = match &lib {
+        Some(lib_path) if template.is_some() || strict => {
+            Some(parse_pack(&fs::read_to_string(lib_path)?)?)
+        }
+        _ => None,
+    };
+
+    if strict && let Some(library) = &library {
+        check_warnings(library)?;
+    }
+
+    let ast = match (&library, &template, &inline) {
+        (Some(library), Some(template_name), None) => {
             // Parse a template from the library
-            let content = fs::read_to_string(lib_path)?;
-            let library = parse_pack(&content)?;
-            let tmpl = library.find_template(template_name).ok_or_else(|| {
-                CliError::InvalidArgs(format!("Template '{}' not found in library", template_name))
-            })?;
+            let tmpl = library
+                .find_template(template_name)
+                .ok_or_else(|| template_not_found(library, template_name))?;
             tmpl.ast.clone()
         }
-        (None, None, Some(inline_str)) | (Some(_), None, Some(inline_str)) => {
+        (_, None, Some(inline_str)) => {
             // Parse an inline template string
-            parse_template(inline_str)?
+            let error_format = match format {
+                ParseFormat::Json => OutputFormat::Json,
+                ParseFormat::Text | ParseFormat::Sexpr => OutputFormat::Text,
+            };
+            check_inline(inline_str, &error_format)?
         }
         _ => {
             return Err(CliError::InvalidArgs(
@@ -244,7 +635,7 @@ fn cmd_parse(
     };
 
     match format {
-        OutputFormat::Text => {
+        ParseFormat::Text => {
             println!("Template structure:");
             for (node, span) in &ast.nodes {
                 let (node_type, content) = describe_node(node);
@@ -269,8 +660,8 @@ fn cmd_parse(
 
             // Show slots
             let slots: Vec<_> = ast.nodes.iter().filter_map(|(node, _)| {
-                if let promptgen_core::Node::Slot(name) = node {
-                    Some(name.clone())
+                if let promptgen_core::Node::Slot(slot) = node {
+                    Some(format_slot(slot))
                 } else {
                     None
                 }
@@ -283,7 +674,7 @@ fn cmd_parse(
                 }
             }
         }
-        OutputFormat::Json => {
+        ParseFormat::Json => {
             let nodes: Vec<NodeInfo> = ast.nodes.iter().map(|(node, _)| {
                 let (node_type, content) = describe_node(node);
                 NodeInfo { node_type, content }
@@ -298,8 +689,8 @@ fn cmd_parse(
             }).collect();
 
             let slots: Vec<String> = ast.nodes.iter().filter_map(|(node, _)| {
-                if let promptgen_core::Node::Slot(name) = node {
-                    Some(name.clone())
+                if let promptgen_core::Node::Slot(slot) = node {
+                    Some(format_slot(slot))
                 } else {
                     None
                 }
@@ -308,16 +699,32 @@ fn cmd_parse(
             let output = ParseOutput { nodes, library_refs: refs, slots };
             println!("{}", serde_json::to_string_pretty(&output)?);
         }
+        ParseFormat::Sexpr => println!("{}", ast.to_sexpr()),
     }
 
     Ok(())
 }
 
+/// Print library warnings and fail if there are any (`--strict`).
+fn check_warnings(library: &Library) -> Result<(), CliError> {
+    let warnings = library.warnings();
+    for warning in &warnings {
+        eprintln!("warning: {}", warning.message);
+    }
+
+    if warnings.is_empty() {
+        Ok(())
+    } else {
+        Err(CliError::Strict(warnings.len()))
+    }
+}
+
 fn describe_node(node: &promptgen_core::Node) -> (String, String) {
     match node {
         promptgen_core::Node::Text(text) => ("Text".to_string(), text.clone()),
         promptgen_core::Node::Comment(text) => ("Comment".to_string(), text.clone()),
-        promptgen_core::Node::Slot(name) => ("Slot".to_string(), name.clone()),
+        promptgen_core::Node::Slot(slot) => ("Slot".to_string(), format_slot(slot)),
+        promptgen_core::Node::SlotRef(name) => ("SlotRef".to_string(), name.clone()),
         promptgen_core::Node::LibraryRef(lib_ref) => {
             ("LibraryRef".to_string(), format_library_ref(lib_ref))
         }
@@ -330,13 +737,32 @@ fn describe_node(node: &promptgen_core::Node) -> (String, String) {
             }).collect();
             ("InlineOptions".to_string(), items.join(" | "))
         }
+        promptgen_core::Node::MaybeOption(maybe) => {
+            ("MaybeOption".to_string(), format!("{} ({}%)", maybe.text, maybe.chance))
+        }
+    }
+}
+
+fn format_slot(slot: &promptgen_core::Slot) -> String {
+    if slot.required {
+        format!("{}!", slot.name)
+    } else {
+        slot.name.clone()
     }
 }
 
 fn format_library_ref(lib_ref: &promptgen_core::LibraryRef) -> String {
-    match &lib_ref.library {
+    let name = match &lib_ref.library {
         Some(lib) => format!("{}:{}", lib, lib_ref.group),
         None => lib_ref.group.clone(),
+    };
+    let name = match lib_ref.transform {
+        Some(transform) => format!("{} | {}", name, transform.keyword()),
+        None => name,
+    };
+    match &lib_ref.fallback {
+        Some(fallback) => format!("{} ?? \"{}\"", name, fallback),
+        None => name,
     }
 }
 
@@ -355,18 +781,34 @@ struct TemplateInfo {
     id: String,
     name: String,
     description: String,
+    tags: Vec<String>,
 }
 
-fn cmd_list(what: ListTarget, lib: PathBuf, format: OutputFormat) -> Result<(), CliError> {
+fn cmd_list(
+    what: ListTarget,
+    lib: PathBuf,
+    tags: &[String],
+    format: OutputFormat,
+) -> Result<(), CliError> {
     let content = fs::read_to_string(&lib)?;
     let library = parse_pack(&content)?;
 
     match what {
         ListTarget::Groups => list_groups(&library, format),
-        ListTarget::Templates => list_templates(&library, format),
+        ListTarget::Templates => list_templates(&library, tags, format),
     }
 }
 
+/// Templates that have any of `tags` (ignoring case), or all templates if
+/// no tags are given.
+fn filter_templates<'a>(library: &'a Library, tags: &[String]) -> Vec<&'a PromptTemplate> {
+    library
+        .templates
+        .iter()
+        .filter(|t| tags.is_empty() || tags.iter().any(|tag| t.has_tag(tag)))
+        .collect()
+}
+
 fn list_groups(library: &Library, format: OutputFormat) -> Result<(), CliError> {
     match format {
         OutputFormat::Text => {
@@ -388,24 +830,32 @@ fn list_groups(library: &Library, format: OutputFormat) -> Result<(), CliError>
     Ok(())
 }
 
-fn list_templates(library: &Library, format: OutputFormat) -> Result<(), CliError> {
+fn list_templates(library: &Library, tags: &[String], format: OutputFormat) -> Result<(), CliError> {
+    let templates = filter_templates(library, tags);
+
     match format {
         OutputFormat::Text => {
             println!("Templates in '{}':", library.name);
-            for tmpl in &library.templates {
+            for tmpl in templates {
+                let label = if tmpl.tags.is_empty() {
+                    tmpl.name.clone()
+                } else {
+                    format!("{} [{}]", tmpl.name, tmpl.tags.join(", "))
+                };
                 if tmpl.description.is_empty() {
-                    println!("  {}", tmpl.name);
+                    println!("  {}", label);
                 } else {
-                    println!("  {} - {}", tmpl.name, tmpl.description);
+                    println!("  {} - {}", label, tmpl.description);
                 }
             }
         }
         OutputFormat::Json => {
-            let templates: Vec<TemplateInfo> = library.templates.iter().map(|t| {
+            let templates: Vec<TemplateInfo> = templates.into_iter().map(|t| {
                 TemplateInfo {
                     id: t.id.clone(),
                     name: t.name.clone(),
                     description: t.description.clone(),
+                    tags: t.tags.clone(),
                 }
             }).collect();
             println!("{}", serde_json::to_string_pretty(&templates)?);
@@ -429,27 +879,51 @@ struct ChosenOptionInfo {
     group: String,
     library: Option<String>,
     option: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alias: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent: Option<usize>,
 }
 
 fn cmd_render(
-    lib: PathBuf,
+    lib: Option<PathBuf>,
     template: Option<String>,
     inline: Option<String>,
     slots: Option<String>,
     seed: Option<u64>,
+    show_choices: bool,
     format: OutputFormat,
-) -> Result<(), CliError> {
-    let content = fs::read_to_string(&lib)?;
-    let library = parse_pack(&content)?;
+) -> Result<String, CliError> {
+    let inline = read_inline(inline)?;
+
+    // Inline templates can be rendered without a library as long as they
+    // don't reference any groups
+    let library = match &lib {
+        Some(lib_path) => parse_pack(&fs::read_to_string(lib_path)?)?,
+        None if template.is_some() => {
+            return Err(CliError::InvalidArgs("--template requires --lib".to_string()));
+        }
+        None => Library::with_id("inline", "inline"),
+    };
 
     let tmpl: PromptTemplate = match (&template, &inline) {
         (Some(template_name), None) => {
-            library.find_template(template_name).ok_or_else(|| {
-                CliError::InvalidArgs(format!("Template '{}' not found in library", template_name))
-            })?.clone()
+            library
+                .find_template(template_name)
+                .ok_or_else(|| template_not_found(&library, template_name))?.clone()
         }
         (None, Some(inline_str)) => {
             let ast = parse_template(inline_str).map_err(|e| CliError::Parse(e.to_string()))?;
+            // Check every reference up front, not only the ones a render
+            // happens to pick
+            if lib.is_none()
+                && let Some(err) = library.analyze_template(inline_str).errors.first()
+            {
+                return Err(CliError::InvalidArgs(format!(
+                    "template references {} but no --lib was given",
+                    &inline_str[err.span.clone()]
+                )));
+            }
             PromptTemplate::new("inline", ast)
         }
         _ => {
@@ -466,21 +940,26 @@ fn cmd_render(
         HashMap::new()
     };
 
-    // Create evaluation context
-    let mut ctx = match seed {
-        Some(s) => EvalContext::with_seed(&library, s),
-        None => EvalContext::new(&library),
-    };
-    for (k, v) in slot_overrides {
-        ctx.set_slot(&k, v);
-    }
-
-    // Render the template
-    let result = render(&tmpl, &mut ctx)?;
+    let result = render_once(&library, &tmpl, library.resolve_seed(seed), &slot_overrides)
+        .map_err(|e| match e {
+            RenderError::GroupNotFound(name) if lib.is_none() => CliError::InvalidArgs(format!(
+                "template references @{} but no --lib was given",
+                name
+            )),
+            RenderError::GroupNotFound(name) => {
+                let suggestion = library.suggest_group(&name);
+                CliError::Render(RenderError::GroupNotFound(name), suggestion)
+            }
+            e => e.into(),
+        })?;
 
-    match format {
+    let rendered = match format {
         OutputFormat::Text => {
-            println!("{}", result.text);
+            let mut text = format!("{}\n", result.text);
+            if show_choices && !result.chosen_options.is_empty() {
+                text.push_str(&format!("# chosen: {}\n", result.summary()));
+            }
+            text
         }
         OutputFormat::Json => {
             let output = RenderOutput {
@@ -490,12 +969,822 @@ fn cmd_render(
                         group: c.group_name,
                         library: c.library_name,
                         option: c.option_text,
+                        alias: c.alias,
+                        parent: c.parent,
                     }
                 }).collect(),
             };
+            format!("{}\n", serde_json::to_string_pretty(&output)?)
+        }
+    };
+
+    Ok(rendered)
+}
+
+/// Print `text` as is, or write it to `path` if one was given.
+fn write_output(path: Option<&Path>, text: &str) -> Result<(), CliError> {
+    match path {
+        Some(path) => create_output_file(path)?.write_all(text.as_bytes())?,
+        None => print!("{}", text),
+    }
+    Ok(())
+}
+
+/// Create `path` for writing, along with any missing parent directories.
+fn create_output_file(path: &Path) -> Result<fs::File, CliError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(fs::File::create(path)?)
+}
+
+/// Render a template once against a library.
+///
+/// Shared by `render` and `export`. The template's default slots apply
+/// first and `slots` override them key by key. Without a seed the output
+/// is random.
+fn render_once(
+    library: &Library,
+    template: &PromptTemplate,
+    seed: Option<u64>,
+    slots: &HashMap<String, String>,
+) -> Result<RenderResult, RenderError> {
+    let mut ctx = eval_context(library, template, seed, slots);
+    render(template, &mut ctx)
+}
+
+fn eval_context<'a>(
+    library: &'a Library,
+    template: &PromptTemplate,
+    seed: Option<u64>,
+    slots: &HashMap<String, String>,
+) -> EvalContext<'a> {
+    let mut ctx = match seed {
+        Some(s) => EvalContext::with_seed(library, s),
+        None => EvalContext::new(library),
+    };
+    ctx.set_slots(template.slots_with_defaults(slots));
+    ctx
+}
+
+// ============================================================================
+// Export command
+// ============================================================================
+
+#[derive(Serialize)]
+struct ExportRecord<'a> {
+    prompt: &'a str,
+    text: String,
+    seed: u64,
+}
+
+fn cmd_export(
+    lib: PathBuf,
+    out: PathBuf,
+    count: usize,
+    seed: Option<u64>,
+    format: ExportFormat,
+) -> Result<(), CliError> {
+    let content = fs::read_to_string(&lib)?;
+    let library = parse_pack(&content)?;
+
+    let base_seed = library.resolve_seed(seed).unwrap_or_else(rand::random);
+    let mut writer = BufWriter::new(create_output_file(&out)?);
+    write_export(&library, count, base_seed, &format, &mut writer)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Render every template in `library` `count` times and write the results.
+///
+/// Render `i` of each template uses seed `base_seed + i`, so a given
+/// template's output does not depend on the templates before it.
+fn write_export(
+    library: &Library,
+    count: usize,
+    base_seed: u64,
+    format: &ExportFormat,
+    out: &mut impl Write,
+) -> Result<(), CliError> {
+    let slots = HashMap::new();
+
+    for (index, tmpl) in library.templates.iter().enumerate() {
+        if matches!(format, ExportFormat::Text) {
+            if index > 0 {
+                writeln!(out)?;
+            }
+            writeln!(out, "# {}", tmpl.name)?;
+        }
+
+        for i in 0..count {
+            let seed = base_seed.wrapping_add(i as u64);
+
+            match format {
+                ExportFormat::Text => {
+                    // Stream straight to the file instead of building the text
+                    let mut ctx = eval_context(library, tmpl, Some(seed), &slots);
+                    render_to_writer(tmpl, &mut ctx, out)?;
+                    writeln!(out)?;
+                }
+                ExportFormat::Jsonl => {
+                    let result = render_once(library, tmpl, Some(seed), &slots)?;
+                    let record = ExportRecord { prompt: &tmpl.name, text: result.text, seed };
+                    writeln!(out, "{}", serde_json::to_string(&record)?)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// ============================================================================
+// Merge command
+// ============================================================================
+
+fn cmd_merge(libs: &[PathBuf], out: PathBuf, on_conflict: OnConflict) -> Result<(), CliError> {
+    let strategy = match on_conflict {
+        OnConflict::First => ConflictStrategy::First,
+        OnConflict::Last => ConflictStrategy::Last,
+        OnConflict::Error => ConflictStrategy::Error,
+    };
+
+    let mut merged: Option<Library> = None;
+    for path in libs {
+        let library = parse_pack(&fs::read_to_string(path)?)?;
+        let Some(merged) = merged.as_mut() else {
+            merged = Some(library);
+            continue;
+        };
+
+        let conflicts = merged.merge(library, strategy).map_err(|e| {
+            CliError::InvalidArgs(format!(
+                "{} conflicts with earlier libraries ({}); use --on-conflict first or last",
+                path.display(),
+                conflict_names(&e.0)
+            ))
+        })?;
+        if !conflicts.is_empty() {
+            eprintln!(
+                "warning: {} redefines {}",
+                path.display(),
+                conflict_names(&conflicts)
+            );
+        }
+    }
+
+    if let Some(merged) = merged {
+        save_pack(&merged, &out)?;
+    }
+    Ok(())
+}
+
+fn conflict_names(conflicts: &MergeConflicts) -> String {
+    let groups = conflicts.groups.iter().map(|name| format!("group '{}'", name));
+    let templates = conflicts.templates.iter().map(|name| format!("template '{}'", name));
+    groups.chain(templates).collect::<Vec<_>>().join(", ")
+}
+
+// ============================================================================
+// Clean command
+// ============================================================================
+
+fn cmd_clean(lib: PathBuf, dry_run: bool) -> Result<(), CliError> {
+    // load_pack keeps a missing id stable, so rewriting doesn't invent one
+    let mut library = load_pack(&lib)?;
+
+    let mut total = 0;
+    for group in &mut library.groups {
+        let removed = group.dedupe();
+        if removed > 0 {
+            println!("{}: {} duplicate option(s)", group.name, removed);
+            total += removed;
+        }
+    }
+
+    if total == 0 {
+        println!("No duplicate options");
+    } else if dry_run {
+        println!("Would remove {} duplicate option(s)", total);
+    } else {
+        save_pack(&library, &lib)?;
+        println!("Removed {} duplicate option(s)", total);
+    }
+    Ok(())
+}
+
+// ============================================================================
+// Enumerate command
+// ============================================================================
+
+#[derive(Serialize)]
+struct EnumerateOutput<'a> {
+    prompts: &'a [String],
+    truncated: bool,
+}
+
+fn cmd_enumerate(
+    lib: PathBuf,
+    template: Option<String>,
+    inline: Option<String>,
+    limit: usize,
+    format: OutputFormat,
+) -> Result<(), CliError> {
+    let inline = read_inline(inline)?;
+    let library = parse_pack(&fs::read_to_string(&lib)?)?;
+
+    let ast = match (&template, &inline) {
+        (Some(template_name), None) => {
+            library
+                .find_template(template_name)
+                .ok_or_else(|| template_not_found(&library, template_name))?.ast.clone()
+        }
+        (None, Some(inline_str)) => {
+            parse_template(inline_str).map_err(|e| CliError::Parse(e.to_string()))?
+        }
+        _ => {
+            return Err(CliError::InvalidArgs(
+                "Specify either --template or --inline".to_string(),
+            ));
+        }
+    };
+
+    let result = enumerate(&ast, &library, limit);
+    match format {
+        OutputFormat::Text => {
+            for prompt in &result.prompts {
+                println!("{}", prompt);
+            }
+            if result.truncated {
+                eprintln!(
+                    "warning: stopped after {} prompts; raise --limit to see more",
+                    limit
+                );
+            }
+        }
+        OutputFormat::Json => {
+            let output = EnumerateOutput {
+                prompts: &result.prompts,
+                truncated: result.truncated,
+            };
             println!("{}", serde_json::to_string_pretty(&output)?);
         }
     }
+    Ok(())
+}
+
+// ============================================================================
+// Shuffle command
+// ============================================================================
+
+#[derive(Serialize)]
+struct ShuffleOutput {
+    group: String,
+    seed: u64,
+    options: Vec<String>,
+}
+
+fn cmd_shuffle(
+    lib: PathBuf,
+    group_name: &str,
+    seed: Option<u64>,
+    format: OutputFormat,
+) -> Result<(), CliError> {
+    let library = parse_pack(&fs::read_to_string(&lib)?)?;
+    let group = library.find_group(group_name).ok_or_else(|| {
+        CliError::InvalidArgs(with_suggestion(
+            format!("Group '{}' not found in library", group_name),
+            library.suggest_group(group_name),
+        ))
+    })?;
+
+    let seed = library.resolve_seed(seed).unwrap_or_else(rand::random);
+    let options = group.shuffled(seed);
 
+    match format {
+        OutputFormat::Text => {
+            for option in &options {
+                println!("{}", option);
+            }
+        }
+        OutputFormat::Json => {
+            let output = ShuffleOutput {
+                group: group.name.clone(),
+                seed,
+                options,
+            };
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        }
+    }
     Ok(())
 }
+
+// ============================================================================
+// Import options command
+// ============================================================================
+
+/// Split a text file into options.
+///
+/// Each non-empty line is one option, with surrounding whitespace trimmed.
+/// A line holding only `---` opens a block that runs to the next `---` line;
+/// the lines in between become a single option, joined with newlines. An
+/// unclosed block runs to the end of the file.
+fn parse_option_lines(text: &str) -> Vec<String> {
+    let mut options = Vec::new();
+    let mut block: Option<Vec<&str>> = None;
+
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed == "---" {
+            match block.take() {
+                Some(lines) => push_block(&mut options, &lines),
+                None => block = Some(Vec::new()),
+            }
+        } else if let Some(lines) = &mut block {
+            lines.push(line.trim_end());
+        } else if !trimmed.is_empty() {
+            options.push(trimmed.to_string());
+        }
+    }
+    if let Some(lines) = block {
+        push_block(&mut options, &lines);
+    }
+    options
+}
+
+fn push_block(options: &mut Vec<String>, lines: &[&str]) {
+    let option = lines.join("\n");
+    let option = option.trim_matches('\n');
+    if !option.trim().is_empty() {
+        options.push(option.to_string());
+    }
+}
+
+fn cmd_import_options(
+    lib: PathBuf,
+    group_name: &str,
+    from: PathBuf,
+    replace: bool,
+) -> Result<(), CliError> {
+    let options = parse_option_lines(&fs::read_to_string(&from)?);
+    if options.is_empty() {
+        return Err(CliError::InvalidArgs(format!(
+            "No options found in {}",
+            from.display()
+        )));
+    }
+
+    let mut library = load_pack(&lib)?;
+    let count = options.len();
+    match library.groups.iter_mut().find(|g| g.name == group_name) {
+        Some(group) if replace => {
+            group.options = options;
+            let PromptGroup { options, option_meta, .. } = group;
+            option_meta.retain(|text, _| options.contains(text));
+        }
+        Some(group) => group.options.extend(options),
+        None => library.groups.push(PromptGroup::new(group_name, options)),
+    }
+    save_pack(&library, &lib)?;
+
+    let verb = if replace { "Replaced options of" } else { "Imported into" };
+    println!("{} '{}': {} option(s)", verb, group_name, count);
+    Ok(())
+}
+
+// ============================================================================
+// Pack and unpack commands
+// ============================================================================
+
+fn cmd_pack(libs: &[PathBuf], out: PathBuf) -> Result<(), CliError> {
+    // load_pack gives id-less files the same id every time, so unpacking
+    // doesn't invent new ones
+    let libraries = libs
+        .iter()
+        .map(|path| load_pack(path))
+        .collect::<Result<Vec<_>, _>>()?;
+    fs::write(&out, serialize_bundle(&libraries)?)?;
+
+    println!("Packed {} library(s) into {}", libraries.len(), out.display());
+    Ok(())
+}
+
+fn cmd_unpack(bundle: PathBuf, out_dir: PathBuf) -> Result<(), CliError> {
+    let libraries = parse_bundle(&fs::read_to_string(&bundle)?)?;
+    fs::create_dir_all(&out_dir)?;
+
+    let mut used = HashSet::new();
+    for library in &libraries {
+        let stem = library_file_stem(library, &mut used);
+        // A stem that could leave `out_dir` is never written
+        if stem.contains(['/', '\\']) || stem.contains("..") {
+            return Err(CliError::InvalidArgs(format!(
+                "library '{}' has no usable file name",
+                library.name
+            )));
+        }
+        let path = out_dir.join(format!("{}.yml", stem));
+        save_pack(library, &path)?;
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+/// A file name for `library` based on its name, such as `my-library` for
+/// "My Library". Falls back to the id written the same way, then to
+/// `library`, and adds a number when the name is already in `used`.
+fn library_file_stem(library: &Library, used: &mut HashSet<String>) -> String {
+    let base = [&library.name, &library.id]
+        .into_iter()
+        .map(|text| slug(text))
+        .find(|slug| !slug.is_empty())
+        .unwrap_or_else(|| "library".to_string());
+
+    let mut stem = base.clone();
+    let mut n = 2;
+    while !used.insert(stem.clone()) {
+        stem = format!("{}-{}", base, n);
+        n += 1;
+    }
+    stem
+}
+
+/// `text` in lowercase, with each run of other characters than letters and
+/// digits turned into one `-`, trimmed.
+fn slug(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+// ============================================================================
+// Wildcard export command
+// ============================================================================
+
+fn cmd_export_wildcards(lib: PathBuf, out_dir: PathBuf) -> Result<(), CliError> {
+    let library = parse_pack(&fs::read_to_string(&lib)?)?;
+    let files: BTreeMap<String, String> = to_wildcards(&library).into_iter().collect();
+
+    for (name, text) in files {
+        let path = wildcard_path(&out_dir, &name).ok_or_else(|| {
+            CliError::InvalidArgs(format!("Group '{}' can't be used as a file name", name))
+        })?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, text)?;
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+/// Where the wildcard file for group `name` goes. A `/` in the name makes a
+/// subdirectory, as `__colors/warm__` expects; names that would leave
+/// `out_dir` give `None`.
+fn wildcard_path(out_dir: &Path, name: &str) -> Option<PathBuf> {
+    let mut path = out_dir.to_path_buf();
+    for part in name.split('/') {
+        if part.is_empty() || part == "." || part == ".." || part.contains('\\') {
+            return None;
+        }
+        path.push(part);
+    }
+    path.as_mut_os_string().push(".txt");
+    Some(path)
+}
+
+// ============================================================================
+// Graph command
+// ============================================================================
+
+fn cmd_graph(lib: PathBuf) -> Result<(), CliError> {
+    let library = parse_pack(&fs::read_to_string(&lib)?)?;
+    print!("{}", dependency_dot(&library));
+    Ok(())
+}
+
+// ============================================================================
+// Complete command
+// ============================================================================
+
+fn cmd_complete(lib: PathBuf, inline: String, cursor: Option<usize>) -> Result<(), CliError> {
+    let library = parse_pack(&fs::read_to_string(&lib)?)?;
+    let source = read_inline(Some(inline))?.unwrap_or_default();
+    let items = library.completions_at(&source, cursor.unwrap_or(source.len()));
+    println!("{}", serde_json::to_string_pretty(&items)?);
+    Ok(())
+}
+
+// ============================================================================
+// Analyze command
+// ============================================================================
+
+/// Widest histogram bar, for the most frequent option of a group.
+const HISTOGRAM_WIDTH: usize = 40;
+
+/// Each option of a group with the number of times it was chosen.
+type OptionCounts = Vec<(String, usize)>;
+
+#[derive(Serialize)]
+struct AnalyzeOutput {
+    template: String,
+    count: usize,
+    seed: u64,
+    /// Group name -> option -> times chosen
+    groups: BTreeMap<String, BTreeMap<String, usize>>,
+}
+
+fn cmd_analyze(
+    lib: PathBuf,
+    template_name: &str,
+    count: usize,
+    seed: Option<u64>,
+    format: OutputFormat,
+) -> Result<(), CliError> {
+    let library = parse_pack(&fs::read_to_string(&lib)?)?;
+    let template = library
+        .find_template(template_name)
+        .ok_or_else(|| template_not_found(&library, template_name))?;
+
+    let base_seed = library.resolve_seed(seed).unwrap_or_else(rand::random);
+    let frequencies = option_frequencies(&library, template, count, base_seed)?;
+
+    match format {
+        OutputFormat::Text => {
+            for (index, (group, options)) in frequencies.iter().enumerate() {
+                if index > 0 {
+                    println!();
+                }
+                println!("{}", group);
+                print!("{}", histogram(options));
+            }
+        }
+        OutputFormat::Json => {
+            let output = AnalyzeOutput {
+                template: template.name.clone(),
+                count,
+                seed: base_seed,
+                groups: frequencies
+                    .into_iter()
+                    .map(|(group, options)| (group, options.into_iter().collect()))
+                    .collect(),
+            };
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        }
+    }
+    Ok(())
+}
+
+/// How often each option was chosen over `count` renders with seeds
+/// `base_seed + i`, per group.
+///
+/// Groups are in library order, followed by any that aren't in the
+/// library. A group's options are in its own order, including those never
+/// chosen, with unknown picks after them.
+fn option_frequencies(
+    library: &Library,
+    template: &PromptTemplate,
+    count: usize,
+    base_seed: u64,
+) -> Result<Vec<(String, OptionCounts)>, CliError> {
+    let slots = HashMap::new();
+    let mut counts: HashMap<String, HashMap<String, usize>> = HashMap::new();
+    let mut group_order: Vec<String> = Vec::new();
+
+    for i in 0..count {
+        let result = render_once(library, template, Some(base_seed.wrapping_add(i as u64)), &slots)?;
+        for chosen in result.chosen_options.into_iter().filter(|c| !c.used_fallback) {
+            if !counts.contains_key(&chosen.group_name) {
+                group_order.push(chosen.group_name.clone());
+            }
+            *counts
+                .entry(chosen.group_name)
+                .or_default()
+                .entry(chosen.option_text)
+                .or_default() += 1;
+        }
+    }
+
+    // Library groups first, in library order
+    group_order.sort_by_key(|name| {
+        library.groups.iter().position(|g| &g.name == name).unwrap_or(usize::MAX)
+    });
+
+    Ok(group_order
+        .into_iter()
+        .map(|name| {
+            let mut chosen = counts.remove(&name).unwrap_or_default();
+            let mut options: OptionCounts = Vec::new();
+            if let Some(group) = library.find_group(&name) {
+                for option in &group.options {
+                    if let Some(times) = chosen.remove(option) {
+                        options.push((option.clone(), times));
+                    } else if !options.iter().any(|(o, _)| o == option) {
+                        options.push((option.clone(), 0));
+                    }
+                }
+            }
+            let mut rest: OptionCounts = chosen.into_iter().collect();
+            rest.sort();
+            options.extend(rest);
+            (name, options)
+        })
+        .collect())
+}
+
+/// One line per option: the option, its count and a bar scaled to the
+/// most frequent one.
+fn histogram(options: &[(String, usize)]) -> String {
+    let max = options.iter().map(|(_, times)| *times).max().unwrap_or(0);
+    let name_width = options.iter().map(|(option, _)| option.chars().count()).max().unwrap_or(0);
+    let count_width = max.to_string().len();
+
+    let mut out = String::new();
+    for (option, times) in options {
+        let bar = (times * HISTOGRAM_WIDTH).checked_div(max).unwrap_or(0);
+        let line = format!(
+            "  {:<name_width$}  {:>count_width$}  {}",
+            option,
+            times,
+            "#".repeat(bar)
+        );
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TWO_TEMPLATE_LIB: &str = r#"
+name: Export Test
+groups:
+  - name: Hair
+    options:
+      - red hair
+      - blue hair
+templates:
+  - name: Portrait
+    source: "@Hair, portrait"
+  - name: Plain
+    source: "a plain prompt"
+"#;
+
+    #[test]
+    fn test_format_diagnostic_with_suggestion() {
+        let library = parse_pack(TWO_TEMPLATE_LIB).unwrap();
+        let source = "@Hiar, portrait";
+        let errors = library.analyze_template(source).errors;
+
+        let expected = [
+            "error: unknown group 'Hiar'",
+            " --> 1:1",
+            "  |",
+            "1 | @Hiar, portrait",
+            "  | ^^^^^",
+            "  = help: did you mean 'Hair'?",
+        ];
+        assert_eq!(format_diagnostic(source, &errors[0]), expected.join("\n"));
+    }
+
+    #[test]
+    fn test_format_diagnostic_on_later_line() {
+        let source = "first line\nsecond {red|blue";
+        let error = parse_template(source).unwrap_err().diagnostics().remove(0);
+
+        let expected = [
+            "error: unexpected end of input",
+            " --> 2:17",
+            "  |",
+            "2 | second {red|blue",
+            "  |                 ^",
+        ];
+        assert_eq!(format_diagnostic(source, &error), expected.join("\n"));
+    }
+
+    #[test]
+    fn test_export_jsonl() {
+        let library = parse_pack(TWO_TEMPLATE_LIB).unwrap();
+        let mut buf = Vec::new();
+        write_export(&library, 2, 7, &ExportFormat::Jsonl, &mut buf).unwrap();
+
+        let output = String::from_utf8(buf).unwrap();
+        let records: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0]["prompt"], "Portrait");
+        assert_eq!(records[0]["seed"], 7);
+        assert_eq!(records[1]["seed"], 8);
+        assert!(records[0]["text"].as_str().unwrap().ends_with("hair, portrait"));
+        assert_eq!(records[2]["prompt"], "Plain");
+        assert_eq!(records[2]["seed"], 7);
+        assert_eq!(records[3]["text"], "a plain prompt");
+
+        // Same seed renders the same text as the render command would
+        let expected = render_once(&library, &library.templates[0], Some(7), &HashMap::new()).unwrap();
+        assert_eq!(records[0]["text"], expected.text);
+    }
+
+    const TAGGED_LIB: &str = r#"
+name: Tagged
+templates:
+  - name: Portrait
+    source: "a portrait"
+    tags: [People, wip]
+  - name: Landscape
+    source: "a landscape"
+    tags: [nature]
+  - name: Untagged
+    source: "nothing"
+"#;
+
+    fn filtered_names(library: &Library, tags: &[&str]) -> Vec<String> {
+        let tags: Vec<String> = tags.iter().map(|t| t.to_string()).collect();
+        filter_templates(library, &tags).iter().map(|t| t.name.clone()).collect()
+    }
+
+    #[test]
+    fn test_filter_templates_by_tag() {
+        let library = parse_pack(TAGGED_LIB).unwrap();
+
+        assert_eq!(filtered_names(&library, &[]), vec!["Portrait", "Landscape", "Untagged"]);
+        assert_eq!(filtered_names(&library, &["people"]), vec!["Portrait"]);
+        assert_eq!(filtered_names(&library, &["WIP", "nature"]), vec!["Portrait", "Landscape"]);
+        assert!(filtered_names(&library, &["missing"]).is_empty());
+    }
+
+    #[test]
+    fn test_export_text_groups_by_template() {
+        let library = parse_pack(TWO_TEMPLATE_LIB).unwrap();
+        let mut buf = Vec::new();
+        write_export(&library, 1, 0, &ExportFormat::Text, &mut buf).unwrap();
+
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.starts_with("# Portrait\n"));
+        assert!(output.ends_with("\n\n# Plain\na plain prompt\n"));
+    }
+
+    #[test]
+    fn test_parse_option_lines_skips_blank_lines() {
+        let options = parse_option_lines("  red hair \n\n black hair\r\n");
+        assert_eq!(options, vec!["red hair", "black hair"]);
+    }
+
+    #[test]
+    fn test_parse_option_lines_multiline_blocks() {
+        let text = "short\n---\nfirst line\n  indented\n---\nafter\n---\nunclosed\n";
+        let options = parse_option_lines(text);
+        assert_eq!(options, vec!["short", "first line\n  indented", "after", "unclosed"]);
+    }
+
+    #[test]
+    fn test_histogram_scales_to_most_frequent() {
+        let options = vec![
+            ("red".to_string(), 300),
+            ("blue hair".to_string(), 75),
+            ("green".to_string(), 0),
+        ];
+        let bar = |n: usize| "#".repeat(n);
+        assert_eq!(
+            histogram(&options),
+            format!(
+                "  red        300  {}\n  blue hair   75  {}\n  green        0\n",
+                bar(40),
+                bar(10)
+            )
+        );
+    }
+
+    #[test]
+    fn test_wildcard_path_stays_in_out_dir() {
+        let out = Path::new("out");
+        assert_eq!(wildcard_path(out, "Hair"), Some(PathBuf::from("out/Hair.txt")));
+        assert_eq!(
+            wildcard_path(out, "colors/warm"),
+            Some(PathBuf::from("out/colors/warm.txt"))
+        );
+        assert_eq!(wildcard_path(out, "Style v1.5"), Some(PathBuf::from("out/Style v1.5.txt")));
+        assert_eq!(wildcard_path(out, "../Hair"), None);
+        assert_eq!(wildcard_path(out, "/etc/Hair"), None);
+    }
+
+    #[test]
+    fn test_library_file_stem() {
+        let mut used = HashSet::new();
+        let stem = |name: &str, used: &mut HashSet<String>| {
+            library_file_stem(&Library::with_id("lib-id", name), used)
+        };
+
+        assert_eq!(stem("My Library: v2", &mut used), "my-library-v2");
+        assert_eq!(stem("my library v2", &mut used), "my-library-v2-2");
+        assert_eq!(stem("!!!", &mut used), "lib-id");
+    }
+}
@@ -0,0 +1,594 @@
+//! Integration tests that run the `promptgen` binary.
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+use promptgen_core::{load_pack, parse_bundle, serialize_pack};
+use tempfile::TempDir;
+
+const UNUSED_GROUP_LIB: &str = r#"
+name: Strict Test
+groups:
+  - name: Hair
+    options:
+      - red hair
+  - name: Hat
+    options:
+      - top hat
+templates:
+  - name: Portrait
+    source: "@Hair portrait"
+"#;
+
+fn write_library(yaml: &str) -> (TempDir, String) {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("lib.yml");
+    fs::write(&path, yaml).unwrap();
+    (dir, path.to_string_lossy().into_owned())
+}
+
+fn promptgen(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_promptgen"))
+        .args(args)
+        .output()
+        .expect("failed to run promptgen")
+}
+
+#[test]
+fn parse_ignores_warnings_without_strict() {
+    let (_dir, lib) = write_library(UNUSED_GROUP_LIB);
+
+    let output = promptgen(&["parse", "-l", &lib, "-t", "Portrait"]);
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn strict_parse_fails_on_unused_group() {
+    let (_dir, lib) = write_library(UNUSED_GROUP_LIB);
+
+    let output = promptgen(&["--strict", "parse", "-l", &lib, "-t", "Portrait"]);
+    assert_eq!(output.status.code(), Some(7));
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("warning: group 'Hat' is not used by any template"));
+    assert!(stderr.contains("1 warning(s) treated as errors"));
+}
+
+#[test]
+fn strict_flag_is_accepted_after_subcommand() {
+    let (_dir, lib) = write_library(UNUSED_GROUP_LIB);
+
+    let output = promptgen(&["parse", "-l", &lib, "-t", "Portrait", "--strict"]);
+    assert_eq!(output.status.code(), Some(7));
+}
+
+#[test]
+fn parse_inline_does_not_read_library_without_strict() {
+    let output = promptgen(&["parse", "-l", "missing.yml", "-i", "{a|b}"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let output = promptgen(&["--strict", "parse", "-l", "missing.yml", "-i", "{a|b}"]);
+    assert!(!output.status.success());
+}
+
+const DEFAULT_SEED_LIB: &str = r#"
+name: Seeded
+default_seed: 42
+groups:
+  - name: Color
+    options: [red, orange, yellow, green, blue, indigo, violet]
+templates:
+  - name: Colors
+    source: "@Color @Color @Color @Color"
+"#;
+
+fn promptgen_with_stdin(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_promptgen"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run promptgen");
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn run_ok(args: &[&str]) -> String {
+    let output = promptgen(args);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn render_uses_library_default_seed() {
+    let (_dir, lib) = write_library(DEFAULT_SEED_LIB);
+
+    let default = run_ok(&["render", "-l", &lib, "-t", "Colors"]);
+    let seed_42 = run_ok(&["render", "-l", &lib, "-t", "Colors", "-s", "42"]);
+    let seed_7 = run_ok(&["render", "-l", &lib, "-t", "Colors", "-s", "7"]);
+
+    assert_eq!(default, seed_42);
+    // An explicit seed overrides the default
+    assert_ne!(default, seed_7);
+}
+
+const DEFAULT_SLOTS_LIB: &str = r#"
+name: Slots
+templates:
+  - name: Scene
+    source: "{{ Subject }} in {{ Place }}"
+    default_slots:
+      Subject: a cat
+      Place: a forest
+"#;
+
+#[test]
+fn render_applies_default_slots_under_user_slots() {
+    let (_dir, lib) = write_library(DEFAULT_SLOTS_LIB);
+
+    let defaults = run_ok(&["render", "-l", &lib, "-t", "Scene"]);
+    assert_eq!(defaults, "a cat in a forest\n");
+
+    let overridden = run_ok(&[
+        "render", "-l", &lib, "-t", "Scene", "--slots", r#"{"Place": "the rain"}"#,
+    ]);
+    assert_eq!(overridden, "a cat in the rain\n");
+}
+
+#[test]
+fn list_templates_filters_by_tag_in_json() {
+    let (_dir, lib) = write_library(
+        r#"
+name: Tagged
+templates:
+  - name: Portrait
+    source: "a portrait"
+    tags: [people]
+  - name: Untagged
+    source: "nothing"
+"#,
+    );
+
+    let output = run_ok(&["list", "templates", "-l", &lib, "--tag", "People", "-f", "json"]);
+    let templates: serde_json::Value = serde_json::from_str(&output).unwrap();
+
+    assert_eq!(templates.as_array().unwrap().len(), 1);
+    assert_eq!(templates[0]["name"], "Portrait");
+    assert_eq!(templates[0]["tags"], serde_json::json!(["people"]));
+}
+
+#[test]
+fn render_inline_without_library() {
+    let text = run_ok(&["render", "-i", "a {red|green|blue} ball", "-s", "3"]);
+    let text = text.trim_end();
+    assert!(
+        ["a red ball", "a green ball", "a blue ball"].contains(&text),
+        "unexpected output: {text}"
+    );
+}
+
+#[test]
+fn render_inline_reference_without_library_fails() {
+    let output = promptgen(&["render", "-i", "@Hair portrait"]);
+    assert_eq!(output.status.code(), Some(5));
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("template references @Hair but no --lib was given"));
+}
+
+#[test]
+fn render_inline_reference_without_library_fails_for_every_seed() {
+    for seed in ["1", "2", "3", "4"] {
+        let output = promptgen(&["render", "-i", "{a|@Hair}", "--seed", seed]);
+        assert_eq!(output.status.code(), Some(5));
+    }
+}
+
+const MERGE_A: &str = r#"
+name: A
+groups:
+  - name: Hair
+    options: [red hair]
+templates:
+  - name: Portrait
+    source: "@Hair"
+"#;
+
+const MERGE_B: &str = r#"
+name: B
+groups:
+  - name: Hair
+    options: [black hair]
+  - name: Hat
+    options: [top hat]
+templates:
+  - name: Landscape
+    source: "a valley"
+"#;
+
+#[test]
+fn merge_combines_groups_and_templates() {
+    let (_a_dir, a) = write_library(MERGE_A);
+    let (_b_dir, b) = write_library(MERGE_B);
+    let out_dir = TempDir::new().unwrap();
+    let out = out_dir.path().join("combined.yml");
+    let out = out.to_str().unwrap();
+
+    run_ok(&["merge", "--on-conflict", "last", "-o", out, &a, &b]);
+
+    let groups = run_ok(&["list", "groups", "-l", out, "-f", "json"]);
+    let groups: serde_json::Value = serde_json::from_str(&groups).unwrap();
+    assert_eq!(groups[0]["name"], "Hair");
+    assert_eq!(groups[1]["name"], "Hat");
+
+    let templates = run_ok(&["list", "templates", "-l", out]);
+    assert!(templates.contains("Portrait"));
+    assert!(templates.contains("Landscape"));
+
+    assert_eq!(run_ok(&["render", "-l", out, "-i", "@Hair"]).trim_end(), "black hair");
+}
+
+#[test]
+fn merge_fails_on_conflict_by_default() {
+    let (_a_dir, a) = write_library(MERGE_A);
+    let (_b_dir, b) = write_library(MERGE_B);
+    let out_dir = TempDir::new().unwrap();
+    let out = out_dir.path().join("combined.yml");
+
+    let output = promptgen(&["merge", "-o", out.to_str().unwrap(), &a, &b]);
+    assert_eq!(output.status.code(), Some(5));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("group 'Hair'"));
+    assert!(!out.exists());
+}
+
+const DUPLICATE_OPTIONS_LIB: &str = r#"
+name: Duplicates
+groups:
+  - name: Hair
+    options: [red hair, black hair, red hair]
+  - name: Eyes
+    options: [blue eyes]
+"#;
+
+#[test]
+fn clean_removes_duplicate_options() {
+    let (_dir, lib) = write_library(DUPLICATE_OPTIONS_LIB);
+
+    let stdout = run_ok(&["clean", "-l", &lib]);
+    assert!(stdout.contains("Hair: 1 duplicate option(s)"));
+    assert!(stdout.contains("Removed 1 duplicate option(s)"));
+
+    let groups = run_ok(&["list", "groups", "-l", &lib, "-f", "json"]);
+    let groups: serde_json::Value = serde_json::from_str(&groups).unwrap();
+    assert_eq!(groups[0]["option_count"], 2);
+    assert_eq!(groups[1]["option_count"], 1);
+    assert_eq!(fs::read_to_string(&lib).unwrap().matches("red hair").count(), 1);
+
+    assert!(run_ok(&["clean", "-l", &lib]).contains("No duplicate options"));
+}
+
+#[test]
+fn clean_dry_run_leaves_file_unchanged() {
+    let (_dir, lib) = write_library(DUPLICATE_OPTIONS_LIB);
+
+    let stdout = run_ok(&["clean", "-l", &lib, "--dry-run"]);
+    assert!(stdout.contains("Would remove 1 duplicate option(s)"));
+    assert_eq!(fs::read_to_string(&lib).unwrap(), DUPLICATE_OPTIONS_LIB);
+}
+
+#[test]
+fn shuffle_is_deterministic_for_a_seed() {
+    let (_dir, lib) = write_library(DUPLICATE_OPTIONS_LIB);
+
+    let first = run_ok(&["shuffle", "-l", &lib, "-g", "Hair", "-s", "7"]);
+    assert_eq!(first, run_ok(&["shuffle", "-l", &lib, "-g", "Hair", "-s", "7"]));
+    assert_eq!(first.lines().count(), 3);
+
+    let json = run_ok(&["shuffle", "-l", &lib, "-g", "Hair", "-s", "7", "-f", "json"]);
+    let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(json["seed"], 7);
+    let options: Vec<&str> = json["options"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|o| o.as_str().unwrap())
+        .collect();
+    assert_eq!(options, first.lines().collect::<Vec<_>>());
+}
+
+#[test]
+fn shuffle_unknown_group_fails() {
+    let (_dir, lib) = write_library(DUPLICATE_OPTIONS_LIB);
+
+    let output = promptgen(&["shuffle", "-l", &lib, "-g", "Hat"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().contains("Group 'Hat' not found"));
+}
+
+#[test]
+fn render_reads_inline_template_from_stdin() {
+    let (_dir, lib) = write_library(MERGE_A);
+
+    let output = promptgen_with_stdin(&["render", "-l", &lib, "-i", "-"], "@Hair, {{ Scene }}\n");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "red hair, {{ Scene }}\n");
+}
+
+#[test]
+fn parse_reads_inline_template_from_stdin() {
+    let (_dir, lib) = write_library(MERGE_A);
+
+    let output = promptgen_with_stdin(&["parse", "-l", &lib, "-i", "-"], "@Hair {red|blue");
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().contains("1 | @Hair {red|blue"));
+}
+
+#[test]
+fn render_rejects_empty_stdin() {
+    let output = promptgen_with_stdin(&["render", "-i", "-"], "");
+    assert_eq!(output.status.code(), Some(5));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("no template on standard input"));
+}
+
+#[test]
+fn enumerate_lists_every_combination() {
+    let (_dir, lib) = write_library(MERGE_B);
+
+    let stdout = run_ok(&["enumerate", "-l", &lib, "-i", "@Hair {with|without} @Hat"]);
+    assert_eq!(stdout, "black hair with top hat\nblack hair without top hat\n");
+
+    let output = promptgen(&["enumerate", "-l", &lib, "-i", "{a|b|c}", "--limit", "2", "-f", "json"]);
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["prompts"], serde_json::json!(["a", "b"]));
+    assert_eq!(json["truncated"], true);
+}
+
+#[test]
+fn render_show_choices_appends_summary() {
+    let (_dir, lib) = write_library(MERGE_B);
+
+    let stdout = run_ok(&["render", "-l", &lib, "-i", "@Hair, @{Hat | upper}", "--show-choices"]);
+    assert_eq!(stdout, "black hair, TOP HAT\n# chosen: Hair=black hair, Hat=TOP HAT\n");
+
+    let plain = run_ok(&["render", "-l", &lib, "-i", "@Hair"]);
+    assert_eq!(plain, "black hair\n");
+}
+
+fn import_options(lib: &str, group: &str, text: &str, replace: bool) -> String {
+    let dir = TempDir::new().unwrap();
+    let from = dir.path().join("options.txt");
+    fs::write(&from, text).unwrap();
+    let mut args = vec!["import-options", "-l", lib, "-g", group, "--from", from.to_str().unwrap()];
+    if replace {
+        args.push("--replace");
+    }
+    run_ok(&args)
+}
+
+#[test]
+fn import_options_appends_and_creates_groups() {
+    let (_dir, lib) = write_library(MERGE_B);
+
+    let stdout = import_options(&lib, "Hair", "red hair\n\n  blonde hair  \n", false);
+    assert!(stdout.contains("Imported into 'Hair': 2 option(s)"));
+    import_options(&lib, "Mood", "---\ncalm,\nquiet\n---\nangry\n", false);
+
+    let stdout = run_ok(&["enumerate", "-l", &lib, "-i", "@Hair"]);
+    assert_eq!(stdout, "black hair\nred hair\nblonde hair\n");
+    let stdout = run_ok(&["enumerate", "-l", &lib, "-i", "@Mood"]);
+    assert_eq!(stdout, "calm,\nquiet\nangry\n");
+}
+
+#[test]
+fn import_options_replace_overwrites_group() {
+    let (_dir, lib) = write_library(MERGE_B);
+
+    let stdout = import_options(&lib, "Hair", "grey hair\n", true);
+    assert!(stdout.contains("Replaced options of 'Hair': 1 option(s)"));
+    assert_eq!(run_ok(&["enumerate", "-l", &lib, "-i", "@Hair"]), "grey hair\n");
+    assert_eq!(run_ok(&["enumerate", "-l", &lib, "-i", "@Hat"]), "top hat\n");
+}
+
+#[test]
+fn parse_prints_sexpr() {
+    let stdout = run_ok(&["parse", "-i", "@Hair, {a|b}", "-f", "sexpr"]);
+    assert_eq!(stdout, "(template (lib-ref Hair) (text \", \") (inline-options a b))\n");
+}
+
+#[test]
+fn pack_and_unpack_round_trip() {
+    let (_a_dir, a) = write_library(MERGE_A);
+    let (_b_dir, b) = write_library(MERGE_B);
+    let out_dir = TempDir::new().unwrap();
+    let bundle = out_dir.path().join("bundle.pgpack");
+    let bundle = bundle.to_str().unwrap();
+    let libs_dir = out_dir.path().join("libs");
+
+    let stdout = run_ok(&["pack", "--out", bundle, &a, &b]);
+    assert!(stdout.contains("Packed 2 library(s)"));
+    run_ok(&["unpack", bundle, "--out-dir", libs_dir.to_str().unwrap()]);
+
+    // Each unpacked file holds the library as it was bundled
+    let bundled = parse_bundle(&fs::read_to_string(bundle).unwrap()).unwrap();
+    for (library, name) in bundled.iter().zip(["a.yml", "b.yml"]) {
+        let unpacked = load_pack(&libs_dir.join(name)).unwrap();
+        assert_eq!(serialize_pack(&unpacked).unwrap(), serialize_pack(library).unwrap());
+    }
+
+    // which is the library that was packed, with its id kept
+    let original = load_pack(Path::new(&b)).unwrap();
+    assert_eq!(bundled[1].id, original.id);
+    assert_eq!(bundled[1].groups.len(), 2);
+    assert_eq!(bundled[1].groups[1].options, vec!["top hat"]);
+    assert_eq!(bundled[1].templates[0].name, "Landscape");
+}
+
+#[test]
+fn unpack_keeps_files_in_out_dir() {
+    let (_dir, lib) = write_library("id: ../escaped\nname: \"!!!\"\ngroups: []\n");
+    let (_other_dir, other) = write_library("id: \"..\"\nname: \"/\"\ngroups: []\n");
+    let out_dir = TempDir::new().unwrap();
+    let bundle = out_dir.path().join("bundle.pgpack");
+    let bundle = bundle.to_str().unwrap();
+    let libs_dir = out_dir.path().join("libs");
+
+    run_ok(&["pack", "--out", bundle, &lib, &other]);
+    let stdout = run_ok(&["unpack", bundle, "--out-dir", libs_dir.to_str().unwrap()]);
+
+    // The id is slugged like the name, and falls back to a fixed stem
+    assert!(libs_dir.join("escaped.yml").exists());
+    assert!(libs_dir.join("library.yml").exists());
+    assert!(!out_dir.path().join("escaped.yml").exists());
+    assert_eq!(stdout.lines().count(), 2);
+}
+
+#[test]
+fn render_suggests_close_names() {
+    let (_dir, lib) = write_library(MERGE_A);
+
+    let output = promptgen(&["render", "-l", &lib, "-i", "@Hiar"]);
+    assert_eq!(output.status.code(), Some(4));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("group not found: Hiar; did you mean 'Hair'?"), "{stderr}");
+
+    let output = promptgen(&["render", "-l", &lib, "-t", "Portrat"]);
+    assert_eq!(output.status.code(), Some(5));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("did you mean 'Portrait'?"), "{stderr}");
+}
+
+#[test]
+fn render_seed_label_is_deterministic() {
+    let (_dir, lib) = write_library(DEFAULT_SEED_LIB);
+    let args = ["render", "-l", &lib, "-t", "Colors", "--seed-label", "sunset"];
+
+    let first = run_ok(&args);
+    assert_eq!(run_ok(&args), first);
+
+    let output = promptgen(&["render", "-l", &lib, "-t", "Colors", "-s", "1", "--seed-label", "x"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().contains("cannot be used with"));
+}
+
+#[test]
+fn graph_prints_option_references() {
+    let yaml = r#"
+name: Graph
+groups:
+  - name: A
+    options: ["@B hair"]
+  - name: B
+    options: [red, "@A"]
+"#;
+    let (_dir, lib) = write_library(yaml);
+
+    let dot = run_ok(&["graph", "-l", &lib]);
+    assert!(dot.starts_with("digraph \"Graph\" {\n"), "{dot}");
+    assert!(dot.contains("  \"A\" -> \"B\" [color=red];\n"), "{dot}");
+    assert!(dot.contains("  \"B\" -> \"A\" [color=red];\n"), "{dot}");
+}
+
+#[test]
+fn export_wildcards_writes_one_file_per_group() {
+    let yaml = r#"
+name: Wild
+groups:
+  - name: Hair
+    options: [red hair, "@Color hair"]
+  - name: Color
+    options: [red, blue]
+"#;
+    let (_dir, lib) = write_library(yaml);
+    let out_dir = TempDir::new().unwrap();
+    let wildcards = out_dir.path().join("wildcards");
+
+    run_ok(&["export-wildcards", "-l", &lib, "--out-dir", wildcards.to_str().unwrap()]);
+    let hair = fs::read_to_string(wildcards.join("Hair.txt")).unwrap();
+    assert_eq!(hair, "red hair\n__Color__ hair\n");
+    let color = fs::read_to_string(wildcards.join("Color.txt")).unwrap();
+    assert_eq!(color, "red\nblue\n");
+}
+
+#[test]
+fn analyze_shows_skew_from_repeated_options() {
+    // Listing an option three times makes it three times as likely
+    let yaml = r#"
+name: Weighted
+groups:
+  - name: Hair
+    options: [red hair, red hair, red hair, black hair]
+  - name: Unused
+    options: [never]
+templates:
+  - name: Portrait
+    source: "@Hair"
+"#;
+    let (_dir, lib) = write_library(yaml);
+
+    let stdout = run_ok(&["analyze", "-l", &lib, "-t", "Portrait", "-c", "1000", "-s", "1", "-f", "json"]);
+    let output: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    let red = output["groups"]["Hair"]["red hair"].as_u64().unwrap();
+    let black = output["groups"]["Hair"]["black hair"].as_u64().unwrap();
+    assert_eq!(red + black, 1000);
+    assert!(red > 2 * black, "red {red}, black {black}");
+    assert!(output["groups"].get("Unused").is_none());
+
+    let text = run_ok(&["analyze", "-l", &lib, "-t", "Portrait", "-c", "1000", "-s", "1"]);
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines[0], "Hair");
+    assert!(lines[1].starts_with(&format!("  red hair    {red}  {}", "#".repeat(40))), "{text}");
+    assert!(lines[2].starts_with(&format!("  black hair  {black}  #")), "{text}");
+}
+
+#[test]
+fn render_writes_output_file() {
+    let (dir, lib) = write_library(MERGE_A);
+    let out = dir.path().join("nested").join("dir").join("prompt.json");
+
+    let stdout = run_ok(&["render", "-l", &lib, "-i", "@Hair", "-f", "json", "--output", out.to_str().unwrap()]);
+    assert!(stdout.is_empty());
+
+    let written: serde_json::Value = serde_json::from_str(&fs::read_to_string(&out).unwrap()).unwrap();
+    assert_eq!(written["prompt"], "red hair");
+
+    let export = dir.path().join("exports").join("all.txt");
+    run_ok(&["export", "-l", &lib, "--output", export.to_str().unwrap()]);
+    assert_eq!(fs::read_to_string(&export).unwrap(), "# Portrait\nred hair\n");
+}
+
+#[test]
+fn complete_prints_group_completions_at_cursor() {
+    let yaml = r#"
+name: Complete
+groups:
+  - name: Hair
+    options: [red hair]
+  - name: Hat
+    options: [top hat]
+  - name: Eye Color
+    options: [blue]
+"#;
+    let (_dir, lib) = write_library(yaml);
+
+    let stdout = run_ok(&["complete", "-l", &lib, "-i", "@Ha and more", "--cursor", "3"]);
+    let items: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(
+        items,
+        serde_json::json!([
+            {"label": "Hair", "kind": "group", "insert_text": "@Hair", "replace": {"start": 0, "end": 3}},
+            {"label": "Hat", "kind": "group", "insert_text": "@Hat", "replace": {"start": 0, "end": 3}},
+        ])
+    );
+
+    let stdout = run_ok(&["complete", "-l", &lib, "-i", "@\"eye"]);
+    let items: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(items[0]["insert_text"], "@\"Eye Color\"");
+
+    let help = run_ok(&["--help"]);
+    assert!(!help.contains("complete"), "{help}");
+}
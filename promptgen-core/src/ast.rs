@@ -1,8 +1,16 @@
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
 use crate::span::Span;
 
 /// A parsed template containing a sequence of nodes.
+///
+/// With the `serde` feature, each node serializes as
+/// `{ "kind": ..., "value": ..., "span": { "start", "end" } }`.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct Template {
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_spanned_nodes"))]
     pub nodes: Vec<Spanned<Node>>,
 }
 
@@ -15,12 +23,21 @@ pub type Spanned<T> = (T, Span);
 /// - `@Hair` -> library: None, group: "Hair"
 /// - `@"Eye Color"` -> library: None, group: "Eye Color"
 /// - `@"MyLib:Hair"` -> library: Some("MyLib"), group: "Hair"
+/// - `@{Hair | upper}` -> library: None, group: "Hair", transform: Some(Upper)
+/// - `@Hair ?? "brown hair"` -> library: None, group: "Hair", fallback: Some("brown hair")
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct LibraryRef {
     /// Optional library name qualifier. None means search all libraries.
     pub library: Option<String>,
     /// The group name to reference.
     pub group: String,
+    /// Case transform applied to the resolved option text.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub transform: Option<CaseKind>,
+    /// Text used instead when the group is missing or has no options.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub fallback: Option<String>,
 }
 
 impl LibraryRef {
@@ -29,6 +46,8 @@ impl LibraryRef {
         Self {
             library: None,
             group: group.into(),
+            transform: None,
+            fallback: None,
         }
     }
 
@@ -37,21 +56,139 @@ impl LibraryRef {
         Self {
             library: Some(library.into()),
             group: group.into(),
+            transform: None,
+            fallback: None,
+        }
+    }
+
+    /// Apply a case transform to the resolved text of this reference.
+    pub fn with_transform(mut self, transform: CaseKind) -> Self {
+        self.transform = Some(transform);
+        self
+    }
+
+    /// Use `fallback` when the referenced group is missing or empty.
+    pub fn with_fallback(mut self, fallback: impl Into<String>) -> Self {
+        self.fallback = Some(fallback.into());
+        self
+    }
+}
+
+/// A slot to be filled with a user-provided value.
+///
+/// Examples:
+/// - `{{ Scene }}` -> name: "Scene", required: false
+/// - `{{ Scene! }}` -> name: "Scene", required: true
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Slot {
+    pub name: String,
+    /// Rendering fails if a required slot has no value.
+    pub required: bool,
+}
+
+impl Slot {
+    /// Create an optional slot.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            required: false,
+        }
+    }
+
+    /// Create a slot that must be given a value.
+    pub fn required(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            required: true,
+        }
+    }
+}
+
+/// Text that only appears some of the time.
+///
+/// Examples:
+/// - `{rainy?}` -> text: "rainy", chance: 50
+/// - `{rainy?75}` -> text: "rainy", chance: 75
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct MaybeOption {
+    pub text: String,
+    /// Percent chance, from 0 to 100, that the text is rendered.
+    pub chance: u8,
+}
+
+impl MaybeOption {
+    /// The chance used when `{text?}` gives no number.
+    pub const DEFAULT_CHANCE: u8 = 50;
+}
+
+/// A case transformation applied to rendered text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum CaseKind {
+    /// `upper` – all characters uppercased.
+    Upper,
+    /// `lower` – all characters lowercased.
+    Lower,
+    /// `title` – first character of each word uppercased, the rest lowercased.
+    Title,
+}
+
+impl CaseKind {
+    /// The keyword used for this transform in template syntax.
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            CaseKind::Upper => "upper",
+            CaseKind::Lower => "lower",
+            CaseKind::Title => "title",
+        }
+    }
+
+    /// Apply this transform to `text`.
+    pub fn apply(&self, text: &str) -> String {
+        match self {
+            CaseKind::Upper => text.to_uppercase(),
+            CaseKind::Lower => text.to_lowercase(),
+            CaseKind::Title => {
+                let mut output = String::with_capacity(text.len());
+                let mut at_word_start = true;
+                for c in text.chars() {
+                    if c.is_whitespace() {
+                        output.push(c);
+                        at_word_start = true;
+                    } else if at_word_start {
+                        output.extend(c.to_uppercase());
+                        at_word_start = false;
+                    } else {
+                        output.extend(c.to_lowercase());
+                    }
+                }
+                output
+            }
         }
     }
 }
 
 /// An item within inline options `{a|b|c}`.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind", content = "value"))]
 pub enum OptionItem {
     /// Plain text option.
     Text(String),
     /// Option containing nested grammar (e.g., `{@Hair|bald}` where `@Hair` is nested).
-    Nested(Vec<Spanned<Node>>),
+    Nested(
+        #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_spanned_nodes"))]
+        Vec<Spanned<Node>>,
+    ),
 }
 
 /// Template node types.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind", content = "value"))]
 pub enum Node {
     /// Plain literal text.
     Text(String),
@@ -59,12 +196,35 @@ pub enum Node {
     /// `{a|b|c}` – inline options, pick one randomly.
     InlineOptions(Vec<OptionItem>),
 
+    /// `{text?}` or `{text?75}` – the text, or nothing.
+    MaybeOption(MaybeOption),
+
     /// `@Name` or `@"Name"` or `@"Lib:Name"` – reference to a library group.
+    /// `@{Name | upper}` additionally transforms the case of the result.
     LibraryRef(LibraryRef),
 
-    /// `{{ name }}` – user-provided slot value.
-    Slot(String),
+    /// `{{ name }}` or `{{ name! }}` – user-provided slot value.
+    Slot(Slot),
+
+    /// `{{= name }}` – the value slot `name` resolved to, inserted as-is.
+    SlotRef(String),
 
     /// `# comment to end of line` – ignored in output.
     Comment(String),
 }
+
+/// Serialize spanned nodes as flat objects with the span alongside the node.
+#[cfg(feature = "serde")]
+fn serialize_spanned_nodes<S: serde::Serializer>(
+    nodes: &[Spanned<Node>],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct SpannedNode<'a> {
+        #[serde(flatten)]
+        node: &'a Node,
+        span: &'a Span,
+    }
+
+    serializer.collect_seq(nodes.iter().map(|(node, span)| SpannedNode { node, span }))
+}
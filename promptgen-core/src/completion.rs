@@ -0,0 +1,230 @@
+//! Completions for an editor at a cursor in template source.
+//!
+//! Two places are completed: a group reference being typed after `@`
+//! (also `@{` and `@"`), and a slot reference after `{{=`, which offers the
+//! slots the template itself declares. Names match by case-insensitive
+//! prefix. The cursor is a byte offset, and each item says which bytes of
+//! the source its text replaces.
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::ast::Node;
+use crate::library::Library;
+use crate::parser::parse_template_recover;
+use crate::span::Span;
+
+/// What a [`CompletionItem`] inserts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum CompletionKind {
+    /// A reference to a group of the library.
+    Group,
+    /// A reference to a slot declared in the template.
+    Slot,
+}
+
+/// One completion offered at the cursor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct CompletionItem {
+    /// The name to show in a completion list.
+    pub label: String,
+    pub kind: CompletionKind,
+    /// The text to put in place of `replace`, quoted where the name needs it.
+    pub insert_text: String,
+    /// The bytes of the source the completion replaces, ending at the cursor.
+    pub replace: Span,
+}
+
+impl Library {
+    /// The completions for `source` with the cursor at byte `cursor`, in
+    /// library order for groups and source order for slots.
+    ///
+    /// A group completion replaces the whole reference typed so far, from
+    /// the `@`, so `@"Eye` completes to `@"Eye Color"`. A slot completion
+    /// replaces only the name after `{{=`. Returns nothing when the cursor
+    /// is not in a reference.
+    pub fn completions_at(&self, source: &str, cursor: usize) -> Vec<CompletionItem> {
+        let mut cursor = cursor.min(source.len());
+        while !source.is_char_boundary(cursor) {
+            cursor -= 1;
+        }
+        let before = &source[..cursor];
+
+        if let Some(start) = slot_ref_start(before) {
+            let prefix = &before[start..];
+            return declared_slots(source)
+                .into_iter()
+                .filter(|name| starts_with_ignore_case(name, prefix))
+                .map(|name| CompletionItem {
+                    insert_text: name.clone(),
+                    label: name,
+                    kind: CompletionKind::Slot,
+                    replace: start..cursor,
+                })
+                .collect();
+        }
+
+        let Some(typed) = group_ref_typed(before) else {
+            return Vec::new();
+        };
+        let start = cursor - typed.len();
+        let braced = typed.starts_with("@{");
+        let prefix = typed[if braced { 2 } else { 1 }..].trim_start_matches('"');
+
+        self.groups
+            .iter()
+            .filter(|group| starts_with_ignore_case(&group.name, prefix))
+            .map(|group| {
+                let name = quote_if_needed(&group.name);
+                CompletionItem {
+                    label: group.name.clone(),
+                    kind: CompletionKind::Group,
+                    insert_text: if braced {
+                        format!("@{{{}", name)
+                    } else {
+                        format!("@{}", name)
+                    },
+                    replace: start..cursor,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Where the name of a `{{=` reference starts, if `before` ends inside one.
+fn slot_ref_start(before: &str) -> Option<usize> {
+    let open = before.rfind("{{=")?;
+    let rest = &before[open + 3..];
+    if rest.contains('}') {
+        return None;
+    }
+    Some(before.len() - rest.trim_start().len())
+}
+
+/// The reference typed so far, from its `@`, if `before` ends inside one.
+fn group_ref_typed(before: &str) -> Option<&str> {
+    // A quoted name runs to the closing quote, so it may hold spaces
+    if let Some(quote) = before.rfind('"') {
+        let open = before[..quote]
+            .strip_suffix("@{")
+            .or(before[..quote].strip_suffix('@'));
+        if let Some(open) = open
+            && !before[quote + 1..].contains('"')
+        {
+            return Some(&before[open.len()..]);
+        }
+    }
+
+    let name_len = before
+        .chars()
+        .rev()
+        .take_while(|&c| is_name_char(c))
+        .map(char::len_utf8)
+        .sum::<usize>();
+    let head = &before[..before.len() - name_len];
+    let open = head.strip_suffix("@{").or(head.strip_suffix('@'))?;
+    Some(&before[open.len()..])
+}
+
+/// Names of the slots declared in `source`, once each.
+fn declared_slots(source: &str) -> Vec<String> {
+    let (template, _errors) = parse_template_recover(source);
+    let mut names: Vec<String> = Vec::new();
+    for (node, _span) in &template.nodes {
+        if let Node::Slot(slot) = node
+            && !names.contains(&slot.name)
+        {
+            names.push(slot.name.clone());
+        }
+    }
+    names
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+/// `name`, in double quotes unless it can be written bare after `@`.
+fn quote_if_needed(name: &str) -> String {
+    let mut chars = name.chars();
+    let bare =
+        chars.next().is_some_and(|c| c.is_alphabetic() || c == '_') && chars.all(is_name_char);
+    if bare {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name)
+    }
+}
+
+fn starts_with_ignore_case(name: &str, prefix: &str) -> bool {
+    name.to_lowercase().starts_with(&prefix.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::PromptGroup;
+
+    fn library() -> Library {
+        let mut lib = Library::new("Test");
+        lib.groups
+            .push(PromptGroup::with_options("Hair", vec!["red hair"]));
+        lib.groups
+            .push(PromptGroup::with_options("Eye Color", vec!["blue"]));
+        lib.groups
+            .push(PromptGroup::with_options("hat", vec!["cap"]));
+        lib
+    }
+
+    fn labels(items: &[CompletionItem]) -> Vec<&str> {
+        items.iter().map(|item| item.label.as_str()).collect()
+    }
+
+    #[test]
+    fn test_completes_group_prefix() {
+        let items = library().completions_at("a @ha", 5);
+        assert_eq!(labels(&items), ["Hair", "hat"]);
+        assert_eq!(items[0].kind, CompletionKind::Group);
+        assert_eq!(items[0].insert_text, "@Hair");
+        assert_eq!(items[0].replace, 2..5);
+
+        // Everything right after `@`, and quoting where needed
+        let items = library().completions_at("@", 1);
+        assert_eq!(items[1].insert_text, r#"@"Eye Color""#);
+    }
+
+    #[test]
+    fn test_completes_quoted_and_braced_refs() {
+        let items = library().completions_at(r#"x @"eye c"#, 9);
+        assert_eq!(labels(&items), ["Eye Color"]);
+        assert_eq!(items[0].insert_text, r#"@"Eye Color""#);
+        assert_eq!(items[0].replace, 2..9);
+
+        let items = library().completions_at("@{Ha | upper}", 4);
+        assert_eq!(items[0].insert_text, "@{Hair");
+        assert_eq!(items[0].replace, 0..4);
+    }
+
+    #[test]
+    fn test_completes_declared_slots() {
+        let source = "{{ Hero }} meets {{ Villain! }} and {{= h";
+        let items = library().completions_at(source, source.len());
+        assert_eq!(labels(&items), ["Hero"]);
+        assert_eq!(items[0].kind, CompletionKind::Slot);
+        assert_eq!(items[0].replace, source.len() - 1..source.len());
+    }
+
+    #[test]
+    fn test_no_completions_outside_refs() {
+        assert!(library().completions_at("plain text", 5).is_empty());
+        assert!(
+            library()
+                .completions_at(r#"@"Eye Color" done"#, 17)
+                .is_empty()
+        );
+        assert!(library().completions_at("@Hair done", 10).is_empty());
+    }
+}
@@ -0,0 +1,141 @@
+//! Diagnostics produced when checking templates against a library.
+//!
+//! Unlike [`crate::ParseError`], diagnostics always carry a byte span into the
+//! checked source and, where possible, a suggested fix, so editors can point
+//! at the offending text.
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::ast::Template;
+use crate::span::Span;
+
+/// An error that prevents a template from rendering.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DiagnosticError {
+    /// Human-readable description of the problem.
+    pub message: String,
+    /// Location of the problem in the source.
+    pub span: Span,
+    /// What the author probably meant, if something close enough exists.
+    pub suggestion: Option<String>,
+}
+
+impl DiagnosticError {
+    /// Create an error without a suggestion.
+    pub fn new(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            message: message.into(),
+            span,
+            suggestion: None,
+        }
+    }
+}
+
+/// The category of a [`DiagnosticWarning`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum WarningKind {
+    /// A group that no template uses, directly or through other groups.
+    Unused,
+    /// Several groups share a name; references resolve to the first.
+    DuplicateName,
+    /// A group with an option that references the group itself, which
+    /// fails with a circular reference whenever that option is picked.
+    SelfReference,
+    /// A reference qualified with another library's name, such as
+    /// `@"Other:Hair"`, checked against a single library. The qualifier is
+    /// ignored and the group is looked up in this library.
+    IgnoredQualifier,
+    /// Inline options such as `{}` or `{|}` whose branches are all empty.
+    /// A single empty branch, as in `{a|}`, is fine.
+    EmptyInlineOptions,
+}
+
+/// A non-fatal issue worth surfacing to the author.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DiagnosticWarning {
+    pub kind: WarningKind,
+    /// Human-readable description of the issue.
+    pub message: String,
+    /// Location in the checked source, for warnings tied to a template.
+    pub span: Option<Span>,
+}
+
+/// The outcome of checking a template source against a library.
+#[derive(Debug, Clone)]
+pub struct ParseResult {
+    /// The parsed template, if the source was syntactically valid.
+    pub template: Option<Template>,
+    /// Problems that would make rendering fail.
+    pub errors: Vec<DiagnosticError>,
+    /// Issues in the template that don't stop it rendering.
+    pub warnings: Vec<DiagnosticWarning>,
+}
+
+impl ParseResult {
+    /// Whether the template parsed and all references resolved.
+    pub fn is_ok(&self) -> bool {
+        self.template.is_some() && self.errors.is_empty()
+    }
+}
+
+/// Find the candidate closest to `name` by edit distance.
+///
+/// Only candidates within a third of the name's length (rounded up, at most 3
+/// edits) are considered close enough to suggest.
+pub(crate) fn closest_match<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let max_distance = name.chars().count().div_ceil(3).clamp(1, 3);
+
+    candidates
+        .into_iter()
+        .map(|candidate| (levenshtein_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Case-sensitive Levenshtein distance between two strings, counted in chars.
+fn levenshtein_distance(a: &str, b: &str) -> usize {
+    let b_chars: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b_chars.len()).collect();
+    let mut curr = vec![0; b_chars.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        curr[0] = i + 1;
+        for (j, cb) in b_chars.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+
+    prev[b_chars.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levenshtein_distance() {
+        assert_eq!(levenshtein_distance("Hair", "Hair"), 0);
+        assert_eq!(levenshtein_distance("Hiar", "Hair"), 2);
+        assert_eq!(levenshtein_distance("Hai", "Hair"), 1);
+        assert_eq!(levenshtein_distance("", "Eyes"), 4);
+    }
+
+    #[test]
+    fn test_closest_match() {
+        let candidates = ["Hair", "Eyes", "Eye Color"];
+        assert_eq!(closest_match("Hiar", candidates), Some("Hair"));
+        assert_eq!(closest_match("Eye", candidates), Some("Eyes"));
+        assert_eq!(closest_match("Background", candidates), None);
+    }
+}
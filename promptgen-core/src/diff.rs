@@ -0,0 +1,323 @@
+//! Structural diffs between two versions of a library.
+//!
+//! Groups and templates are matched by name. A matched item whose contents
+//! differ is reported as modified; for groups the option-level changes are
+//! included so a change summary can be shown before saving.
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::library::{Library, PromptGroup, PromptTemplate};
+
+/// Differences between an old and a new version of a library.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct LibraryDiff {
+    /// True when the library's own fields, such as its name, description
+    /// or default seed, differ.
+    pub metadata_changed: bool,
+    /// Names of groups only present in the new library.
+    pub added_groups: Vec<String>,
+    /// Names of groups only present in the old library.
+    pub removed_groups: Vec<String>,
+    /// Groups present in both whose options, aliases or option notes differ.
+    pub modified_groups: Vec<GroupDiff>,
+    /// Names of templates only present in the new library.
+    pub added_templates: Vec<String>,
+    /// Names of templates only present in the old library.
+    pub removed_templates: Vec<String>,
+    /// Names of templates present in both whose source or metadata differ.
+    pub modified_templates: Vec<String>,
+}
+
+impl LibraryDiff {
+    /// Returns true if the two libraries have the same groups and templates.
+    pub fn is_empty(&self) -> bool {
+        !self.metadata_changed
+            && self.added_groups.is_empty()
+            && self.removed_groups.is_empty()
+            && self.modified_groups.is_empty()
+            && self.added_templates.is_empty()
+            && self.removed_templates.is_empty()
+            && self.modified_templates.is_empty()
+    }
+}
+
+/// Changes to a group present in both libraries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct GroupDiff {
+    pub name: String,
+    /// Options in the new group that the old group doesn't have.
+    pub added_options: Vec<String>,
+    /// Options in the old group that the new group doesn't have.
+    pub removed_options: Vec<String>,
+    /// True when the options are the same but in a different order.
+    pub reordered: bool,
+    /// True when the group's aliases differ.
+    pub aliases_changed: bool,
+    /// True when the notes on the group's options differ.
+    pub option_meta_changed: bool,
+}
+
+/// Compare two libraries by group and template name.
+///
+/// Results are listed in the order items appear in their library.
+pub fn diff_libraries(old: &Library, new: &Library) -> LibraryDiff {
+    let mut diff = LibraryDiff {
+        metadata_changed: old.name != new.name
+            || old.description != new.description
+            || old.default_seed != new.default_seed,
+        ..LibraryDiff::default()
+    };
+
+    for group in &new.groups {
+        match old.find_group(&group.name) {
+            Some(old_group) => {
+                if let Some(group_diff) = diff_group(old_group, group) {
+                    diff.modified_groups.push(group_diff);
+                }
+            }
+            None => diff.added_groups.push(group.name.clone()),
+        }
+    }
+    diff.removed_groups = old
+        .groups
+        .iter()
+        .filter(|g| new.find_group(&g.name).is_none())
+        .map(|g| g.name.clone())
+        .collect();
+
+    for template in &new.templates {
+        match old.find_template(&template.name) {
+            Some(old_template) => {
+                if template_changed(old_template, template) {
+                    diff.modified_templates.push(template.name.clone());
+                }
+            }
+            None => diff.added_templates.push(template.name.clone()),
+        }
+    }
+    diff.removed_templates = old
+        .templates
+        .iter()
+        .filter(|t| new.find_template(&t.name).is_none())
+        .map(|t| t.name.clone())
+        .collect();
+
+    diff
+}
+
+/// Compare two groups, or `None` if their options, aliases and option notes
+/// are identical.
+///
+/// Options are compared as a multiset, so a duplicated option that is
+/// dropped shows up as removed.
+fn diff_group(old: &PromptGroup, new: &PromptGroup) -> Option<GroupDiff> {
+    let aliases_changed = old.aliases != new.aliases;
+    let option_meta_changed = old.option_meta != new.option_meta;
+    if old.options == new.options && !aliases_changed && !option_meta_changed {
+        return None;
+    }
+
+    let mut unmatched: Vec<&String> = new.options.iter().collect();
+    let mut removed_options = Vec::new();
+    for option in &old.options {
+        match unmatched.iter().position(|o| *o == option) {
+            Some(pos) => {
+                unmatched.remove(pos);
+            }
+            None => removed_options.push(option.clone()),
+        }
+    }
+    let added_options: Vec<String> = unmatched.into_iter().cloned().collect();
+    let reordered =
+        old.options != new.options && added_options.is_empty() && removed_options.is_empty();
+
+    Some(GroupDiff {
+        name: new.name.clone(),
+        added_options,
+        removed_options,
+        reordered,
+        aliases_changed,
+        option_meta_changed,
+    })
+}
+
+fn template_changed(old: &PromptTemplate, new: &PromptTemplate) -> bool {
+    old.description != new.description
+        || old.engine_hint != new.engine_hint
+        || old.ast.nodes != new.ast.nodes
+        || old.default_slots != new.default_slots
+        || old.tags != new.tags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::OptionMeta;
+    use crate::parser::parse_template;
+
+    fn library(groups: &[(&str, &[&str])], templates: &[(&str, &str)]) -> Library {
+        let mut lib = Library::with_id("lib", "Test");
+        for (name, options) in groups {
+            lib.groups.push(PromptGroup::with_options(*name, options.to_vec()));
+        }
+        for (name, source) in templates {
+            let ast = parse_template(source).unwrap();
+            lib.templates.push(PromptTemplate::new(*name, ast));
+        }
+        lib
+    }
+
+    #[test]
+    fn test_identical_libraries_have_empty_diff() {
+        let old = library(&[("Hair", &["red", "blue"])], &[("Portrait", "@Hair")]);
+        let new = library(&[("Hair", &["red", "blue"])], &[("Portrait", "@Hair")]);
+
+        assert!(diff_libraries(&old, &new).is_empty());
+    }
+
+    #[test]
+    fn test_changed_library_description() {
+        let old = library(&[("Hair", &["red"])], &[]);
+        let mut new = library(&[("Hair", &["red"])], &[]);
+        new.description = "Portrait bits".to_string();
+
+        let diff = diff_libraries(&old, &new);
+        assert!(diff.metadata_changed);
+        assert!(!diff.is_empty());
+        assert!(diff.modified_groups.is_empty());
+    }
+
+    #[test]
+    fn test_changed_default_seed() {
+        let old = library(&[], &[]);
+        let mut new = library(&[], &[]);
+        new.default_seed = Some(42);
+
+        assert!(diff_libraries(&old, &new).metadata_changed);
+    }
+
+    #[test]
+    fn test_added_group() {
+        let old = library(&[("Hair", &["red"])], &[]);
+        let new = library(&[("Hair", &["red"]), ("Eyes", &["green"])], &[]);
+
+        let diff = diff_libraries(&old, &new);
+        assert_eq!(diff.added_groups, vec!["Eyes"]);
+        assert!(diff.removed_groups.is_empty());
+        assert!(diff.modified_groups.is_empty());
+    }
+
+    #[test]
+    fn test_removed_template() {
+        let old = library(&[], &[("Portrait", "a portrait"), ("Landscape", "a landscape")]);
+        let new = library(&[], &[("Portrait", "a portrait")]);
+
+        let diff = diff_libraries(&old, &new);
+        assert_eq!(diff.removed_templates, vec!["Landscape"]);
+        assert!(diff.added_templates.is_empty());
+        assert!(diff.modified_templates.is_empty());
+    }
+
+    #[test]
+    fn test_modified_template_source() {
+        let old = library(&[], &[("Portrait", "a portrait")]);
+        let new = library(&[], &[("Portrait", "a detailed portrait")]);
+
+        assert_eq!(diff_libraries(&old, &new).modified_templates, vec!["Portrait"]);
+    }
+
+    #[test]
+    fn test_modified_template_default_slots() {
+        let old = library(&[], &[("Portrait", "{{ Hero }}")]);
+        let mut new = library(&[], &[("Portrait", "{{ Hero }}")]);
+        new.templates[0].default_slots.insert("Hero".to_string(), "a knight".to_string());
+
+        assert_eq!(diff_libraries(&old, &new).modified_templates, vec!["Portrait"]);
+    }
+
+    #[test]
+    fn test_modified_template_tags() {
+        let old = library(&[], &[("Portrait", "a portrait")]);
+        let mut new = library(&[], &[("Portrait", "a portrait")]);
+        new.templates[0].tags.push("people".to_string());
+
+        assert_eq!(diff_libraries(&old, &new).modified_templates, vec!["Portrait"]);
+    }
+
+    #[test]
+    fn test_changed_options() {
+        let old = library(&[("Hair", &["red", "blue", "green"])], &[]);
+        let new = library(&[("Hair", &["red", "green", "black"])], &[]);
+
+        let diff = diff_libraries(&old, &new);
+        assert_eq!(
+            diff.modified_groups,
+            vec![GroupDiff {
+                name: "Hair".to_string(),
+                added_options: vec!["black".to_string()],
+                removed_options: vec!["blue".to_string()],
+                reordered: false,
+                aliases_changed: false,
+                option_meta_changed: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_reordered_options() {
+        let old = library(&[("Hair", &["red", "blue"])], &[]);
+        let new = library(&[("Hair", &["blue", "red"])], &[]);
+
+        let diff = diff_libraries(&old, &new);
+        assert_eq!(diff.modified_groups.len(), 1);
+        let group = &diff.modified_groups[0];
+        assert!(group.reordered);
+        assert!(group.added_options.is_empty());
+        assert!(group.removed_options.is_empty());
+    }
+
+    #[test]
+    fn test_changed_aliases() {
+        let old = library(&[("Hair", &["red"])], &[]);
+        let mut new = library(&[("Hair", &["red"])], &[]);
+        new.groups[0].aliases.insert("ginger".to_string(), "red".to_string());
+
+        let diff = diff_libraries(&old, &new);
+        assert_eq!(diff.modified_groups.len(), 1);
+        let group = &diff.modified_groups[0];
+        assert!(group.aliases_changed);
+        assert!(!group.reordered);
+        assert!(group.added_options.is_empty());
+    }
+
+    #[test]
+    fn test_changed_option_meta() {
+        let old = library(&[("Hair", &["red"])], &[]);
+        let mut new = library(&[("Hair", &["red"])], &[]);
+        new.groups[0].option_meta.insert(
+            "red".to_string(),
+            OptionMeta {
+                note: Some("warm".to_string()),
+                source: None,
+            },
+        );
+
+        let diff = diff_libraries(&old, &new);
+        assert_eq!(diff.modified_groups.len(), 1);
+        assert!(diff.modified_groups[0].option_meta_changed);
+        assert!(!diff.modified_groups[0].aliases_changed);
+    }
+
+    #[test]
+    fn test_dropped_duplicate_option_is_removed() {
+        let old = library(&[("Hair", &["red", "red"])], &[]);
+        let new = library(&[("Hair", &["red"])], &[]);
+
+        let diff = diff_libraries(&old, &new);
+        assert_eq!(diff.modified_groups[0].removed_options, vec!["red"]);
+        assert!(!diff.modified_groups[0].reordered);
+    }
+}
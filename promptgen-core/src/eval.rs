@@ -8,56 +8,167 @@
 //! - Lazy parsing of option text for nested grammar
 //! - Cycle detection for circular references
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
 
 use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+
+use crate::ast::{CaseKind, LibraryRef, MaybeOption, Node, OptionItem, Spanned, Template};
+use crate::library::{Library, PromptGroup, PromptTemplate};
+use crate::output::OutputBuilder;
+use crate::parser::{ParseError, parse_template};
+use crate::source::{library_ref_to_source, node_to_source};
+use crate::span::Span;
+
+/// The RNG [`EvalContext::with_stable_rng`] uses: ChaCha with 8 rounds,
+/// whose output for a given seed is fixed by its specification rather than
+/// by the `rand` version, unlike [`StdRng`].
+pub type StableRng = ChaCha8Rng;
+
+/// An RNG a render can draw its picks from.
+///
+/// Group salts and [`EvalContext::stable_node_seeds`] seed further RNGs of
+/// the same type. By default a pick is drawn with `rand`'s `random_range`,
+/// which keeps the picks [`StdRng`] seeds have always given; [`StableRng`]
+/// turns one draw into an index in this crate instead, since how
+/// `random_range` does that may change between `rand` releases.
+pub trait PickRng: Rng + SeedableRng + Clone {
+    /// An index below `len`.
+    fn pick_below(&mut self, len: usize) -> usize {
+        self.random_range(0..len)
+    }
+}
 
-use crate::ast::{LibraryRef, Node, OptionItem};
-use crate::library::{Library, PromptTemplate};
-use crate::parser::parse_template;
+impl PickRng for StdRng {}
+
+impl PickRng for StableRng {
+    fn pick_below(&mut self, len: usize) -> usize {
+        // Widening multiply; the bias is at most `len` in 2^64
+        ((u128::from(self.next_u64()) * len as u128) >> 64) as usize
+    }
+}
 
 /// Context for evaluating a template.
-pub struct EvalContext<'a, R: Rng = StdRng> {
+pub struct EvalContext<'a, R: PickRng = StdRng> {
     /// The library containing groups and their options.
     pub library: &'a Library,
     /// Random number generator for selecting options.
     pub rng: R,
     /// Overrides for freeform slots (slot name -> value).
     pub slot_overrides: HashMap<String, String>,
+    /// Let `@hair` resolve to a group named `Hair` when there is no exact
+    /// match. Off by default.
+    pub case_insensitive_refs: bool,
+    /// What to do with a reference to a group that doesn't exist.
+    pub on_missing_ref: MissingRefPolicy,
+    /// Salts that re-pick a group's option (group name -> salt). The main RNG
+    /// is drawn from as usual, and the salted pick and its nested grammar use
+    /// an RNG derived from the salt, the group name and the render's seed, so
+    /// choices elsewhere stay the same and a salt re-picks differently under
+    /// each seed.
+    pub group_salts: HashMap<String, u64>,
+    /// Tidy the rendered text with [`normalize_whitespace`]. Applies to
+    /// [`render`] and [`render_to_writer`]; off by default.
+    pub collapse_whitespace: bool,
+    /// Give each top-level node its own RNG, seeded from one draw of `rng`
+    /// and the node's source text. Adding, removing or editing other nodes
+    /// then leaves a node's picks unchanged, at the cost of different
+    /// output from the default mode for the same seed. Off by default.
+    ///
+    /// Seeds are keyed by source rather than position, since positions shift
+    /// whenever a node is inserted. Repeats of the same source are told apart
+    /// by how many came before, so identical references still pick
+    /// independently.
+    pub stable_node_seeds: bool,
+    /// Drop the comma or space after a reference, inline options or slot
+    /// that renders empty, so `@Adj, @Noun` doesn't leave `, noun`. Applies
+    /// to [`render`] and [`render_to_writer`]; off by default. See
+    /// [`OutputBuilder`].
+    pub skip_empty_separators: bool,
+    /// Drop the line a comment has to itself, so `a\n# note\nb` renders as
+    /// `a\nb` rather than leaving a blank line. On by default.
+    pub strip_comment_lines: bool,
+    /// Fill [`RenderResult::segments`] with where each part of the text came
+    /// from. Only applies to [`render`], and not under `collapse_whitespace`;
+    /// off by default.
+    pub record_segments: bool,
+    /// Drawn from a copy of `rng` when the context is created, so group salts
+    /// depend on the seed without drawing from `rng`
+    salt_seed: u64,
+    /// RNG for the top-level node being evaluated, under `stable_node_seeds`,
+    /// or for a salted group's option
+    node_rng: Option<R>,
+    /// Choices replayed from an earlier render; see [`EvalContext::apply_overrides`]
+    pinned: HashMap<String, VecDeque<String>>,
+    /// Inline options chosen so far in this render, as (source, text)
+    inline_choices: Vec<(String, String)>,
+    /// First value each slot resolved to in this render, reused by later
+    /// `{{ name }}` and `{{= name }}` blocks
+    resolved_slots: HashMap<String, String>,
+    /// Slots whose values are being evaluated (for cycle detection)
+    resolving_slots: Vec<String>,
     /// Stack of group names being evaluated (for cycle detection).
     eval_stack: Vec<String>,
+    /// How many inline options or maybe-options are being evaluated
+    inline_depth: usize,
 }
 
 impl<'a> EvalContext<'a, StdRng> {
     /// Create a new context with the given library and a random seed.
     pub fn new(library: &'a Library) -> Self {
-        Self {
-            library,
-            rng: StdRng::from_os_rng(),
-            slot_overrides: HashMap::new(),
-            eval_stack: Vec::new(),
-        }
+        Self::with_rng(library, StdRng::from_os_rng())
+    }
+
+    /// Create a new context seeded from a word or phrase, which is easier to
+    /// remember than a number. See [`seed_from_label`].
+    pub fn with_seed_str(library: &'a Library, label: &str) -> Self {
+        Self::with_seed(library, seed_from_label(label))
     }
 
     /// Create a new context with a specific seed for deterministic evaluation.
+    ///
+    /// The same seed may pick differently after a `rand` upgrade; use
+    /// [`EvalContext::with_stable_rng`] for seeds that are shared.
     pub fn with_seed(library: &'a Library, seed: u64) -> Self {
-        Self {
-            library,
-            rng: StdRng::seed_from_u64(seed),
-            slot_overrides: HashMap::new(),
-            eval_stack: Vec::new(),
-        }
+        Self::with_rng(library, StdRng::seed_from_u64(seed))
+    }
+}
+
+impl<'a> EvalContext<'a, StableRng> {
+    /// Create a context whose picks for `seed` stay the same across PromptGen
+    /// and `rand` releases, for seeds saved with a shared library. Both the
+    /// RNG and the way a draw becomes an option index are fixed in this
+    /// crate, including for group salts and `stable_node_seeds`. Slower than
+    /// [`EvalContext::with_seed`].
+    pub fn with_stable_rng(library: &'a Library, seed: u64) -> Self {
+        Self::with_rng(library, StableRng::seed_from_u64(seed))
     }
 }
 
-impl<'a, R: Rng> EvalContext<'a, R> {
+impl<'a, R: PickRng> EvalContext<'a, R> {
     /// Create a new context with a custom RNG.
     pub fn with_rng(library: &'a Library, rng: R) -> Self {
         Self {
             library,
+            salt_seed: rng.clone().next_u64(),
             rng,
             slot_overrides: HashMap::new(),
+            case_insensitive_refs: false,
+            on_missing_ref: MissingRefPolicy::default(),
+            group_salts: HashMap::new(),
+            collapse_whitespace: false,
+            stable_node_seeds: false,
+            skip_empty_separators: false,
+            strip_comment_lines: true,
+            record_segments: false,
+            node_rng: None,
+            pinned: HashMap::new(),
+            inline_choices: Vec::new(),
+            resolved_slots: HashMap::new(),
+            resolving_slots: Vec::new(),
             eval_stack: Vec::new(),
+            inline_depth: 0,
         }
     }
 
@@ -70,6 +181,173 @@ impl<'a, R: Rng> EvalContext<'a, R> {
     pub fn set_slots(&mut self, overrides: impl IntoIterator<Item = (String, String)>) {
         self.slot_overrides.extend(overrides);
     }
+
+    /// Replay choices from [`RenderResult::to_slot_overrides`].
+    ///
+    /// Keys starting with `@` pin a group's picks and keys starting with `{`
+    /// pin an inline option's picks, each used in order and then falling
+    /// back to random picks. Any other key sets a slot to its first value.
+    pub fn apply_overrides(&mut self, overrides: HashMap<String, Vec<String>>) {
+        for (key, values) in overrides {
+            if key.starts_with('@') || key.starts_with('{') {
+                self.pinned.insert(key, values.into());
+            } else if let Some(value) = values.into_iter().next() {
+                self.slot_overrides.insert(key, value);
+            }
+        }
+    }
+
+    /// The next pinned choice for `key`, if any. Choices inside a group's
+    /// options are never pinned: the group's pinned text already has them.
+    fn take_pinned(&mut self, key: &str) -> Option<String> {
+        if !self.eval_stack.is_empty() {
+            return None;
+        }
+        self.pinned.get_mut(key)?.pop_front()
+    }
+
+    /// Pick an index below `len` from the current node's RNG, if it has one.
+    fn pick_index(&mut self, len: usize) -> usize {
+        match &mut self.node_rng {
+            Some(rng) => rng.pick_below(len),
+            None => self.rng.pick_below(len),
+        }
+    }
+}
+
+/// Seeds top-level nodes under [`EvalContext::stable_node_seeds`].
+struct NodeSeeder {
+    base: u64,
+    seen: HashMap<String, u64>,
+}
+
+impl NodeSeeder {
+    /// Returns `None` unless the context asks for stable node seeds.
+    fn new<R: PickRng>(ctx: &mut EvalContext<'_, R>) -> Option<Self> {
+        ctx.stable_node_seeds.then(|| NodeSeeder {
+            base: ctx.rng.next_u64(),
+            seen: HashMap::new(),
+        })
+    }
+
+    /// Point the context at a fresh RNG for `node`.
+    fn seed<R: PickRng>(&mut self, node: &Node, ctx: &mut EvalContext<'_, R>) {
+        let mut source = String::new();
+        node_to_source(node, &mut source);
+        let occurrence = self.seen.entry(source.clone()).or_insert(0);
+
+        let bytes = self
+            .base
+            .to_le_bytes()
+            .into_iter()
+            .chain(source.bytes())
+            .chain(occurrence.to_le_bytes());
+        let seed = fnv1a(bytes);
+        *occurrence += 1;
+
+        ctx.node_rng = Some(R::seed_from_u64(seed));
+    }
+}
+
+/// What to cut from a text node next to full-line comments.
+#[derive(Debug, Default, Clone, Copy)]
+struct LineTrim {
+    /// The line break ending a comment line above
+    leading_newline: bool,
+    /// Spaces or tabs indenting a comment below
+    trailing_indent: bool,
+    /// The line break before a comment that ends the template
+    trailing_newline: bool,
+}
+
+/// Text nodes rewritten without the lines that hold only a comment, by
+/// index; see [`EvalContext::strip_comment_lines`].
+///
+/// A comment is on its own line when only spaces or tabs separate it from
+/// the previous line break (or the start of the template), and a line break
+/// (or the end of the template) follows it.
+fn comment_line_texts(nodes: &[Spanned<Node>]) -> HashMap<usize, String> {
+    let text_at = |i: usize| match nodes.get(i) {
+        Some((Node::Text(text), _)) => Some(text.as_str()),
+        _ => None,
+    };
+
+    let mut trims: HashMap<usize, LineTrim> = HashMap::new();
+    for (i, (node, _span)) in nodes.iter().enumerate() {
+        if !matches!(node, Node::Comment(_)) {
+            continue;
+        }
+        let starts_line = i == 0
+            || text_at(i - 1).is_some_and(|text| {
+                let before = text.trim_end_matches([' ', '\t']);
+                before.ends_with('\n') || (before.is_empty() && i == 1)
+            });
+        let is_last = i + 1 == nodes.len();
+        let ends_line =
+            is_last || text_at(i + 1).is_some_and(|text| text.starts_with(['\n', '\r']));
+        if !starts_line || !ends_line {
+            continue;
+        }
+
+        if i > 0 {
+            let trim = trims.entry(i - 1).or_default();
+            trim.trailing_indent = true;
+            trim.trailing_newline |= is_last;
+        }
+        if !is_last {
+            trims.entry(i + 1).or_default().leading_newline = true;
+        }
+    }
+
+    trims
+        .into_iter()
+        .filter_map(|(i, trim)| {
+            let mut text = text_at(i)?;
+            if trim.leading_newline {
+                text = text
+                    .strip_prefix("\r\n")
+                    .or_else(|| text.strip_prefix('\n'))
+                    .unwrap_or(text);
+            }
+            if trim.trailing_indent {
+                text = text.trim_end_matches([' ', '\t']);
+            }
+            if trim.trailing_newline {
+                text = text.strip_suffix('\n').unwrap_or(text);
+                text = text.strip_suffix('\r').unwrap_or(text);
+            }
+            Some((i, text.to_string()))
+        })
+        .collect()
+}
+
+/// The seed for a text label, as used by [`EvalContext::with_seed_str`].
+///
+/// The same label always gives the same seed, on any platform and Rust
+/// version. Labels are case-sensitive and used as written.
+pub fn seed_from_label(label: &str) -> u64 {
+    fnv1a(label.bytes())
+}
+
+/// 64-bit FNV-1a, which unlike std's hashers is stable across Rust versions.
+pub(crate) fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes.into_iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// How rendering handles a reference to a group that doesn't exist.
+///
+/// A reference with a `?? "fallback"` always uses its fallback instead.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingRefPolicy {
+    /// Fail with [`RenderError::GroupNotFound`].
+    #[default]
+    Error,
+    /// Render nothing in place of the reference.
+    EmptyString,
+    /// Render the reference as written, e.g. `@Hair`.
+    KeepLiteral,
 }
 
 /// Record of which option was chosen from a group.
@@ -81,6 +359,32 @@ pub struct ChosenOption {
     pub library_name: Option<String>,
     /// The text of the option that was selected.
     pub option_text: String,
+    /// The alias that was picked, when the group mapped it to `option_text`.
+    pub alias: Option<String>,
+    /// Case transform the reference applied to `option_text` in the output.
+    pub transform: Option<CaseKind>,
+    /// True if the group was missing or empty and the reference's fallback
+    /// text was used instead.
+    pub used_fallback: bool,
+    /// Index in [`RenderResult::chosen_options`] of the choice whose option
+    /// text held this reference, such as the pick from `FancyEyes` for the
+    /// `@Color` in its option `@Color eyes`. `None` for references written
+    /// in the template, a slot value or inline options. A choice always
+    /// comes after its parent.
+    pub parent: Option<usize>,
+    /// True if the choice was made in a picked branch of inline options or
+    /// a maybe-option, whose text a replay pins as a whole.
+    pub in_inline_options: bool,
+}
+
+impl ChosenOption {
+    /// The text as it appears in the output, with any transform applied.
+    pub fn output_text(&self) -> String {
+        match self.transform {
+            Some(transform) => transform.apply(&self.option_text),
+            None => self.option_text.clone(),
+        }
+    }
 }
 
 /// Result of rendering a template.
@@ -92,6 +396,118 @@ pub struct RenderResult {
     pub chosen_options: Vec<ChosenOption>,
     /// Slot values that were used.
     pub slot_values: HashMap<String, String>,
+    /// Inline options chosen outside group options, as (source, chosen
+    /// text), in the order they were evaluated.
+    pub inline_choices: Vec<(String, String)>,
+    /// The parts of `text` each top-level node produced, in order, when
+    /// [`EvalContext::record_segments`] is set. Nodes that produced nothing
+    /// are left out, so the ranges together cover the whole text.
+    pub segments: Vec<OutputSegment>,
+}
+
+/// The part of a render's text that one top-level template node produced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputSegment {
+    /// Byte range in [`RenderResult::text`].
+    pub range: Span,
+    /// Span of the node in the template source.
+    pub span: Span,
+    pub kind: SegmentKind,
+}
+
+/// What kind of node produced an [`OutputSegment`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SegmentKind {
+    /// Literal template text.
+    Text,
+    /// An option of the named group.
+    Group(String),
+    /// Inline options or a maybe-option.
+    InlineOptions,
+    /// The value of the named slot, or a reference to it.
+    Slot(String),
+}
+
+impl SegmentKind {
+    fn of(node: &Node) -> Self {
+        match node {
+            Node::Text(_) | Node::Comment(_) => SegmentKind::Text,
+            Node::LibraryRef(lib_ref) => SegmentKind::Group(lib_ref.group.clone()),
+            Node::InlineOptions(_) | Node::MaybeOption(_) => SegmentKind::InlineOptions,
+            Node::Slot(slot) => SegmentKind::Slot(slot.name.clone()),
+            Node::SlotRef(name) => SegmentKind::Slot(name.clone()),
+        }
+    }
+}
+
+impl RenderResult {
+    /// The choices made in this render, in a form that
+    /// [`EvalContext::apply_overrides`] can replay.
+    ///
+    /// Groups are keyed `@Name` and inline options by their source, such as
+    /// `{red|blue}`, each with the text chosen every time it was evaluated.
+    /// Slots are keyed by name. Rendering the same template with these
+    /// overrides gives the same text whatever the seed.
+    pub fn to_slot_overrides(&self) -> HashMap<String, Vec<String>> {
+        let mut overrides: HashMap<String, Vec<String>> = self
+            .slot_values
+            .iter()
+            .map(|(name, value)| (name.clone(), vec![value.clone()]))
+            .collect();
+        // Fallbacks are used again on their own, and a replayed option or
+        // inline option already holds the choices nested in it
+        for chosen in self
+            .chosen_options
+            .iter()
+            .filter(|c| !c.used_fallback && c.parent.is_none() && !c.in_inline_options)
+        {
+            overrides
+                .entry(format!("@{}", chosen.group_name))
+                .or_default()
+                .push(chosen.option_text.clone());
+        }
+        for (source, text) in &self.inline_choices {
+            overrides.entry(source.clone()).or_default().push(text.clone());
+        }
+        overrides
+    }
+
+    /// A one-line, human-readable list of the choices made, such as
+    /// `Hair=blonde hair, Eyes=blue eyes`. Empty if nothing was chosen.
+    pub fn summary(&self) -> String {
+        self.chosen_options
+            .iter()
+            .map(|chosen| {
+                let mut entry = match &chosen.library_name {
+                    Some(library) => format!("{}:{}", library, chosen.group_name),
+                    None => chosen.group_name.clone(),
+                };
+                entry.push('=');
+                entry.push_str(&chosen.output_text());
+                if chosen.used_fallback {
+                    entry.push_str(" (fallback)");
+                }
+                entry
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Return the result with its text passed through [`normalize_whitespace`].
+    pub fn normalized(mut self) -> Self {
+        self.text = normalize_whitespace(&self.text);
+        self
+    }
+}
+
+/// Result of [`render_to_writer`]: everything in [`RenderResult`] except
+/// the text, which has already been written out.
+#[derive(Debug, Clone)]
+pub struct RenderSummary {
+    /// Options that were chosen during rendering (for provenance).
+    pub chosen_options: Vec<ChosenOption>,
+    /// Slot values that were used.
+    pub slot_values: HashMap<String, String>,
 }
 
 /// Error that can occur during rendering.
@@ -111,31 +527,301 @@ pub enum RenderError {
 
     #[error("ambiguous group reference '{0}' found in multiple libraries")]
     AmbiguousGroup(String),
+
+    #[error("group reference '{0}' matches several groups that differ only by case")]
+    AmbiguousGroupCase(String),
+
+    #[error("required slot has no value: {0}")]
+    MissingRequiredSlot(String),
+
+    #[error("value for slot '{slot}' doesn't parse near '{excerpt}': {message}")]
+    SlotValueParseError {
+        slot: String,
+        message: String,
+        /// Byte range in the slot value that the error points at
+        span: Span,
+        /// The text of the value in `span`
+        excerpt: String,
+    },
+
+    #[error("value for slot '{0}' contains a slot block")]
+    SlotInSlotValue(String),
+
+    #[error("circular slot reference: {0}")]
+    CircularSlotReference(String),
+
+    #[error("render was cancelled")]
+    Cancelled,
+
+    #[error("failed to write output: {0}")]
+    Write(#[from] std::io::Error),
 }
 
 /// Render a template using the given context.
-pub fn render<R: Rng>(
+pub fn render<R: PickRng>(
     template: &PromptTemplate,
     ctx: &mut EvalContext<'_, R>,
 ) -> Result<RenderResult, RenderError> {
-    let mut output = String::new();
+    render_cancellable(template, ctx, None)
+}
+
+/// Render a template, asking `should_cancel` before each top-level node
+/// whether to stop.
+///
+/// Returns [`RenderError::Cancelled`] as soon as the callback returns true.
+/// Without a callback this is the same as [`render`].
+pub fn render_cancellable<R: PickRng>(
+    template: &PromptTemplate,
+    ctx: &mut EvalContext<'_, R>,
+    should_cancel: Option<&dyn Fn() -> bool>,
+) -> Result<RenderResult, RenderError> {
+    let mut output = OutputBuilder::new(ctx.skip_empty_separators);
     let mut chosen_options = Vec::new();
+    let mut segments = Vec::new();
     let slot_values = ctx.slot_overrides.clone();
+    ctx.inline_choices.clear();
+    ctx.resolved_slots.clear();
+    let mut seeder = NodeSeeder::new(ctx);
+    let texts = stripped_texts(template, ctx);
+
+    for (i, (node, span)) in template.ast.nodes.iter().enumerate() {
+        if should_cancel.is_some_and(|cancel| cancel()) {
+            return Err(RenderError::Cancelled);
+        }
+        if let Some(seeder) = &mut seeder {
+            seeder.seed(node, ctx);
+        }
+        let text = match texts.get(&i) {
+            Some(text) => Ok(text.clone()),
+            None => eval_node(node, ctx, &mut chosen_options),
+        };
+        ctx.node_rng = None;
+        let start = output.len();
+        match node {
+            Node::Text(_) | Node::Comment(_) => output.push_text(&text?),
+            _ => output.push_expansion(&text?),
+        }
+        if ctx.record_segments {
+            segments.push(OutputSegment {
+                range: start..output.len(),
+                span: span.clone(),
+                kind: SegmentKind::of(node),
+            });
+        }
+    }
 
-    for (node, _span) in &template.ast.nodes {
-        let text = eval_node(node, ctx, &mut chosen_options)?;
-        output.push_str(&text);
+    let mut output = output.finish();
+    if ctx.collapse_whitespace {
+        output = normalize_whitespace(&output);
+        segments.clear();
+    }
+    // Separators trimmed from the end may shorten the last segments
+    for segment in &mut segments {
+        segment.range = segment.range.start.min(output.len())..segment.range.end.min(output.len());
     }
+    segments.retain(|segment| !segment.range.is_empty());
 
     Ok(RenderResult {
         text: output,
         chosen_options,
         slot_values,
+        inline_choices: std::mem::take(&mut ctx.inline_choices),
+        segments,
+    })
+}
+
+/// Clean up the gaps left by empty slots and options.
+///
+/// Within each line, runs of whitespace become a single space, spaces
+/// before `, . ; : ! ?` are removed, and empty comma-separated items are
+/// dropped, along with leading and trailing commas. Lines are kept, and the
+/// result is trimmed.
+pub fn normalize_whitespace(text: &str) -> String {
+    let lines: Vec<String> = text.lines().map(normalize_line).collect();
+    lines.join("\n").trim().to_string()
+}
+
+fn normalize_line(line: &str) -> String {
+    let mut out = String::new();
+    for word in line.split_whitespace() {
+        if word.starts_with([',', '.', ';', ':', '!', '?']) {
+            if word == "," && (out.is_empty() || out.ends_with(',')) {
+                continue;
+            }
+            out.push_str(word);
+        } else {
+            if !out.is_empty() {
+                out.push(' ');
+            }
+            out.push_str(word);
+        }
+    }
+    out.trim_end_matches(',').to_string()
+}
+
+/// Applies [`normalize_whitespace`] to text that arrives in pieces, giving
+/// back each line once it is complete.
+#[derive(Debug, Default)]
+struct LineNormalizer {
+    /// Text after the last line break
+    line: String,
+    /// A non-empty line has been given back
+    started: bool,
+    /// Empty lines since, kept only if another non-empty line follows
+    blank_lines: usize,
+}
+
+impl LineNormalizer {
+    /// Add `text`, returning the normalized output of the lines it completed.
+    fn push(&mut self, text: &str) -> String {
+        self.line.push_str(text);
+        let mut out = String::new();
+        while let Some(end) = self.line.find('\n') {
+            let line: String = self.line.drain(..=end).collect();
+            self.push_line(&line, &mut out);
+        }
+        out
+    }
+
+    /// The normalized output of the last line.
+    fn finish(mut self) -> String {
+        let mut out = String::new();
+        let line = std::mem::take(&mut self.line);
+        self.push_line(&line, &mut out);
+        out
+    }
+
+    fn push_line(&mut self, line: &str, out: &mut String) {
+        let line = normalize_line(line);
+        if line.is_empty() {
+            self.blank_lines += 1;
+            return;
+        }
+        // Lines before the first and after the last are trimmed away
+        if self.started {
+            out.push_str(&"\n".repeat(self.blank_lines + 1));
+        }
+        out.push_str(&line);
+        self.started = true;
+        self.blank_lines = 0;
+    }
+}
+
+/// Render a template, writing each top-level node's output as it is produced.
+///
+/// Makes the same random choices as [`render`] for the same context, and
+/// drops the same separators under `skip_empty_separators`, so the written
+/// text equals `render(...).text`. Separators after an expansion are held
+/// back until the next node shows whether to keep them, and under
+/// `collapse_whitespace` each line is written once it ends. On error, the
+/// output of earlier nodes has already been written.
+pub fn render_to_writer<W: Write, R: PickRng>(
+    template: &PromptTemplate,
+    ctx: &mut EvalContext<'_, R>,
+    w: &mut W,
+) -> Result<RenderSummary, RenderError> {
+    let mut output = OutputBuilder::new(ctx.skip_empty_separators);
+    let mut lines = ctx.collapse_whitespace.then(LineNormalizer::default);
+    let mut write = |w: &mut W, text: String| match &mut lines {
+        Some(lines) => w.write_all(lines.push(&text).as_bytes()),
+        None => w.write_all(text.as_bytes()),
+    };
+    let mut chosen_options = Vec::new();
+    let slot_values = ctx.slot_overrides.clone();
+    ctx.resolved_slots.clear();
+    let mut seeder = NodeSeeder::new(ctx);
+    let texts = stripped_texts(template, ctx);
+
+    for (i, (node, _span)) in template.ast.nodes.iter().enumerate() {
+        if let Some(seeder) = &mut seeder {
+            seeder.seed(node, ctx);
+        }
+        let text = match texts.get(&i) {
+            Some(text) => Ok(text.clone()),
+            None => eval_node(node, ctx, &mut chosen_options),
+        };
+        ctx.node_rng = None;
+        match node {
+            Node::Text(_) | Node::Comment(_) => output.push_text(&text?),
+            _ => output.push_expansion(&text?),
+        }
+        write(w, output.take_ready())?;
+    }
+    write(w, output.finish())?;
+    if let Some(lines) = lines {
+        w.write_all(lines.finish().as_bytes())?;
+    }
+    ctx.inline_choices.clear();
+
+    Ok(RenderSummary {
+        chosen_options,
+        slot_values,
     })
 }
 
+/// Text nodes that [`EvalContext::strip_comment_lines`] changes, if set.
+fn stripped_texts<R: PickRng>(
+    template: &PromptTemplate,
+    ctx: &EvalContext<'_, R>,
+) -> HashMap<usize, String> {
+    if ctx.strip_comment_lines {
+        comment_line_texts(&template.ast.nodes)
+    } else {
+        HashMap::new()
+    }
+}
+
+impl Library {
+    /// Render `ast` once per seed, with a fresh [`EvalContext::with_seed`]
+    /// for each.
+    ///
+    /// Results are in the order of `seeds`, and each one is the same as a
+    /// single render with that seed. With the `rayon` feature the renders
+    /// run in parallel.
+    pub fn render_batch(
+        &self,
+        ast: &Template,
+        seeds: &[u64],
+    ) -> Vec<Result<RenderResult, RenderError>> {
+        let template = PromptTemplate::new("batch", ast.clone());
+        let render_seed = |seed: &u64| render(&template, &mut EvalContext::with_seed(self, *seed));
+
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+            seeds.par_iter().map(render_seed).collect()
+        }
+        #[cfg(not(feature = "rayon"))]
+        {
+            seeds.iter().map(render_seed).collect()
+        }
+    }
+
+    /// A one-line preview of the template `name`, for list views.
+    ///
+    /// Renders with `seed` so the preview doesn't change between calls,
+    /// joins lines with spaces, and cuts the text to at most `max_chars`
+    /// characters, ending in `…` if anything was cut; with a limit of 0
+    /// the preview is empty. Returns `None` if there is no such template or
+    /// it fails to render.
+    pub fn preview_prompt(&self, name: &str, seed: u64, max_chars: usize) -> Option<String> {
+        let template = self.find_template(name)?;
+        let result = render(template, &mut EvalContext::with_seed(self, seed)).ok()?;
+        let text = result.text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        if text.chars().count() <= max_chars {
+            return Some(text);
+        }
+        if max_chars == 0 {
+            return Some(String::new());
+        }
+        let kept: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+        Some(format!("{}…", kept.trim_end()))
+    }
+}
+
 /// Evaluate a single node, returning the output text.
-fn eval_node<R: Rng>(
+fn eval_node<R: PickRng>(
     node: &Node,
     ctx: &mut EvalContext<'_, R>,
     chosen_options: &mut Vec<ChosenOption>,
@@ -145,34 +831,121 @@ fn eval_node<R: Rng>(
 
         Node::Comment(_) => Ok(String::new()),
 
-        Node::Slot(slot_name) => {
-            if let Some(value) = ctx.slot_overrides.get(slot_name).cloned() {
+        Node::Slot(slot) => {
+            // A `{{= name }}` before the slot may have resolved it already
+            if let Some(text) = ctx.resolved_slots.get(&slot.name) {
+                return Ok(text.clone());
+            }
+            if let Some(value) = ctx.slot_overrides.get(&slot.name).cloned() {
                 // Slot values can contain grammar - parse and evaluate
-                eval_slot_value(&value, ctx, chosen_options)
+                resolve_slot(&slot.name, &value, ctx, chosen_options)
+            } else if slot.required {
+                Err(RenderError::MissingRequiredSlot(slot.name.clone()))
             } else {
                 // Leave the slot placeholder as-is if no override provided
-                Ok(format!("{{{{ {} }}}}", slot_name))
+                Ok(format!("{{{{ {} }}}}", slot.name))
+            }
+        }
+
+        Node::SlotRef(name) => {
+            if let Some(text) = ctx.resolved_slots.get(name) {
+                return Ok(text.clone());
+            }
+            if ctx.resolving_slots.contains(name) {
+                let mut chain = ctx.resolving_slots.clone();
+                chain.push(name.clone());
+                return Err(RenderError::CircularSlotReference(chain.join(" -> ")));
+            }
+            match ctx.slot_overrides.get(name).cloned() {
+                // The slot hasn't been rendered yet, so resolve it now
+                Some(value) => resolve_slot(name, &value, ctx, chosen_options),
+                None => Ok(format!("{{{{= {} }}}}", name)),
             }
         }
 
         Node::LibraryRef(lib_ref) => {
-            let (text, chosen) = resolve_library_ref(lib_ref, ctx)?;
-            chosen_options.push(chosen);
+            let (text, mut picks) = resolve_library_ref(lib_ref, ctx)?;
+            if ctx.inline_depth > 0 {
+                for chosen in &mut picks {
+                    chosen.in_inline_options = true;
+                }
+            }
+            append_chosen(chosen_options, picks);
             Ok(text)
         }
 
-        Node::InlineOptions(options) => eval_inline_options(options, ctx, chosen_options),
+        Node::InlineOptions(options) => {
+            pinned_or_eval(node, ctx, |ctx| eval_inline_options(options, ctx, chosen_options))
+        }
+
+        Node::MaybeOption(maybe) => {
+            pinned_or_eval(node, ctx, |ctx| eval_maybe_option(maybe, ctx, chosen_options))
+        }
+    }
+}
+
+/// Use the pinned choice for an inline option node, or evaluate it with
+/// `eval`, and record the result so the render can be replayed.
+fn pinned_or_eval<'a, R: PickRng>(
+    node: &Node,
+    ctx: &mut EvalContext<'a, R>,
+    eval: impl FnOnce(&mut EvalContext<'a, R>) -> Result<String, RenderError>,
+) -> Result<String, RenderError> {
+    let mut source = String::new();
+    node_to_source(node, &mut source);
+    let text = match ctx.take_pinned(&source) {
+        Some(text) => text,
+        None => {
+            ctx.inline_depth += 1;
+            let text = eval(ctx);
+            ctx.inline_depth -= 1;
+            text?
+        }
+    };
+    // Inline options nested in others are replayed with the outer choice
+    if ctx.eval_stack.is_empty() && ctx.inline_depth == 0 {
+        ctx.inline_choices.push((source, text.clone()));
     }
+    Ok(text)
+}
+
+/// Evaluate the value of slot `name`, keeping the first result for later
+/// `{{= name }}` references.
+fn resolve_slot<R: PickRng>(
+    name: &str,
+    value: &str,
+    ctx: &mut EvalContext<'_, R>,
+    chosen_options: &mut Vec<ChosenOption>,
+) -> Result<String, RenderError> {
+    // A slot first resolved inside inline options is replayed where it's
+    // declared, so its choices are pinned on their own
+    let inline_depth = std::mem::take(&mut ctx.inline_depth);
+    ctx.resolving_slots.push(name.to_string());
+    let text = eval_slot_value(name, value, ctx, chosen_options);
+    ctx.resolving_slots.pop();
+    ctx.inline_depth = inline_depth;
+
+    let text = text?;
+    ctx.resolved_slots
+        .entry(name.to_string())
+        .or_insert_with(|| text.clone());
+    Ok(text)
 }
 
 /// Evaluate a slot value, which may contain grammar.
-fn eval_slot_value<R: Rng>(
+fn eval_slot_value<R: PickRng>(
+    slot_name: &str,
     value: &str,
     ctx: &mut EvalContext<'_, R>,
     chosen_options: &mut Vec<ChosenOption>,
 ) -> Result<String, RenderError> {
     // Parse the slot value as a template
-    let ast = parse_template(value).map_err(|e| RenderError::OptionParseError(e.to_string()))?;
+    let ast = parse_template(value).map_err(|e| slot_parse_error(slot_name, value, &e))?;
+
+    // A slot inside a value could name its own slot and never finish
+    if contains_slot(&ast.nodes) {
+        return Err(RenderError::SlotInSlotValue(slot_name.to_string()));
+    }
 
     let mut output = String::new();
     for (node, _span) in &ast.nodes {
@@ -183,12 +956,116 @@ fn eval_slot_value<R: Rng>(
     Ok(output)
 }
 
+/// Whether `nodes` hold a slot anywhere, including in inline options and
+/// maybe-options, whose text is only parsed when it is picked.
+fn contains_slot(nodes: &[(Node, Span)]) -> bool {
+    let text_contains_slot =
+        |text: &str| parse_template(text).is_ok_and(|ast| contains_slot(&ast.nodes));
+    nodes.iter().any(|(node, _)| match node {
+        Node::Slot(_) => true,
+        Node::InlineOptions(options) => options.iter().any(|option| match option {
+            OptionItem::Text(text) => text_contains_slot(text),
+            OptionItem::Nested(nested) => contains_slot(nested),
+        }),
+        Node::MaybeOption(maybe) => text_contains_slot(&maybe.text),
+        Node::Text(_) | Node::Comment(_) | Node::LibraryRef(_) | Node::SlotRef(_) => false,
+    })
+}
+
+/// Describe why a slot's value doesn't parse, pointing at its first error.
+fn slot_parse_error(slot_name: &str, value: &str, error: &ParseError<'_>) -> RenderError {
+    let (message, span) = match error.diagnostics().into_iter().next() {
+        Some(diagnostic) => (diagnostic.message, diagnostic.span),
+        None => (error.to_string(), 0..value.len()),
+    };
+    let span = error_span(value, span);
+    RenderError::SlotValueParseError {
+        slot: slot_name.to_string(),
+        message,
+        excerpt: value.get(span.clone()).unwrap_or_default().to_string(),
+        span,
+    }
+}
+
+/// Widen an error at the very end of `value`, as an unclosed brace gives, to
+/// start at the last `{` that was never closed.
+fn error_span(value: &str, span: Span) -> Span {
+    if span.start < value.len() {
+        return span;
+    }
+    let mut open = Vec::new();
+    for (i, c) in value.char_indices() {
+        match c {
+            '{' => open.push(i),
+            '}' => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+    match open.last() {
+        Some(&start) => start..value.len(),
+        None => span,
+    }
+}
+
+/// Append `picks`, whose parent indices count from the start of `picks`.
+fn append_chosen(chosen_options: &mut Vec<ChosenOption>, picks: Vec<ChosenOption>) {
+    let base = chosen_options.len();
+    chosen_options.extend(picks.into_iter().map(|mut chosen| {
+        chosen.parent = chosen.parent.map(|parent| parent + base);
+        chosen
+    }));
+}
+
 /// Resolve a library reference to a random option.
-fn resolve_library_ref<R: Rng>(
+///
+/// Returns the choice, followed by the choices made by references in the
+/// chosen option's text, with parent indices counting from the first. No
+/// option is chosen when a missing group is rendered per
+/// [`EvalContext::on_missing_ref`].
+fn resolve_library_ref<R: PickRng>(
     lib_ref: &LibraryRef,
     ctx: &mut EvalContext<'_, R>,
-) -> Result<(String, ChosenOption), RenderError> {
-    let group_name = &lib_ref.group;
+) -> Result<(String, Vec<ChosenOption>), RenderError> {
+    // Find the group; a qualifier naming another library can't resolve here
+    let here = lib_ref
+        .library
+        .as_deref()
+        .is_none_or(|library| ctx.library.is_named(library));
+    let found = if here {
+        lookup_group(ctx, &lib_ref.group)?
+    } else {
+        None
+    };
+    let group = match found {
+        Some(group) if !group.options.is_empty() => group,
+        found => {
+            // A missing or empty group uses the reference's fallback, if any
+            if let Some(fallback) = &lib_ref.fallback {
+                let (text, chosen) = fallback_choice(lib_ref, fallback);
+                return Ok((text, vec![chosen]));
+            }
+            return match (found, ctx.on_missing_ref) {
+                (Some(_), _) => Err(RenderError::EmptyGroup(lib_ref.group.clone())),
+                (None, MissingRefPolicy::Error) => Err(RenderError::GroupNotFound(
+                    match &lib_ref.library {
+                        Some(library) => format!("{}:{}", library, lib_ref.group),
+                        None => lib_ref.group.clone(),
+                    },
+                )),
+                (None, MissingRefPolicy::EmptyString) => Ok((String::new(), Vec::new())),
+                (None, MissingRefPolicy::KeepLiteral) => {
+                    let mut literal = String::new();
+                    library_ref_to_source(lib_ref, &mut literal);
+                    Ok((literal, Vec::new()))
+                }
+            };
+        }
+    };
+    // The group's own name, which differs from the reference when matched
+    // case-insensitively
+    let group_name = &group.name;
 
     // Check for circular reference
     if ctx.eval_stack.contains(group_name) {
@@ -199,43 +1076,137 @@ fn resolve_library_ref<R: Rng>(
         )));
     }
 
-    // Find the group
-    // TODO: Handle lib_ref.library for multi-library support
-    let group = ctx
-        .library
-        .find_group(group_name)
-        .ok_or_else(|| RenderError::GroupNotFound(group_name.clone()))?;
-
-    if group.options.is_empty() {
-        return Err(RenderError::EmptyGroup(group_name.clone()));
+    // A pinned choice is used as-is, since its nested grammar was already
+    // evaluated when it was first picked
+    if let Some(text) = ctx.take_pinned(&format!("@{}", group_name)) {
+        let output_text = match lib_ref.transform {
+            Some(transform) => transform.apply(&text),
+            None => text.clone(),
+        };
+        let chosen = ChosenOption {
+            group_name: group_name.clone(),
+            library_name: lib_ref.library.clone(),
+            option_text: text,
+            alias: None,
+            transform: lib_ref.transform,
+            used_fallback: false,
+            parent: None,
+            in_inline_options: false,
+        };
+        return Ok((output_text, vec![chosen]));
     }
 
-    // Pick a random option
-    let idx = ctx.rng.random_range(0..group.options.len());
-    let option_text = &group.options[idx];
-
     // Push to eval stack for cycle detection
     ctx.eval_stack.push(group_name.clone());
 
+    // Pick a random option; an alias renders as its canonical form
+    let mut idx = ctx.pick_index(group.options.len());
+    let mut outer_rng = None;
+    if let Some(&salt) = ctx.group_salts.get(group_name) {
+        // Evaluate the unsalted pick and drop it, so the main RNG advances as
+        // it would without the salt. The salted pick and its nested grammar
+        // then draw from an RNG of their own, seeded per render and group.
+        eval_option_text(group.canonical_option(&group.options[idx]), ctx, None)?;
+        let bytes = ctx
+            .salt_seed
+            .to_le_bytes()
+            .into_iter()
+            .chain(group_name.bytes())
+            .chain(salt.to_le_bytes())
+            .chain(idx.to_le_bytes());
+        let salted = R::seed_from_u64(fnv1a(bytes));
+        outer_rng = Some(ctx.node_rng.replace(salted));
+        idx = ctx.pick_index(group.options.len());
+    }
+    let picked = &group.options[idx];
+    let option_text = group.canonical_option(picked);
+    let alias = (option_text != picked).then(|| picked.clone());
+
     // Parse and evaluate the option (lazy evaluation for nested grammar)
-    let evaluated_text = eval_option_text(option_text, ctx)?;
+    let mut nested = Vec::new();
+    let evaluated_text = eval_option_text(option_text, ctx, Some(&mut nested))?;
 
     // Pop from eval stack
     ctx.eval_stack.pop();
+    if let Some(outer_rng) = outer_rng {
+        ctx.node_rng = outer_rng;
+    }
+
+    // Apply any case transform; provenance keeps the untransformed text
+    let output_text = match lib_ref.transform {
+        Some(transform) => transform.apply(&evaluated_text),
+        None => evaluated_text.clone(),
+    };
 
     let chosen = ChosenOption {
         group_name: group_name.clone(),
         library_name: lib_ref.library.clone(),
-        option_text: evaluated_text.clone(),
+        option_text: evaluated_text,
+        alias,
+        transform: lib_ref.transform,
+        used_fallback: false,
+        parent: None,
+        in_inline_options: false,
     };
 
-    Ok((evaluated_text, chosen))
+    // The nested choices follow their parent, at index 0
+    let mut picks = vec![chosen];
+    picks.extend(nested.into_iter().map(|mut nested| {
+        nested.parent = Some(nested.parent.map_or(0, |parent| parent + 1));
+        nested
+    }));
+    Ok((output_text, picks))
+}
+
+/// Find the group a reference names.
+///
+/// An exact match always wins. With `case_insensitive_refs` enabled, a
+/// reference can also match a single group whose name differs only by case.
+fn lookup_group<'a, R: PickRng>(
+    ctx: &EvalContext<'a, R>,
+    name: &str,
+) -> Result<Option<&'a PromptGroup>, RenderError> {
+    if let Some(group) = ctx.library.find_group(name) {
+        return Ok(Some(group));
+    }
+    if !ctx.case_insensitive_refs {
+        return Ok(None);
+    }
+
+    match ctx.library.find_group_ci(name).as_slice() {
+        [] => Ok(None),
+        [group] => Ok(Some(group)),
+        _ => Err(RenderError::AmbiguousGroupCase(name.to_string())),
+    }
+}
+
+/// The output and provenance for a reference that used its fallback text.
+///
+/// The fallback is literal: it is not parsed for nested grammar.
+fn fallback_choice(lib_ref: &LibraryRef, fallback: &str) -> (String, ChosenOption) {
+    let output_text = match lib_ref.transform {
+        Some(transform) => transform.apply(fallback),
+        None => fallback.to_string(),
+    };
+    let chosen = ChosenOption {
+        group_name: lib_ref.group.clone(),
+        library_name: lib_ref.library.clone(),
+        option_text: fallback.to_string(),
+        alias: None,
+        transform: lib_ref.transform,
+        used_fallback: true,
+        parent: None,
+        in_inline_options: false,
+    };
+    (output_text, chosen)
 }
 
-/// Evaluate option text, which may contain nested grammar.
-fn eval_option_text<R: Rng>(
+/// Evaluate option text, which may contain nested grammar, adding the
+/// choices made to `chosen_options` if given.
+fn eval_option_text<R: PickRng>(
     option_text: &str,
     ctx: &mut EvalContext<'_, R>,
+    chosen_options: Option<&mut Vec<ChosenOption>>,
 ) -> Result<String, RenderError> {
     // Parse the option text as a template
     let ast =
@@ -243,17 +1214,32 @@ fn eval_option_text<R: Rng>(
 
     let mut output = String::new();
     let mut temp_chosen = Vec::new();
+    let chosen_options = chosen_options.unwrap_or(&mut temp_chosen);
 
     for (node, _span) in &ast.nodes {
-        let text = eval_node(node, ctx, &mut temp_chosen)?;
+        let text = eval_node(node, ctx, chosen_options)?;
         output.push_str(&text);
     }
 
     Ok(output)
 }
 
+/// Evaluate `{text?chance}`: the text `chance` percent of the time, else
+/// nothing. One number is drawn from the RNG either way.
+fn eval_maybe_option<R: PickRng>(
+    maybe: &MaybeOption,
+    ctx: &mut EvalContext<'_, R>,
+    chosen_options: &mut Vec<ChosenOption>,
+) -> Result<String, RenderError> {
+    if ctx.pick_index(100) < usize::from(maybe.chance) {
+        eval_option_text(&maybe.text, ctx, Some(chosen_options))
+    } else {
+        Ok(String::new())
+    }
+}
+
 /// Evaluate inline options {a|b|c}.
-fn eval_inline_options<R: Rng>(
+fn eval_inline_options<R: PickRng>(
     options: &[OptionItem],
     ctx: &mut EvalContext<'_, R>,
     chosen_options: &mut Vec<ChosenOption>,
@@ -263,14 +1249,14 @@ fn eval_inline_options<R: Rng>(
     }
 
     // Pick a random option
-    let idx = ctx.rng.random_range(0..options.len());
+    let idx = ctx.pick_index(options.len());
     let option = &options[idx];
 
     match option {
         OptionItem::Text(text) => {
             // Plain text option - but it might still contain grammar like @Hair
             // Parse and evaluate it
-            eval_option_text(text, ctx)
+            eval_option_text(text, ctx, None)
         }
         OptionItem::Nested(nodes) => {
             // Already-parsed nested nodes
@@ -310,26 +1296,207 @@ mod tests {
         lib
     }
 
+    fn render_hair(source: &str, seed: u64, stable: bool) -> String {
+        let mut lib = make_test_library();
+        lib.groups[0].options = (0..50).map(|i| format!("hair {i}")).collect();
+        let template = PromptTemplate::new("test", parse_template(source).unwrap());
+        let mut ctx = EvalContext::with_seed(&lib, seed);
+        ctx.stable_node_seeds = stable;
+        let result = render(&template, &mut ctx).unwrap();
+        result
+            .chosen_options
+            .into_iter()
+            .find(|c| c.group_name == "Hair")
+            .unwrap()
+            .option_text
+    }
+
     #[test]
-    fn test_render_plain_text() {
-        let lib = make_test_library();
-        let ast = parse_template("Hello, world!").unwrap();
-        let template = PromptTemplate::new("test", ast);
-        let mut ctx = EvalContext::with_seed(&lib, 42);
+    fn test_stable_node_seeds_survive_inserted_nodes() {
+        for seed in 0..20 {
+            let hair = render_hair("@Hair", seed, true);
+            assert_eq!(render_hair("portrait of @Hair", seed, true), hair);
+            assert_eq!(render_hair("{a|b|c} @Color, @Hair", seed, true), hair);
+        }
+    }
 
-        let result = render(&template, &mut ctx).unwrap();
-        assert_eq!(result.text, "Hello, world!");
-        assert!(result.chosen_options.is_empty());
+    #[test]
+    fn test_default_mode_picks_shift_with_inserted_nodes() {
+        // Literal text draws nothing, so it never shifts picks
+        for seed in 0..20 {
+            assert_eq!(
+                render_hair("portrait of @Hair", seed, false),
+                render_hair("@Hair", seed, false)
+            );
+        }
+        // A random node before the reference does
+        assert!((0..20).any(|seed| {
+            render_hair("{a|b|c} @Color, @Hair", seed, false) != render_hair("@Hair", seed, false)
+        }));
     }
 
     #[test]
-    fn test_render_library_ref() {
-        let lib = make_test_library();
-        let ast = parse_template("A girl with @Hair").unwrap();
-        let template = PromptTemplate::new("test", ast);
-        let mut ctx = EvalContext::with_seed(&lib, 42);
+    fn test_stable_node_seeds_repeated_refs_pick_independently() {
+        let mut lib = make_test_library();
+        lib.groups[0].options = (0..50).map(|i| format!("hair {i}")).collect();
+        let template = PromptTemplate::new("test", parse_template("@Hair @Hair").unwrap());
 
-        let result = render(&template, &mut ctx).unwrap();
+        let differ = (0..20).any(|seed| {
+            let mut ctx = EvalContext::with_seed(&lib, seed);
+            ctx.stable_node_seeds = true;
+            let result = render(&template, &mut ctx).unwrap();
+            result.chosen_options[0].option_text != result.chosen_options[1].option_text
+        });
+        assert!(differ);
+    }
+
+    #[test]
+    fn test_stable_node_seeds_render_to_writer_matches_render() {
+        let lib = make_test_library();
+        let template = PromptTemplate::new("test", parse_template("@Hair, {x|y} @Eyes").unwrap());
+
+        let mut ctx = EvalContext::with_seed(&lib, 9);
+        ctx.stable_node_seeds = true;
+        let expected = render(&template, &mut ctx).unwrap().text;
+
+        let mut ctx = EvalContext::with_seed(&lib, 9);
+        ctx.stable_node_seeds = true;
+        let mut written = Vec::new();
+        render_to_writer(&template, &mut ctx, &mut written).unwrap();
+        assert_eq!(String::from_utf8(written).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_render_plain_text() {
+        let lib = make_test_library();
+        let ast = parse_template("Hello, world!").unwrap();
+        let template = PromptTemplate::new("test", ast);
+        let mut ctx = EvalContext::with_seed(&lib, 42);
+
+        let result = render(&template, &mut ctx).unwrap();
+        assert_eq!(result.text, "Hello, world!");
+        assert!(result.chosen_options.is_empty());
+    }
+
+    #[test]
+    fn test_render_to_writer_matches_render() {
+        let lib = make_test_library();
+        let source = "{|\n}{|big}, @Hair and  @Eyes,\n\n  {tall|short|}, {|x} # note\n{|y}\n";
+        let ast = parse_template(source).unwrap();
+        let template = PromptTemplate::new("test", ast);
+
+        let flags = [(false, false), (true, false), (false, true), (true, true)];
+        for (seed, (skip, collapse)) in (0..20).flat_map(|seed| flags.map(|flag| (seed, flag))) {
+            let context = || {
+                let mut ctx = EvalContext::with_seed(&lib, seed);
+                ctx.skip_empty_separators = skip;
+                ctx.collapse_whitespace = collapse;
+                ctx
+            };
+            let expected = render(&template, &mut context()).unwrap();
+
+            let mut out = Vec::new();
+            let summary = render_to_writer(&template, &mut context(), &mut out).unwrap();
+
+            assert_eq!(String::from_utf8(out).unwrap(), expected.text);
+            assert_eq!(summary.chosen_options, expected.chosen_options);
+        }
+    }
+
+    fn render_comments(source: &str, strip: bool) -> String {
+        let lib = make_test_library();
+        let template = PromptTemplate::new("test", parse_template(source).unwrap());
+        let mut ctx = EvalContext::with_seed(&lib, 1);
+        ctx.strip_comment_lines = strip;
+        render(&template, &mut ctx).unwrap().text
+    }
+
+    #[test]
+    fn test_strip_comment_lines() {
+        let source = "portrait\n# lighting notes\nsoft light";
+        assert_eq!(render_comments(source, true), "portrait\nsoft light");
+        assert_eq!(render_comments(source, false), "portrait\n\nsoft light");
+
+        // Indented, consecutive, first and last comment lines
+        let source = "# header\nportrait\n  # one\n\t# two\nsoft light\n# footer";
+        assert_eq!(render_comments(source, true), "portrait\nsoft light");
+        assert_eq!(render_comments(source, false), "\nportrait\n  \n\t\nsoft light\n");
+    }
+
+    #[test]
+    fn test_strip_comment_lines_keeps_trailing_comments() {
+        // A comment after other text on its line keeps the line break
+        let source = "portrait # note\nsoft light";
+        assert_eq!(render_comments(source, true), "portrait \nsoft light");
+        assert_eq!(render_comments(source, true), render_comments(source, false));
+    }
+
+    #[test]
+    fn test_strip_comment_lines_in_render_to_writer() {
+        let lib = make_test_library();
+        let ast = parse_template("@Hair\n# note\n@Eyes").unwrap();
+        let template = PromptTemplate::new("test", ast);
+
+        let expected = render(&template, &mut EvalContext::with_seed(&lib, 3)).unwrap();
+        let mut out = Vec::new();
+        render_to_writer(&template, &mut EvalContext::with_seed(&lib, 3), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), expected.text);
+        assert_eq!(expected.text.lines().count(), 2);
+    }
+
+    #[test]
+    fn test_render_cancellable_stops_after_n_nodes() {
+        let lib = make_test_library();
+        // Five top-level nodes: @Hair, " and ", @Eyes, ", ", {tall|short}
+        let ast = parse_template("@Hair and @Eyes, {tall|short}").unwrap();
+        let template = PromptTemplate::new("test", ast);
+
+        let checks = std::cell::Cell::new(0);
+        let cancel_after_three = || {
+            checks.set(checks.get() + 1);
+            checks.get() > 3
+        };
+        let mut ctx = EvalContext::with_seed(&lib, 1);
+        let result = render_cancellable(&template, &mut ctx, Some(&cancel_after_three));
+        assert!(matches!(result, Err(RenderError::Cancelled)));
+        assert_eq!(checks.get(), 4);
+
+        // A callback that never cancels renders the same as `render`
+        let expected = render(&template, &mut EvalContext::with_seed(&lib, 1)).unwrap();
+        let never = || false;
+        let mut ctx = EvalContext::with_seed(&lib, 1);
+        let result = render_cancellable(&template, &mut ctx, Some(&never)).unwrap();
+        assert_eq!(result.text, expected.text);
+    }
+
+    #[test]
+    fn test_render_with_seed_label_is_deterministic() {
+        let lib = make_test_library();
+        let template = PromptTemplate::new("test", parse_template("@Hair {a|b|c|d}").unwrap());
+
+        for label in ["sunset", "blue moon", ""] {
+            let first = render(&template, &mut EvalContext::with_seed_str(&lib, label)).unwrap();
+            let second = render(&template, &mut EvalContext::with_seed_str(&lib, label)).unwrap();
+            assert_eq!(first.text, second.text);
+
+            let mut numeric = EvalContext::with_seed(&lib, seed_from_label(label));
+            assert_eq!(render(&template, &mut numeric).unwrap().text, first.text);
+        }
+
+        // Pinned so saved labels keep giving the same prompts
+        assert_eq!(seed_from_label(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(seed_from_label("a"), 0xaf63_dc4c_8601_ec8c);
+        assert_ne!(seed_from_label("sunset"), seed_from_label("Sunset"));
+    }
+
+    #[test]
+    fn test_render_library_ref() {
+        let lib = make_test_library();
+        let ast = parse_template("A girl with @Hair").unwrap();
+        let template = PromptTemplate::new("test", ast);
+        let mut ctx = EvalContext::with_seed(&lib, 42);
+
+        let result = render(&template, &mut ctx).unwrap();
         assert!(result.text.starts_with("A girl with "));
         assert!(
             result.text.contains("blonde hair")
@@ -354,6 +1521,361 @@ mod tests {
         assert!(result.text == "amber" || result.text == "violet");
     }
 
+    #[test]
+    fn test_render_transformed_library_ref() {
+        let mut lib = Library::new("Test");
+        lib.groups
+            .push(PromptGroup::with_options("Hair", vec!["Blonde hair"]));
+
+        let ast = parse_template("@{Hair | upper}, @{Hair|lower}, @{Hair | title}").unwrap();
+        let template = PromptTemplate::new("test", ast);
+        let mut ctx = EvalContext::with_seed(&lib, 42);
+
+        let result = render(&template, &mut ctx).unwrap();
+        assert_eq!(result.text, "BLONDE HAIR, blonde hair, Blonde Hair");
+
+        // Provenance records the option as picked, before the transform
+        assert_eq!(result.chosen_options.len(), 3);
+        for chosen in &result.chosen_options {
+            assert_eq!(chosen.option_text, "Blonde hair");
+        }
+    }
+
+    #[test]
+    fn test_fallback_used_for_missing_group() {
+        let lib = make_test_library();
+        let ast = parse_template(r#"@Hat ?? "no hat""#).unwrap();
+        let template = PromptTemplate::new("test", ast);
+        let mut ctx = EvalContext::with_seed(&lib, 42);
+
+        let result = render(&template, &mut ctx).unwrap();
+        assert_eq!(result.text, "no hat");
+        assert_eq!(result.chosen_options[0].group_name, "Hat");
+        assert_eq!(result.chosen_options[0].option_text, "no hat");
+
+        // Without a fallback a missing group is still an error
+        let template = PromptTemplate::new("test", parse_template("@Hat").unwrap());
+        let result = render(&template, &mut ctx);
+        assert!(matches!(result, Err(RenderError::GroupNotFound(name)) if name == "Hat"));
+    }
+
+    #[test]
+    fn test_fallback_used_for_empty_group() {
+        let mut lib = make_test_library();
+        lib.groups.push(PromptGroup::new("Hat", vec![]));
+
+        let ast = parse_template(r#"@{Hat | upper} ?? "no hat""#).unwrap();
+        let template = PromptTemplate::new("test", ast);
+        let mut ctx = EvalContext::with_seed(&lib, 42);
+
+        let result = render(&template, &mut ctx).unwrap();
+        assert_eq!(result.text, "NO HAT");
+    }
+
+    #[test]
+    fn test_fallback_ignored_when_group_has_options() {
+        let lib = make_test_library();
+        let ast = parse_template(r#"@Eyes ?? "no eyes""#).unwrap();
+        let template = PromptTemplate::new("test", ast);
+        let mut ctx = EvalContext::with_seed(&lib, 42);
+
+        let result = render(&template, &mut ctx).unwrap();
+        assert!(result.text == "blue eyes" || result.text == "green eyes");
+    }
+
+    #[test]
+    fn test_summary_lists_each_choice() {
+        let mut lib = make_test_library();
+        lib.groups[0].options = vec!["blonde hair".to_string()];
+        lib.groups[1].options = vec!["blue eyes".to_string()];
+        let ast = parse_template(r#"@Hair, @{Eyes | upper}, @Hat ?? "bare head""#).unwrap();
+        let template = PromptTemplate::new("test", ast);
+        let mut ctx = EvalContext::with_seed(&lib, 42);
+
+        let result = render(&template, &mut ctx).unwrap();
+        assert_eq!(result.text, "blonde hair, BLUE EYES, bare head");
+        assert_eq!(
+            result.summary(),
+            "Hair=blonde hair, Eyes=BLUE EYES, Hat=bare head (fallback)"
+        );
+        assert_eq!(result.chosen_options[1].option_text, "blue eyes");
+    }
+
+    #[test]
+    fn test_nested_choice_links_to_parent() {
+        let mut lib = make_test_library();
+        lib.groups.push(PromptGroup::with_options("FancyEyes", vec!["@Color eyes"]));
+        lib.groups.push(PromptGroup::with_options("Look", vec!["@FancyEyes and @Hair"]));
+        let template = PromptTemplate::new("test", parse_template("@Hair, @Look").unwrap());
+
+        let result = render(&template, &mut EvalContext::with_seed(&lib, 42)).unwrap();
+        let trace: Vec<(&str, Option<usize>)> = result
+            .chosen_options
+            .iter()
+            .map(|c| (c.group_name.as_str(), c.parent))
+            .collect();
+        assert_eq!(
+            trace,
+            [
+                ("Hair", None),
+                ("Look", None),
+                ("FancyEyes", Some(1)),
+                ("Color", Some(2)),
+                ("Hair", Some(1)),
+            ]
+        );
+        let eyes = &result.chosen_options[2];
+        let color = &result.chosen_options[3];
+        assert_eq!(eyes.option_text, format!("{} eyes", color.option_text));
+
+        // Replaying only pins the top-level choices
+        let overrides = result.to_slot_overrides();
+        assert_eq!(overrides["@Hair"].len(), 1);
+        assert!(!overrides.contains_key("@Color"));
+    }
+
+    #[test]
+    fn test_summary_empty_without_choices() {
+        let lib = make_test_library();
+        let template = PromptTemplate::new("test", parse_template("plain text").unwrap());
+        let result = render(&template, &mut EvalContext::with_seed(&lib, 1)).unwrap();
+        assert_eq!(result.summary(), "");
+    }
+
+    #[test]
+    fn test_group_salt_rerolls_only_that_group() {
+        let mut lib = make_test_library();
+        lib.groups.push(PromptGroup::with_options(
+            "Mood",
+            vec!["calm", "angry", "happy", "sad", "tired", "bored"],
+        ));
+        let ast = parse_template("@Hair, @Mood, @Eyes, {tall|short}").unwrap();
+        let template = PromptTemplate::new("test", ast);
+
+        let base = render(&template, &mut EvalContext::with_seed(&lib, 7)).unwrap();
+        let mut moods = std::collections::HashSet::new();
+        for salt in 1..20 {
+            let mut ctx = EvalContext::with_seed(&lib, 7);
+            ctx.group_salts.insert("Mood".to_string(), salt);
+            let result = render(&template, &mut ctx).unwrap();
+
+            // Everything but the salted group is unchanged
+            assert_eq!(result.chosen_options[0], base.chosen_options[0]);
+            assert_eq!(result.chosen_options[2], base.chosen_options[2]);
+            assert!(result.text.ends_with(base.text.rsplit(", ").next().unwrap()));
+            moods.insert(result.chosen_options[1].option_text.clone());
+        }
+        assert!(moods.len() > 1, "salts should pick different moods");
+
+        // The same salt re-picks differently under other seeds, even where
+        // the unsalted pick is the same
+        let mut salted_by_base: HashMap<String, std::collections::HashSet<String>> =
+            HashMap::new();
+        for seed in 0..60 {
+            let base = render(&template, &mut EvalContext::with_seed(&lib, seed)).unwrap();
+            let mut ctx = EvalContext::with_seed(&lib, seed);
+            ctx.group_salts.insert("Mood".to_string(), 1);
+            let salted = render(&template, &mut ctx).unwrap();
+            salted_by_base
+                .entry(base.chosen_options[1].option_text.clone())
+                .or_default()
+                .insert(salted.chosen_options[1].option_text.clone());
+        }
+        assert!(salted_by_base.values().all(|moods| moods.len() > 1));
+    }
+
+    #[test]
+    fn test_group_salt_keeps_unsalted_errors() {
+        let mut lib = make_test_library();
+        lib.groups.push(PromptGroup::with_options("Mood", vec!["calm", "@Nope"]));
+        let template = PromptTemplate::new("test", parse_template("@Mood").unwrap());
+
+        // A salt doesn't hide an error the unsalted pick would give
+        for seed in 0..20 {
+            let unsalted = render(&template, &mut EvalContext::with_seed(&lib, seed));
+            let mut ctx = EvalContext::with_seed(&lib, seed);
+            ctx.group_salts.insert("Mood".to_string(), 3);
+            if unsalted.is_err() {
+                assert!(render(&template, &mut ctx).is_err(), "seed {seed}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_group_salt_keeps_nested_picks_to_itself() {
+        let mut lib = make_test_library();
+        lib.groups.push(PromptGroup::with_options(
+            "Look",
+            vec!["{calm|wild|sly} @Color", "{big|small} grin", "@Color stare"],
+        ));
+        let ast = parse_template("@Look, @Eyes, @Hair").unwrap();
+        let template = PromptTemplate::new("test", ast);
+
+        for seed in 0..20 {
+            let base = render(&template, &mut EvalContext::with_seed(&lib, seed)).unwrap();
+            for salt in 1..10 {
+                let mut ctx = EvalContext::with_seed(&lib, seed);
+                ctx.group_salts.insert("Look".to_string(), salt);
+                let result = render(&template, &mut ctx).unwrap();
+
+                let (_, rest) = result.text.split_once(", ").unwrap();
+                assert!(base.text.ends_with(rest), "{} vs {}", base.text, result.text);
+            }
+        }
+    }
+
+    #[test]
+    fn test_missing_ref_policies() {
+        let lib = make_test_library();
+        let ast = parse_template("@Hair and @Hat, @{Scarf | upper}!").unwrap();
+        let template = PromptTemplate::new("test", ast);
+
+        let render_with = |policy| {
+            let mut ctx = EvalContext::with_seed(&lib, 42);
+            ctx.on_missing_ref = policy;
+            render(&template, &mut ctx)
+        };
+
+        let result = render_with(MissingRefPolicy::Error);
+        assert!(matches!(result, Err(RenderError::GroupNotFound(name)) if name == "Hat"));
+
+        let result = render_with(MissingRefPolicy::EmptyString).unwrap();
+        assert!(result.text.ends_with(" and , !"));
+        assert_eq!(result.chosen_options.len(), 1);
+
+        let result = render_with(MissingRefPolicy::KeepLiteral).unwrap();
+        assert!(result.text.ends_with(" and @Hat, @{Scarf | upper}!"));
+        assert_eq!(result.chosen_options.len(), 1);
+    }
+
+    #[test]
+    fn test_missing_ref_policy_keeps_fallback() {
+        let lib = make_test_library();
+        let template = PromptTemplate::new("test", parse_template(r#"@Hat ?? "cap""#).unwrap());
+        let mut ctx = EvalContext::with_seed(&lib, 42);
+        ctx.on_missing_ref = MissingRefPolicy::KeepLiteral;
+
+        assert_eq!(render(&template, &mut ctx).unwrap().text, "cap");
+    }
+
+    #[test]
+    fn test_render_qualified_refs() {
+        let lib = make_test_library();
+        let render_source = |source: &str, policy| {
+            let template = PromptTemplate::new("test", parse_template(source).unwrap());
+            let mut ctx = EvalContext::with_seed(&lib, 42);
+            ctx.on_missing_ref = policy;
+            render(&template, &mut ctx)
+        };
+
+        // The library's own name or id resolves as usual
+        for source in [r#"@"Test Library:Eyes""#, r#"@"test-lib:Eyes""#] {
+            let text = render_source(source, MissingRefPolicy::Error).unwrap().text;
+            assert!(text.ends_with(" eyes"), "{source}");
+        }
+
+        // Another library's group is missing here
+        let result = render_source(r#"@"Other:Eyes""#, MissingRefPolicy::Error);
+        assert!(matches!(result, Err(RenderError::GroupNotFound(name)) if name == "Other:Eyes"));
+        let result = render_source(r#"a @"Other:Eyes""#, MissingRefPolicy::EmptyString);
+        assert_eq!(result.unwrap().text, "a ");
+        let result = render_source(r#"@"Other:Eyes" ?? "eyes""#, MissingRefPolicy::Error);
+        assert_eq!(result.unwrap().text, "eyes");
+    }
+
+    #[test]
+    fn test_alias_renders_canonical_option() {
+        let mut lib = make_test_library();
+        let mut hair = PromptGroup::with_options("Blond", vec!["blond hair", "blonde hair"]);
+        hair.aliases.insert("blond hair".to_string(), "blonde hair".to_string());
+        lib.groups.push(hair);
+
+        let template = PromptTemplate::new("test", parse_template("@Blond").unwrap());
+        let mut seen_alias = false;
+        for seed in 0..20 {
+            let mut ctx = EvalContext::with_seed(&lib, seed);
+            let result = render(&template, &mut ctx).unwrap();
+
+            assert_eq!(result.text, "blonde hair");
+            let chosen = &result.chosen_options[0];
+            assert_eq!(chosen.option_text, "blonde hair");
+            if let Some(alias) = &chosen.alias {
+                assert_eq!(alias, "blond hair");
+                seen_alias = true;
+            }
+        }
+        assert!(seen_alias, "some seed should pick the alias");
+    }
+
+    #[test]
+    fn test_case_insensitive_refs_off_by_default() {
+        let lib = make_test_library();
+        let template = PromptTemplate::new("test", parse_template("@hair").unwrap());
+        let mut ctx = EvalContext::with_seed(&lib, 42);
+
+        let result = render(&template, &mut ctx);
+        assert!(matches!(result, Err(RenderError::GroupNotFound(name)) if name == "hair"));
+    }
+
+    #[test]
+    fn test_case_insensitive_refs_prefer_exact_match() {
+        let mut lib = make_test_library();
+        lib.groups.push(PromptGroup::with_options("hair", vec!["lowercase hair"]));
+        let template = PromptTemplate::new("test", parse_template("@hair, @EYES").unwrap());
+        let mut ctx = EvalContext::with_seed(&lib, 42);
+        ctx.case_insensitive_refs = true;
+
+        let result = render(&template, &mut ctx).unwrap();
+        assert!(result.text.starts_with("lowercase hair, "));
+        assert_eq!(result.chosen_options[0].group_name, "hair");
+        // The case-insensitive match reports the group's real name
+        assert_eq!(result.chosen_options[1].group_name, "Eyes");
+    }
+
+    #[test]
+    fn test_case_insensitive_refs_ambiguous() {
+        let mut lib = make_test_library();
+        lib.groups.push(PromptGroup::with_options("HAIR", vec!["loud hair"]));
+        let template = PromptTemplate::new("test", parse_template("@hAiR").unwrap());
+        let mut ctx = EvalContext::with_seed(&lib, 42);
+        ctx.case_insensitive_refs = true;
+
+        let result = render(&template, &mut ctx);
+        assert!(matches!(result, Err(RenderError::AmbiguousGroupCase(name)) if name == "hAiR"));
+    }
+
+    #[test]
+    fn test_required_slot_needs_value() {
+        let lib = make_test_library();
+        let template = PromptTemplate::new("test", parse_template("{{ Scene! }}").unwrap());
+
+        let mut ctx = EvalContext::with_seed(&lib, 42);
+        let result = render(&template, &mut ctx);
+        assert!(matches!(result, Err(RenderError::MissingRequiredSlot(name)) if name == "Scene"));
+
+        ctx.set_slot("Scene", "a forest");
+        assert_eq!(render(&template, &mut ctx).unwrap().text, "a forest");
+    }
+
+    #[test]
+    fn test_optional_slot_without_value_keeps_placeholder() {
+        let lib = make_test_library();
+        let template = PromptTemplate::new("test", parse_template("in {{ Scene }}").unwrap());
+        let mut ctx = EvalContext::with_seed(&lib, 42);
+
+        assert_eq!(render(&template, &mut ctx).unwrap().text, "in {{ Scene }}");
+    }
+
+    #[test]
+    fn test_case_transform_unicode() {
+        use crate::ast::CaseKind;
+
+        assert_eq!(CaseKind::Upper.apply("straße"), "STRASSE");
+        assert_eq!(CaseKind::Lower.apply("ÉCOLE Ünïcode"), "école ünïcode");
+        assert_eq!(CaseKind::Title.apply("élan  VITAL"), "Élan  Vital");
+        assert_eq!(CaseKind::Title.apply(""), "");
+    }
+
     #[test]
     fn test_render_deterministic_with_seed() {
         let lib = make_test_library();
@@ -403,6 +1925,52 @@ mod tests {
         assert_eq!(result.text, "Hello {{ Name }}!");
     }
 
+    #[test]
+    fn test_render_collapse_whitespace_with_empty_slot() {
+        let lib = make_test_library();
+        let ast = parse_template("Hello {{ Name }}!").unwrap();
+        let template = PromptTemplate::new("test", ast);
+
+        let mut ctx = EvalContext::with_seed(&lib, 42);
+        ctx.set_slot("Name", "");
+        let result = render(&template, &mut ctx).unwrap();
+        assert_eq!(result.text, "Hello !");
+        assert_eq!(result.normalized().text, "Hello!");
+
+        let mut ctx = EvalContext::with_seed(&lib, 42);
+        ctx.set_slot("Name", "");
+        ctx.collapse_whitespace = true;
+        let result = render(&template, &mut ctx).unwrap();
+        assert_eq!(result.text, "Hello!");
+    }
+
+    #[test]
+    fn test_render_skip_empty_separators() {
+        let mut lib = make_test_library();
+        lib.groups.push(PromptGroup::with_options("Adj", vec![""]));
+        let ast = parse_template("@Adj @Adj, @Hair, @Adj, {|} portrait, @Adj").unwrap();
+        let template = PromptTemplate::new("test", ast);
+
+        let mut ctx = EvalContext::with_seed(&lib, 42);
+        ctx.skip_empty_separators = true;
+        let result = render(&template, &mut ctx).unwrap();
+        let hair = &result.chosen_options[2].option_text;
+        assert_eq!(result.text, format!("{hair}, portrait"));
+
+        // Off by default
+        let mut ctx = EvalContext::with_seed(&lib, 42);
+        let result = render(&template, &mut ctx).unwrap();
+        assert_eq!(result.text, format!(" , {hair}, ,  portrait, "));
+    }
+
+    #[test]
+    fn test_normalize_whitespace() {
+        assert_eq!(normalize_whitespace("  a   red  ball  "), "a red ball");
+        assert_eq!(normalize_whitespace("red hair , , blue eyes ,"), "red hair, blue eyes");
+        assert_eq!(normalize_whitespace(", portrait, "), "portrait");
+        assert_eq!(normalize_whitespace("line one  \n\tline two ."), "line one\nline two.");
+    }
+
     #[test]
     fn test_render_slot_with_grammar() {
         let lib = make_test_library();
@@ -416,6 +1984,311 @@ mod tests {
         assert!(result.text.contains("hair warrior"));
     }
 
+    #[test]
+    fn test_slot_overrides_replay_a_render() {
+        let mut lib = make_test_library();
+        lib.groups.push(PromptGroup::with_options(
+            "Look",
+            vec!["@Color {plain|striped} shirt", "@Hair and @Eyes"],
+        ));
+        let source = "@Look, @{Hair | upper}, {a|b|c} {a|b|c} @Hair {{ Scene }} @Missing ?? \"none\"";
+        let template = PromptTemplate::new("test", parse_template(source).unwrap());
+
+        for seed in 0..20 {
+            let mut ctx = EvalContext::with_seed(&lib, seed);
+            ctx.set_slot("Scene", "in a {dark|bright} room");
+            let original = render(&template, &mut ctx).unwrap();
+            let overrides = original.to_slot_overrides();
+            assert_eq!(overrides["Scene"], vec!["in a {dark|bright} room"]);
+            assert_eq!(overrides["@Hair"].len(), 2);
+            assert_eq!(overrides["{a|b|c}"].len(), 2);
+            assert!(!overrides.contains_key("@Missing"));
+
+            for replay_seed in [seed + 100, seed + 200] {
+                let mut replay = EvalContext::with_seed(&lib, replay_seed);
+                replay.apply_overrides(overrides.clone());
+                assert_eq!(render(&template, &mut replay).unwrap().text, original.text);
+            }
+        }
+
+        // Choices inside inline options are replayed with the option's text
+        for source in ["{@Hair|@Hair} / @Hair", "{@Hair?} @Hair", "{{a|b}|c} {a|b}"] {
+            let template = PromptTemplate::new("test", parse_template(source).unwrap());
+            for seed in 0..20 {
+                let original = render(&template, &mut EvalContext::with_seed(&lib, seed)).unwrap();
+                let mut replay = EvalContext::with_seed(&lib, seed + 100);
+                replay.apply_overrides(original.to_slot_overrides());
+                assert_eq!(render(&template, &mut replay).unwrap().text, original.text);
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_slot_value_with_inline_options() {
+        let lib = make_test_library();
+        let template = PromptTemplate::new("test", parse_template("{{ mood }}").unwrap());
+
+        for seed in 0..10 {
+            let mut ctx = EvalContext::with_seed(&lib, seed);
+            ctx.set_slot("mood", "very {calm|angry}");
+            let text = render(&template, &mut ctx).unwrap().text;
+            assert!(text == "very calm" || text == "very angry", "{text}");
+        }
+    }
+
+    #[test]
+    fn test_render_slot_value_with_slot_is_rejected() {
+        let lib = make_test_library();
+        let template = PromptTemplate::new("test", parse_template("{{ a }}").unwrap());
+        let mut ctx = EvalContext::with_seed(&lib, 42);
+        ctx.set_slot("a", "more {{ a }}");
+
+        let result = render(&template, &mut ctx);
+        assert!(matches!(result, Err(RenderError::SlotInSlotValue(name)) if name == "a"));
+
+        // Also when the slot is nested in options
+        for value in ["{x {{ a }}|x {{ a }}}", "{x|{y|{{ a }}}}", "{x {{ a }}?}"] {
+            ctx.set_slot("a", value);
+            let result = render(&template, &mut ctx);
+            assert!(
+                matches!(result, Err(RenderError::SlotInSlotValue(ref name)) if name == "a"),
+                "{value}: {result:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_stable_rng_golden_picks() {
+        // Pinned output: if this changes, seeds saved with libraries no
+        // longer reproduce their prompts
+        let lib = make_test_library();
+        let source = "@Hair, @Eyes, {small|medium|large} @Color hat";
+        let template = PromptTemplate::new("test", parse_template(source).unwrap());
+
+        let texts: Vec<String> = (0..4)
+            .map(|seed| {
+                let mut ctx = EvalContext::with_stable_rng(&lib, seed);
+                render(&template, &mut ctx).unwrap().text
+            })
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                "black hair, blue eyes, large red hat",
+                "red hair, blue eyes, medium red hat",
+                "black hair, green eyes, large green hat",
+                "red hair, blue eyes, small red hat",
+            ]
+        );
+
+        // Node seeds are drawn from the stable RNG too
+        let texts: Vec<String> = (0..4)
+            .map(|seed| {
+                let mut ctx = EvalContext::with_stable_rng(&lib, seed);
+                ctx.stable_node_seeds = true;
+                render(&template, &mut ctx).unwrap().text
+            })
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                "blonde hair, blue eyes, small red hat",
+                "black hair, blue eyes, small blue hat",
+                "blonde hair, blue eyes, medium green hat",
+                "red hair, blue eyes, large red hat",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_std_rng_keeps_its_picks() {
+        // Pinned output from before StableRng: existing seeds must keep
+        // rendering the same prompts
+        let lib = make_test_library();
+        let source = "@Hair, @Eyes, {small|medium|large} @Color hat";
+        let template = PromptTemplate::new("test", parse_template(source).unwrap());
+
+        let texts: Vec<String> = (0..4)
+            .map(|seed| render(&template, &mut EvalContext::with_seed(&lib, seed)).unwrap().text)
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                "black hair, green eyes, medium green hat",
+                "black hair, green eyes, large green hat",
+                "blonde hair, blue eyes, large red hat",
+                "red hair, blue eyes, large blue hat",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_segments_cover_the_text() {
+        let lib = make_test_library();
+        let source = "{{ Subject }} with @Hair, {tall|short}\n@Eyes";
+        let template = PromptTemplate::new("test", parse_template(source).unwrap());
+
+        for seed in 0..10 {
+            let mut ctx = EvalContext::with_seed(&lib, seed);
+            ctx.record_segments = true;
+            ctx.set_slot("Subject", "a {cat|dog}");
+            let result = render(&template, &mut ctx).unwrap();
+
+            let joined: String =
+                result.segments.iter().map(|s| &result.text[s.range.clone()]).collect();
+            assert_eq!(joined, result.text);
+            assert!(result.segments.windows(2).all(|w| w[0].range.end == w[1].range.start));
+
+            let kinds: Vec<&SegmentKind> = result.segments.iter().map(|s| &s.kind).collect();
+            assert_eq!(
+                kinds,
+                [
+                    &SegmentKind::Slot("Subject".to_string()),
+                    &SegmentKind::Text,
+                    &SegmentKind::Group("Hair".to_string()),
+                    &SegmentKind::Text,
+                    &SegmentKind::InlineOptions,
+                    &SegmentKind::Text,
+                    &SegmentKind::Group("Eyes".to_string()),
+                ]
+            );
+            let hair = &result.segments[2];
+            assert_eq!(&source[hair.span.clone()], "@Hair");
+            assert!(lib.groups[0].options.contains(&result.text[hair.range.clone()].to_string()));
+        }
+    }
+
+    #[test]
+    fn test_segments_after_trimmed_separators() {
+        let mut lib = make_test_library();
+        lib.groups.push(PromptGroup::with_options("Empty", vec![""]));
+        let template =
+            PromptTemplate::new("test", parse_template("@Hair, @Empty, ").unwrap());
+        let mut ctx = EvalContext::with_seed(&lib, 1);
+        ctx.skip_empty_separators = true;
+        ctx.record_segments = true;
+
+        // The ", " after @Hair is trimmed away with the empty @Empty
+        let result = render(&template, &mut ctx).unwrap();
+        assert_eq!(result.text, "black hair");
+        assert_eq!(result.segments.len(), 1);
+        assert_eq!(result.segments[0].range, 0..result.text.len());
+    }
+
+    #[test]
+    fn test_segments_are_opt_in() {
+        let lib = make_test_library();
+        let template = PromptTemplate::new("test", parse_template("@Hair").unwrap());
+        let result = render(&template, &mut EvalContext::with_seed(&lib, 1)).unwrap();
+        assert!(result.segments.is_empty());
+    }
+
+    #[test]
+    fn test_render_slot_value_parse_error_names_slot() {
+        let lib = make_test_library();
+        let template = PromptTemplate::new("test", parse_template("{{ mood }}").unwrap());
+        let mut ctx = EvalContext::with_seed(&lib, 42);
+        ctx.set_slot("mood", "very {a|b");
+
+        let err = render(&template, &mut ctx).unwrap_err();
+        match &err {
+            RenderError::SlotValueParseError { slot, span, excerpt, .. } => {
+                assert_eq!(slot, "mood");
+                assert_eq!(*span, 5..9);
+                assert_eq!(excerpt, "{a|b");
+            }
+            other => panic!("unexpected error: {other:?}"),
+        }
+        assert_eq!(
+            err.to_string(),
+            "value for slot 'mood' doesn't parse near '{a|b': unexpected end of input"
+        );
+    }
+
+    #[test]
+    fn test_render_maybe_option_distribution() {
+        let lib = make_test_library();
+        let shown = |source: &str| {
+            let template = PromptTemplate::new("test", parse_template(source).unwrap());
+            (0..1000)
+                .filter(|seed| {
+                    let text = render(&template, &mut EvalContext::with_seed(&lib, *seed))
+                        .unwrap()
+                        .text;
+                    assert!(text.is_empty() || text == "rainy", "{text}");
+                    !text.is_empty()
+                })
+                .count()
+        };
+
+        assert!((400..600).contains(&shown("{rainy?}")));
+        assert!((650..850).contains(&shown("{rainy?75}")));
+        assert_eq!(shown("{rainy?0}"), 0);
+        assert_eq!(shown("{rainy?100}"), 1000);
+    }
+
+    #[test]
+    fn test_render_maybe_option_records_picks() {
+        let lib = make_test_library();
+        let template = PromptTemplate::new("test", parse_template("{@Hair?100}").unwrap());
+        let result = render(&template, &mut EvalContext::with_seed(&lib, 1)).unwrap();
+
+        assert_eq!(result.chosen_options.len(), 1);
+        assert_eq!(result.chosen_options[0].group_name, "Hair");
+        assert_eq!(result.chosen_options[0].option_text, result.text);
+    }
+
+    #[test]
+    fn test_render_slot_ref_chain() {
+        let lib = make_test_library();
+        let source = "{{ hero }} meets {{ rival }}. {{= hero }} wins.";
+        let template = PromptTemplate::new("test", parse_template(source).unwrap());
+
+        for seed in 0..10 {
+            let mut ctx = EvalContext::with_seed(&lib, seed);
+            ctx.set_slot("hero", "{Ann|Bob}");
+            ctx.set_slot("rival", "the rival of {{= hero }}");
+            let text = render(&template, &mut ctx).unwrap().text;
+
+            // Every reference repeats the one pick made for `hero`
+            let hero = text.split(' ').next().unwrap();
+            assert_eq!(
+                text,
+                format!("{hero} meets the rival of {hero}. {hero} wins.")
+            );
+        }
+    }
+
+    #[test]
+    fn test_render_slot_ref_before_slot() {
+        let lib = make_test_library();
+        let source = "{{= hero }} vs {{ hero }}";
+        let template = PromptTemplate::new("test", parse_template(source).unwrap());
+
+        for seed in 0..10 {
+            let mut ctx = EvalContext::with_seed(&lib, seed);
+            ctx.set_slot("hero", "{Ann|Bob|Cat|Dan}");
+            let text = render(&template, &mut ctx).unwrap().text;
+
+            let (reference, slot) = text.split_once(" vs ").unwrap();
+            assert_eq!(reference, slot);
+        }
+    }
+
+    #[test]
+    fn test_render_slot_ref_cycle() {
+        let lib = make_test_library();
+        let template = PromptTemplate::new("test", parse_template("{{ a }}").unwrap());
+        let mut ctx = EvalContext::with_seed(&lib, 42);
+        ctx.set_slot("a", "x {{= b }}");
+        ctx.set_slot("b", "y {{= a }}");
+
+        let result = render(&template, &mut ctx);
+        assert!(
+            matches!(result, Err(RenderError::CircularSlotReference(chain)) if chain == "a -> b -> a")
+        );
+    }
+
     #[test]
     fn test_render_comments_not_included() {
         let lib = make_test_library();
@@ -452,6 +2325,37 @@ mod tests {
         assert!(matches!(result, Err(RenderError::EmptyGroup(_))));
     }
 
+    #[test]
+    fn test_nested_options_render_like_text_options() {
+        let lib = make_test_library();
+        let ast = parse_template("{@Hair|{red|blue} hair}").unwrap();
+        let nested = PromptTemplate::new("nested", ast);
+        assert!(matches!(
+            &nested.ast.nodes[0].0,
+            Node::InlineOptions(options) if matches!(options[1], OptionItem::Nested(_))
+        ));
+
+        // The same options left as text, to be parsed while rendering
+        let text = PromptTemplate::new(
+            "text",
+            Template {
+                nodes: vec![(
+                    Node::InlineOptions(vec![
+                        OptionItem::Text("@Hair".into()),
+                        OptionItem::Text("{red|blue} hair".into()),
+                    ]),
+                    0..23,
+                )],
+            },
+        );
+
+        for seed in 0..30 {
+            let expected = render(&text, &mut EvalContext::with_seed(&lib, seed)).unwrap();
+            let actual = render(&nested, &mut EvalContext::with_seed(&lib, seed)).unwrap();
+            assert_eq!(actual.text, expected.text);
+        }
+    }
+
     #[test]
     fn test_render_nested_grammar_in_options() {
         let mut lib = make_test_library();
@@ -519,4 +2423,43 @@ mod tests {
         // Should have 2 chosen options (Hair and Eyes)
         assert_eq!(result.chosen_options.len(), 2);
     }
+
+    #[test]
+    fn test_render_batch_matches_sequential_renders() {
+        fn assert_send<T: Send>() {}
+        assert_send::<EvalContext<'static>>();
+
+        let lib = make_test_library();
+        let ast = parse_template("@Hair, @Eyes, {a|b|c} @Color").unwrap();
+        let template = PromptTemplate::new("test", ast.clone());
+        let seeds: Vec<u64> = (0..64).collect();
+
+        let batch = lib.render_batch(&ast, &seeds);
+        assert_eq!(batch.len(), seeds.len());
+        for (seed, result) in seeds.iter().zip(batch) {
+            let expected = render(&template, &mut EvalContext::with_seed(&lib, *seed)).unwrap();
+            assert_eq!(result.unwrap().text, expected.text, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn test_preview_prompt_truncates_long_renders() {
+        let mut lib = make_test_library();
+        let long = parse_template("@Hair with @Eyes,\nstanding in a very long corridor").unwrap();
+        lib.templates.push(PromptTemplate::new("Long", long));
+        lib.templates.push(PromptTemplate::new("Short", parse_template("a cat").unwrap()));
+
+        let preview = lib.preview_prompt("Long", 7, 20).unwrap();
+        assert!(preview.chars().count() <= 20);
+        assert!(preview.ends_with('…'));
+        assert!(!preview.contains('\n'));
+        assert_eq!(lib.preview_prompt("Long", 7, 20), Some(preview));
+
+        assert_eq!(lib.preview_prompt("Short", 7, 20).as_deref(), Some("a cat"));
+        assert_eq!(lib.preview_prompt("Missing", 7, 20), None);
+
+        // No room even for the ellipsis
+        assert_eq!(lib.preview_prompt("Short", 7, 0).as_deref(), Some(""));
+        assert_eq!(lib.preview_prompt("Short", 7, 1).as_deref(), Some("…"));
+    }
 }
@@ -0,0 +1,263 @@
+//! Listing every distinct render of a template.
+//!
+//! Where [`crate::eval`] makes one random choice per reference and inline
+//! option, this walks all of the choices in order, so a small prompt space
+//! can be exported in full.
+
+use std::collections::HashSet;
+
+use crate::ast::{LibraryRef, Node, OptionItem, Spanned, Template};
+use crate::library::Library;
+use crate::parser::parse_template;
+use crate::source::library_ref_to_source;
+
+/// The renders found by [`enumerate`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Enumeration {
+    /// Distinct renders, in the order the choices were walked.
+    pub prompts: Vec<String>,
+    /// True if the template has more renders than the limit allowed.
+    pub truncated: bool,
+}
+
+/// List up to `limit` distinct renders of `template`.
+///
+/// Options are taken in order: the first render picks the first option
+/// everywhere. Slots are left as written, a missing group uses its fallback
+/// or is left as written, and a group reached again from inside itself is
+/// left as the reference instead of being expanded.
+pub fn enumerate(template: &Template, library: &Library, limit: usize) -> Enumeration {
+    let mut walker = Walker {
+        library,
+        limit,
+        truncated: false,
+        stack: Vec::new(),
+    };
+    let prompts = walker.nodes(&template.nodes);
+    Enumeration {
+        prompts,
+        truncated: walker.truncated,
+    }
+}
+
+struct Walker<'a> {
+    library: &'a Library,
+    limit: usize,
+    truncated: bool,
+    /// Groups being expanded, for cycle detection
+    stack: Vec<String>,
+}
+
+/// Distinct strings, up to a limit.
+struct Renders {
+    items: Vec<String>,
+    seen: HashSet<String>,
+}
+
+impl Renders {
+    fn new() -> Self {
+        Renders {
+            items: Vec::new(),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Add `text` unless it is a duplicate. Returns false, after flagging
+    /// truncation, if a new item doesn't fit.
+    fn push(&mut self, text: String, walker: &mut Walker<'_>) -> bool {
+        if self.seen.contains(&text) {
+            return true;
+        }
+        if self.items.len() >= walker.limit {
+            walker.truncated = true;
+            return false;
+        }
+        self.seen.insert(text.clone());
+        self.items.push(text);
+        true
+    }
+}
+
+impl Walker<'_> {
+    /// Every render of a sequence of nodes.
+    fn nodes(&mut self, nodes: &[Spanned<Node>]) -> Vec<String> {
+        let mut renders = vec![String::new()];
+        for (node, _span) in nodes {
+            let alternatives = self.node(node);
+            let mut next = Renders::new();
+            'prefixes: for prefix in &renders {
+                for alternative in &alternatives {
+                    if !next.push(format!("{}{}", prefix, alternative), self) {
+                        break 'prefixes;
+                    }
+                }
+            }
+            renders = next.items;
+        }
+        renders
+    }
+
+    fn node(&mut self, node: &Node) -> Vec<String> {
+        match node {
+            Node::Text(text) => vec![text.clone()],
+            Node::Comment(_) => vec![String::new()],
+            Node::Slot(slot) => vec![format!("{{{{ {} }}}}", slot.name)],
+            Node::SlotRef(name) => vec![format!("{{{{= {} }}}}", name)],
+            Node::LibraryRef(lib_ref) => self.library_ref(lib_ref),
+            Node::MaybeOption(maybe) => {
+                let mut alternatives = Vec::new();
+                if maybe.chance > 0 {
+                    alternatives = self.option_text(&maybe.text);
+                }
+                if maybe.chance < 100 {
+                    alternatives.push(String::new());
+                }
+                let mut renders = Renders::new();
+                for alternative in alternatives {
+                    if !renders.push(alternative, self) {
+                        break;
+                    }
+                }
+                renders.items
+            }
+            Node::InlineOptions(options) => {
+                let mut renders = Renders::new();
+                'options: for option in options {
+                    let alternatives = match option {
+                        OptionItem::Text(text) => self.option_text(text),
+                        OptionItem::Nested(nodes) => self.nodes(nodes),
+                    };
+                    for alternative in alternatives {
+                        if !renders.push(alternative, self) {
+                            break 'options;
+                        }
+                    }
+                }
+                renders.items
+            }
+        }
+    }
+
+    fn library_ref(&mut self, lib_ref: &LibraryRef) -> Vec<String> {
+        let transform = |text: &str| match lib_ref.transform {
+            Some(transform) => transform.apply(text),
+            None => text.to_string(),
+        };
+        let literal = || {
+            let mut literal = String::new();
+            library_ref_to_source(lib_ref, &mut literal);
+            literal
+        };
+
+        let library = self.library;
+        let group = library
+            .find_group(&lib_ref.group)
+            .filter(|group| !group.options.is_empty());
+        let Some(group) = group else {
+            return vec![match &lib_ref.fallback {
+                Some(fallback) => transform(fallback),
+                None => literal(),
+            }];
+        };
+        if self.stack.contains(&group.name) {
+            return vec![literal()];
+        }
+
+        self.stack.push(group.name.clone());
+        let mut renders = Renders::new();
+        'options: for option in &group.options {
+            for text in self.option_text(group.canonical_option(option)) {
+                if !renders.push(transform(&text), self) {
+                    break 'options;
+                }
+            }
+        }
+        self.stack.pop();
+        renders.items
+    }
+
+    /// Every render of option text, which may contain nested grammar.
+    fn option_text(&mut self, text: &str) -> Vec<String> {
+        match parse_template(text) {
+            Ok(ast) => self.nodes(&ast.nodes),
+            Err(_) => vec![text.to_string()],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::PromptGroup;
+
+    fn library() -> Library {
+        let mut lib = Library::with_id("test-lib", "Test Library");
+        lib.groups.push(PromptGroup::with_options("Hair", vec!["red hair", "black hair"]));
+        lib.groups.push(PromptGroup::with_options("Eyes", vec!["blue eyes", "green eyes"]));
+        lib.groups.push(PromptGroup::with_options("Shade", vec!["light @Color", "dark @Color"]));
+        lib.groups.push(PromptGroup::with_options("Color", vec!["red", "blue"]));
+        lib
+    }
+
+    fn run(source: &str, limit: usize) -> Enumeration {
+        enumerate(&parse_template(source).unwrap(), &library(), limit)
+    }
+
+    #[test]
+    fn test_enumerate_cartesian_product_in_order() {
+        let result = run("@Hair, @Eyes", 100);
+        assert_eq!(
+            result.prompts,
+            vec![
+                "red hair, blue eyes",
+                "red hair, green eyes",
+                "black hair, blue eyes",
+                "black hair, green eyes",
+            ]
+        );
+        assert!(!result.truncated);
+    }
+
+    #[test]
+    fn test_enumerate_inline_and_nested_options() {
+        let result = run("{a|b} @Shade", 100);
+        assert_eq!(result.prompts.len(), 8);
+        assert_eq!(result.prompts[0], "a light red");
+        assert_eq!(result.prompts[7], "b dark blue");
+    }
+
+    #[test]
+    fn test_enumerate_stops_at_limit() {
+        let result = run("@Hair, @Eyes", 3);
+        assert_eq!(result.prompts.len(), 3);
+        assert!(result.truncated);
+
+        let exact = run("@Hair, @Eyes", 4);
+        assert_eq!(exact.prompts.len(), 4);
+        assert!(!exact.truncated);
+    }
+
+    #[test]
+    fn test_enumerate_skips_duplicates() {
+        let result = run("{red|red|blue}", 100);
+        assert_eq!(result.prompts, vec!["red", "blue"]);
+    }
+
+    #[test]
+    fn test_enumerate_slots_missing_groups_and_transforms() {
+        let result = run("{{ Scene }} @Missing @Gone ?? \"none\" @{Hair | upper}", 100);
+        assert_eq!(
+            result.prompts,
+            vec!["{{ Scene }} @Missing none RED HAIR", "{{ Scene }} @Missing none BLACK HAIR"]
+        );
+    }
+
+    #[test]
+    fn test_enumerate_cycle_is_left_as_reference() {
+        let mut lib = Library::with_id("test-lib", "Test Library");
+        lib.groups.push(PromptGroup::with_options("Loop", vec!["end", "more @Loop"]));
+
+        let result = enumerate(&parse_template("@Loop").unwrap(), &lib, 100);
+        assert_eq!(result.prompts, vec!["end", "more @Loop"]);
+    }
+}
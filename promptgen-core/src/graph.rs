@@ -0,0 +1,131 @@
+//! Graphviz export of how a library's groups reference each other.
+//!
+//! Each group is a node, and an `@Name` in one of its options is an edge
+//! to `Name`. Edges that are part of a cycle are drawn in red, since
+//! rendering fails whenever such a chain of options is picked.
+
+use std::collections::HashSet;
+
+use crate::library::{Library, collect_group_refs};
+use crate::parser::parse_template;
+
+/// The group references in `library` as a Graphviz `digraph`.
+///
+/// Nodes and edges are listed in library order, each edge once per pair of
+/// groups. A reference to a group the library doesn't define still gets an
+/// edge, so Graphviz shows the missing name as its own node.
+pub fn dependency_dot(library: &Library) -> String {
+    let edges = group_edges(library);
+
+    let mut dot = format!("digraph {} {{\n", quote(&library.name));
+    for group in &library.groups {
+        dot.push_str(&format!("  {};\n", quote(&group.name)));
+    }
+    for (from, to) in &edges {
+        let style = if reaches(&edges, to, from) {
+            " [color=red]"
+        } else {
+            ""
+        };
+        dot.push_str(&format!("  {} -> {}{};\n", quote(from), quote(to), style));
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Each distinct `(group, referenced group)` pair, in library order.
+fn group_edges(library: &Library) -> Vec<(String, String)> {
+    let mut edges = Vec::new();
+    for group in &library.groups {
+        let mut refs = Vec::new();
+        for option in &group.options {
+            if let Ok(ast) = parse_template(option) {
+                collect_group_refs(&ast.nodes, &mut refs);
+            }
+        }
+        for target in refs {
+            let edge = (group.name.clone(), target);
+            if !edges.contains(&edge) {
+                edges.push(edge);
+            }
+        }
+    }
+    edges
+}
+
+/// Whether `to` can be reached from `from` by following edges.
+fn reaches(edges: &[(String, String)], from: &str, to: &str) -> bool {
+    let mut seen = HashSet::new();
+    let mut pending = vec![from];
+    while let Some(name) = pending.pop() {
+        if name == to {
+            return true;
+        }
+        if seen.insert(name) {
+            pending.extend(
+                edges
+                    .iter()
+                    .filter(|(f, _)| f == name)
+                    .map(|(_, t)| t.as_str()),
+            );
+        }
+    }
+    false
+}
+
+/// A DOT identifier: `text` in double quotes, with quotes and backslashes
+/// escaped.
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::PromptGroup;
+
+    #[test]
+    fn test_option_reference_becomes_edge() {
+        let mut lib = Library::new("Test");
+        lib.groups.push(PromptGroup::with_options(
+            "A",
+            vec!["@B hair", "plain", "@B again"],
+        ));
+        lib.groups
+            .push(PromptGroup::with_options("B", vec!["red", "blue"]));
+
+        assert_eq!(group_edges(&lib), vec![("A".to_string(), "B".to_string())]);
+        assert_eq!(
+            dependency_dot(&lib),
+            "digraph \"Test\" {\n  \"A\";\n  \"B\";\n  \"A\" -> \"B\";\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_cyclic_edges_are_marked() {
+        let mut lib = Library::new("Test");
+        lib.groups
+            .push(PromptGroup::with_options("A", vec!["@B", "@C"]));
+        lib.groups
+            .push(PromptGroup::with_options("B", vec!["{x|@A}"]));
+        lib.groups
+            .push(PromptGroup::with_options("C", vec!["@C too"]));
+
+        let dot = dependency_dot(&lib);
+        assert!(dot.contains("  \"A\" -> \"B\" [color=red];\n"));
+        assert!(dot.contains("  \"B\" -> \"A\" [color=red];\n"));
+        assert!(dot.contains("  \"A\" -> \"C\";\n"));
+        assert!(dot.contains("  \"C\" -> \"C\" [color=red];\n"));
+    }
+
+    #[test]
+    fn test_names_are_escaped() {
+        let mut lib = Library::new("My \"Lib\"");
+        lib.groups
+            .push(PromptGroup::with_options("Eye Color", vec!["green"]));
+
+        let dot = dependency_dot(&lib);
+        assert!(dot.starts_with("digraph \"My \\\"Lib\\\"\" {\n"));
+        assert!(dot.contains("  \"Eye Color\";\n"));
+    }
+}
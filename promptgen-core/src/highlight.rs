@@ -0,0 +1,155 @@
+//! Syntax highlighting tokens derived from the template parser.
+//!
+//! Editors can use [`tokens`] instead of re-implementing the grammar. It is
+//! best-effort: when the template has a syntax error, the regions that do
+//! parse are still tokenized and the failing region is reported as
+//! [`TokenKind::Error`].
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::ast::Node;
+use crate::parser::parse_template_recover;
+use crate::span::Span;
+
+/// The kind of source region a token covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum TokenKind {
+    Text,
+    Comment,
+    /// A library reference such as `@Hair` or `@{Hair | upper}`.
+    Ref,
+    /// A whole `{a|b|c}` block.
+    InlineOption,
+    /// A `{{ slot }}` block.
+    Slot,
+    /// Source that could not be parsed.
+    Error,
+}
+
+/// A highlighted region of template source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Token {
+    /// Byte range in the source.
+    pub span: Span,
+    pub kind: TokenKind,
+}
+
+/// Tokenize template source for highlighting.
+///
+/// Tokens are in source order and don't overlap. Parts of the source that
+/// fail to parse are covered by `Error` tokens, and tokenizing resumes
+/// after each error.
+pub fn tokens(source: &str) -> Vec<Token> {
+    let (template, errors) = parse_template_recover(source);
+    let mut errors = errors.into_iter().map(|d| d.span);
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    // The recovering parser skips what it can't parse, so every gap between
+    // nodes is one error. An error at end of input (e.g. an unclosed brace)
+    // covers the rest, since the construct that failed runs to the end.
+    let mut push_error = |gap: Span, tokens: &mut Vec<Token>| {
+        let at_end = errors.next().is_some_and(|span| span.start >= source.len());
+        let end = if at_end { source.len() } else { gap.end };
+        tokens.push(Token { span: gap.start..end, kind: TokenKind::Error });
+        end
+    };
+
+    for (node, span) in &template.nodes {
+        if span.start > pos {
+            pos = push_error(pos..span.start, &mut tokens);
+        }
+        if span.start < pos {
+            continue;
+        }
+        tokens.push(Token { span: span.clone(), kind: token_kind(node) });
+        pos = span.end;
+    }
+    if pos < source.len() {
+        push_error(pos..source.len(), &mut tokens);
+    }
+    tokens
+}
+
+fn token_kind(node: &Node) -> TokenKind {
+    match node {
+        Node::Text(_) => TokenKind::Text,
+        Node::Comment(_) => TokenKind::Comment,
+        Node::LibraryRef(_) => TokenKind::Ref,
+        Node::InlineOptions(_) | Node::MaybeOption(_) => TokenKind::InlineOption,
+        Node::Slot(_) | Node::SlotRef(_) => TokenKind::Slot,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds_and_spans(source: &str) -> Vec<(TokenKind, Span)> {
+        tokens(source).into_iter().map(|t| (t.kind, t.span)).collect()
+    }
+
+    #[test]
+    fn test_tokens_for_valid_template() {
+        assert_eq!(
+            kinds_and_spans("@Hair, {red|blue} {{ Scene }} # note"),
+            vec![
+                (TokenKind::Ref, 0..5),
+                (TokenKind::Text, 5..7),
+                (TokenKind::InlineOption, 7..17),
+                (TokenKind::Text, 17..18),
+                (TokenKind::Slot, 18..29),
+                (TokenKind::Text, 29..30),
+                (TokenKind::Comment, 30..36),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokens_for_unclosed_options() {
+        assert_eq!(
+            kinds_and_spans("@Hair with {red|blue"),
+            vec![
+                (TokenKind::Ref, 0..5),
+                (TokenKind::Text, 5..11),
+                (TokenKind::Error, 11..20),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokens_resume_after_error() {
+        assert_eq!(
+            kinds_and_spans("@Hair } and @Eyes"),
+            vec![
+                (TokenKind::Ref, 0..5),
+                (TokenKind::Text, 5..6),
+                (TokenKind::Error, 6..7),
+                (TokenKind::Text, 7..12),
+                (TokenKind::Ref, 12..17),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokens_for_several_errors() {
+        assert_eq!(
+            kinds_and_spans("a } b {x|{y} z"),
+            vec![
+                (TokenKind::Text, 0..2),
+                (TokenKind::Error, 2..3),
+                (TokenKind::Text, 3..6),
+                (TokenKind::Error, 6..14),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokens_empty_source() {
+        assert!(tokens("").is_empty());
+    }
+}
@@ -0,0 +1,338 @@
+//! Conversion to and from wildcard files, as used by the A1111 and ComfyUI
+//! dynamic prompt extensions.
+//!
+//! A wildcard file holds one option per line and is named after its group,
+//! so `hair.txt` is the group `hair`. Other files are referenced as
+//! `__hair__` where PromptGen writes `@hair`. Inline options `{a|b}` mean the
+//! same in both and are passed through as they are.
+//!
+//! [`parse_prompt_a1111`] reads a prompt written with `__name__` references
+//! directly.
+
+use std::collections::HashMap;
+
+use chumsky::error::Simple;
+use chumsky::span::SimpleSpan;
+use chumsky::util::MaybeRef;
+
+use crate::ast::{Node, OptionItem, Spanned, Template};
+use crate::library::{Library, PromptGroup};
+use crate::parser::{ParseError, parse_template};
+use crate::span::Span;
+
+/// One wildcard file's text per group, keyed by group name.
+///
+/// Plain `@Name` references, including those inside inline options, become
+/// `__Name__`. References with a case transform or a fallback have no
+/// wildcard form and are kept as written. A multi-line option is joined
+/// onto one line.
+pub fn to_wildcards(library: &Library) -> HashMap<String, String> {
+    library
+        .groups
+        .iter()
+        .map(|group| {
+            let mut text = String::new();
+            for option in &group.options {
+                let line = option.lines().map(str::trim).collect::<Vec<_>>().join(" ");
+                text.push_str(&refs_to_wildcards(&line, &library.name));
+                text.push('\n');
+            }
+            (group.name.clone(), text)
+        })
+        .collect()
+}
+
+/// A library with one group per wildcard file, given as `(name, text)`.
+///
+/// Blank lines and lines starting with `#` are skipped, and each `__Name__`
+/// becomes a reference to the group `Name`. Groups are sorted by name.
+pub fn from_wildcards(files: impl IntoIterator<Item = (String, String)>) -> Library {
+    let mut files: Vec<(String, String)> = files.into_iter().collect();
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut library = Library::new("Wildcards");
+    for (name, text) in files {
+        let options = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(wildcards_to_refs)
+            .collect();
+        library.groups.push(PromptGroup::new(name, options));
+    }
+    library
+}
+
+/// Parse a prompt in A1111 dynamic prompt syntax.
+///
+/// Each `__name__`, inside inline options too, is read as a reference to
+/// the group `name`; native `@name` references work alongside it. Spans in
+/// the template and in errors point into `src`.
+pub fn parse_prompt_a1111(src: &str) -> Result<Template, ParseError<'_>> {
+    let (native, edits) = convert_wildcards(src);
+    let to_src = |offset| src_offset(&edits, offset);
+
+    match parse_template(&native) {
+        Ok(mut template) => {
+            map_spans(&mut template.nodes, &to_src);
+            Ok(template)
+        }
+        Err(ParseError::Chumsky(errs)) => Err(ParseError::Chumsky(
+            errs.iter()
+                .map(|err| {
+                    let span = SimpleSpan::from(to_src(err.span().start)..to_src(err.span().end));
+                    Simple::new(err.found().map(|c| MaybeRef::Val(*c)), span)
+                })
+                .collect(),
+        )),
+        Err(ParseError::InputTooLarge { len, max }) => Err(ParseError::InputTooLarge { len, max }),
+        Err(ParseError::TooDeeplyNested { max, at }) => Err(ParseError::TooDeeplyNested {
+            max,
+            at: to_src(at),
+        }),
+    }
+}
+
+/// A `__name__` in the original text and the reference written for it.
+struct Edit {
+    src: Span,
+    native: Span,
+}
+
+/// `text` with each `__name__` written as a reference to `name`, and where
+/// each replacement was made.
+fn convert_wildcards(text: &str) -> (String, Vec<Edit>) {
+    let mut output = String::new();
+    let mut edits = Vec::new();
+    let mut pos = 0;
+    while let Some(found) = text[pos..].find("__") {
+        let start = pos + found;
+        let after = &text[start + 2..];
+        let Some(len) = after
+            .find("__")
+            .filter(|&len| is_wildcard_name(&after[..len]))
+        else {
+            output.push_str(&text[pos..start + 2]);
+            pos = start + 2;
+            continue;
+        };
+        output.push_str(&text[pos..start]);
+        let native_start = output.len();
+        output.push_str(&group_ref(&after[..len]));
+        pos = start + len + 4;
+        edits.push(Edit {
+            src: start..pos,
+            native: native_start..output.len(),
+        });
+    }
+    output.push_str(&text[pos..]);
+    (output, edits)
+}
+
+/// Where `offset` in converted text falls in the original. An offset inside
+/// a replacement maps to the start of its `__name__`.
+fn src_offset(edits: &[Edit], offset: usize) -> usize {
+    let mut shift = 0isize;
+    for edit in edits {
+        if offset < edit.native.start {
+            break;
+        }
+        if offset < edit.native.end {
+            return edit.src.start;
+        }
+        shift = edit.src.end as isize - edit.native.end as isize;
+    }
+    offset.saturating_add_signed(shift)
+}
+
+/// Apply `to_src` to the spans of `nodes` and of options nested in them.
+fn map_spans(nodes: &mut [Spanned<Node>], to_src: &impl Fn(usize) -> usize) {
+    for (node, span) in nodes {
+        *span = to_src(span.start)..to_src(span.end);
+        if let Node::InlineOptions(options) = node {
+            for option in options {
+                if let OptionItem::Nested(nested) = option {
+                    map_spans(nested, to_src);
+                }
+            }
+        }
+    }
+}
+
+/// `option` with its plain references to groups of `library` written as
+/// `__Name__`.
+fn refs_to_wildcards(option: &str, library: &str) -> String {
+    let Ok(ast) = parse_template(option) else {
+        return option.to_string();
+    };
+    let mut refs = Vec::new();
+    plain_refs(&ast.nodes, library, &mut refs);
+    refs.sort_by_key(|(span, _)| span.start);
+
+    let mut output = String::new();
+    let mut end = 0;
+    for (span, name) in refs {
+        output.push_str(&option[end..span.start]);
+        output.push_str(&format!("__{}__", name));
+        end = span.end;
+    }
+    output.push_str(&option[end..]);
+    output
+}
+
+/// The span and group name of each reference in `nodes` that has a
+/// wildcard form.
+fn plain_refs(nodes: &[Spanned<Node>], library: &str, refs: &mut Vec<(Span, String)>) {
+    for (node, span) in nodes {
+        match node {
+            Node::LibraryRef(lib_ref) => {
+                let here = lib_ref.library.as_deref().is_none_or(|lib| lib == library);
+                if here && lib_ref.transform.is_none() && lib_ref.fallback.is_none() {
+                    refs.push((span.clone(), lib_ref.group.clone()));
+                }
+            }
+            Node::InlineOptions(options) => {
+                for option in options {
+                    if let OptionItem::Nested(nested) = option {
+                        plain_refs(nested, library, refs);
+                    }
+                }
+            }
+            Node::Text(_)
+            | Node::MaybeOption(_)
+            | Node::Slot(_)
+            | Node::SlotRef(_)
+            | Node::Comment(_) => {}
+        }
+    }
+}
+
+/// `line` with each `__Name__` written as a reference to `Name`.
+fn wildcards_to_refs(line: &str) -> String {
+    convert_wildcards(line).0
+}
+
+fn is_wildcard_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(char::is_whitespace)
+}
+
+/// `@Name`, quoted unless `name` is a plain identifier.
+fn group_ref(name: &str) -> String {
+    let mut chars = name.chars();
+    let plain = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+    if plain {
+        format!("@{}", name)
+    } else {
+        format!("@\"{}\"", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn library() -> Library {
+        let mut lib = Library::new("Mine");
+        lib.groups.push(PromptGroup::with_options(
+            "hair",
+            vec!["red hair", "blue hair"],
+        ));
+        lib.groups.push(PromptGroup::with_options(
+            "look",
+            vec!["@hair and {green|@\"colors/warm\"} eyes", "@{hair | upper}"],
+        ));
+        lib.groups.push(PromptGroup::with_options(
+            "colors/warm",
+            vec!["amber", "gold"],
+        ));
+        lib
+    }
+
+    #[test]
+    fn test_to_wildcards_writes_one_option_per_line() {
+        let files = to_wildcards(&library());
+        assert_eq!(files.len(), 3);
+        assert_eq!(files["hair"], "red hair\nblue hair\n");
+        assert_eq!(
+            files["look"],
+            "__hair__ and {green|__colors/warm__} eyes\n@{hair | upper}\n"
+        );
+    }
+
+    #[test]
+    fn test_wildcards_round_trip() {
+        let original = library();
+        let loaded = from_wildcards(to_wildcards(&original));
+
+        let names: Vec<&str> = loaded.groups.iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(names, ["colors/warm", "hair", "look"]);
+        for group in &loaded.groups {
+            assert_eq!(
+                group.options,
+                original.find_group(&group.name).unwrap().options
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_wildcards_skips_comments_and_blank_lines() {
+        let files = [(
+            "pose".to_string(),
+            "# poses\nsitting\n\n  standing on __ground__  \na __ b __c\n".to_string(),
+        )];
+        let lib = from_wildcards(files);
+        assert_eq!(
+            lib.groups[0].options,
+            ["sitting", "standing on @ground", "a __ b __c"]
+        );
+    }
+
+    #[test]
+    fn test_parse_prompt_a1111_matches_native() {
+        let a1111 = "a __hair__ girl, {red|__colors/warm__|@Mood {x|__y__}} eyes # __z__";
+        let native = r#"a @hair girl, {red|@"colors/warm"|@Mood {x|@y}} eyes # @z"#;
+
+        let template = parse_prompt_a1111(a1111).unwrap();
+        assert_eq!(
+            template.to_sexpr(),
+            parse_template(native).unwrap().to_sexpr()
+        );
+
+        // Spans point into the A1111 source
+        let spans: Vec<Span> = template
+            .nodes
+            .iter()
+            .map(|(_, span)| span.clone())
+            .collect();
+        assert_eq!(&a1111[spans[1].clone()], "__hair__");
+        assert_eq!(
+            &a1111[spans[3].clone()],
+            "{red|__colors/warm__|@Mood {x|__y__}}"
+        );
+        let Node::InlineOptions(options) = &template.nodes[3].0 else {
+            panic!("expected inline options");
+        };
+        let OptionItem::Nested(nested) = &options[2] else {
+            panic!("expected a nested option");
+        };
+        assert_eq!(&a1111[nested[2].1.clone()], "{x|__y__}");
+    }
+
+    #[test]
+    fn test_parse_prompt_a1111_error_points_into_source() {
+        let src = "__hair__ and {a|__b__";
+        let err = parse_prompt_a1111(src).unwrap_err();
+        assert_eq!(err.diagnostics()[0].span, src.len()..src.len());
+    }
+
+    #[test]
+    fn test_multi_line_option_is_joined() {
+        let mut lib = Library::new("Mine");
+        lib.groups.push(PromptGroup::with_options(
+            "scene",
+            vec!["a forest\n  at night"],
+        ));
+        assert_eq!(to_wildcards(&lib)["scene"], "a forest at night\n");
+    }
+}
@@ -3,14 +3,19 @@
 //! This module provides YAML-based serialization for libraries, groups, and templates.
 //! Templates are stored as source text and re-parsed on load.
 
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use serde::{Deserialize, Serialize};
+use serde_yaml_ng::Value;
 
-use crate::ast::{LibraryRef, Node, OptionItem};
-use crate::library::{EngineHint, Library, PromptGroup, PromptTemplate, new_id};
+use crate::library::{
+    EngineHint, Library, OptionMeta, PromptGroup, PromptTemplate, derived_id, new_id,
+};
 use crate::parser::parse_template;
+use crate::source::template_to_source;
 
 /// Error type for I/O operations.
 #[derive(Debug, thiserror::Error)]
@@ -26,6 +31,10 @@ pub enum IoError {
 
     #[error("duplicate group name: '{0}'")]
     DuplicateGroupName(String),
+
+    /// Valid YAML that doesn't have the shape of a library.
+    #[error("{path}: {message}")]
+    Schema { path: String, message: String },
 }
 
 // ============================================================================
@@ -38,9 +47,26 @@ pub enum IoError {
 pub struct GroupDto {
     /// Unique name for this group.
     pub name: String,
-    /// Options as strings (may contain nested grammar).
+    /// Options (may contain nested grammar), each optionally annotated.
     #[serde(default)]
-    pub options: Vec<String>,
+    pub options: Vec<OptionDto>,
+    /// Alternate spellings that render as a canonical option (alias -> canonical).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub aliases: BTreeMap<String, String>,
+}
+
+/// DTO for a group option: a plain string, or a mapping with notes.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum OptionDto {
+    Plain(String),
+    Annotated {
+        text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        note: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        source: Option<String>,
+    },
 }
 
 /// DTO for PromptTemplate.
@@ -55,13 +81,19 @@ pub struct TemplateDto {
     pub engine_hint: EngineHint,
     /// The template source text (will be parsed into AST on load).
     pub source: String,
+    /// Default slot values, applied before any user-supplied values.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub default_slots: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
 }
 
 /// DTO for a complete library pack (single-file format).
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PackDto {
-    #[serde(default = "new_id")]
-    pub id: String,
+    /// Missing in hand-written files; filled in on load.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     pub name: String,
     #[serde(default)]
     pub description: String,
@@ -69,6 +101,8 @@ pub struct PackDto {
     pub groups: Vec<GroupDto>,
     #[serde(default)]
     pub templates: Vec<TemplateDto>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_seed: Option<u64>,
 }
 
 // ============================================================================
@@ -77,13 +111,47 @@ pub struct PackDto {
 
 impl From<GroupDto> for PromptGroup {
     fn from(dto: GroupDto) -> Self {
+        let mut options = Vec::with_capacity(dto.options.len());
+        let mut option_meta = HashMap::new();
+        for option in dto.options {
+            match option {
+                OptionDto::Plain(text) => options.push(text),
+                OptionDto::Annotated { text, note, source } => {
+                    option_meta.insert(text.clone(), OptionMeta { note, source });
+                    options.push(text);
+                }
+            }
+        }
+
         PromptGroup {
             name: dto.name,
-            options: dto.options,
+            options,
+            aliases: dto.aliases.into_iter().collect(),
+            option_meta,
         }
     }
 }
 
+impl PackDto {
+    /// Convert to a Library, parsing template sources. `missing_id` supplies
+    /// the ID when the pack doesn't have one.
+    fn try_into_library(self, missing_id: impl FnOnce() -> String) -> Result<Library, IoError> {
+        let mut templates = Vec::new();
+        for template_dto in self.templates {
+            templates.push(template_dto.try_into_template()?);
+        }
+
+        Ok(Library {
+            id: self.id.unwrap_or_else(missing_id),
+            name: self.name,
+            description: self.description,
+            groups: self.groups.into_iter().map(Into::into).collect(),
+            templates,
+            default_seed: self.default_seed,
+        })
+    }
+}
+
 impl TemplateDto {
     /// Convert to PromptTemplate, parsing the source text.
     pub fn try_into_template(self) -> Result<PromptTemplate, IoError> {
@@ -98,6 +166,8 @@ impl TemplateDto {
             description: self.description,
             engine_hint: self.engine_hint,
             ast,
+            default_slots: self.default_slots.into_iter().collect(),
+            tags: self.tags,
         })
     }
 }
@@ -110,7 +180,23 @@ impl From<&PromptGroup> for GroupDto {
     fn from(group: &PromptGroup) -> Self {
         GroupDto {
             name: group.name.clone(),
-            options: group.options.clone(),
+            options: group
+                .options
+                .iter()
+                .map(|text| match group.option_meta.get(text) {
+                    Some(meta) if meta != &OptionMeta::default() => OptionDto::Annotated {
+                        text: text.clone(),
+                        note: meta.note.clone(),
+                        source: meta.source.clone(),
+                    },
+                    _ => OptionDto::Plain(text.clone()),
+                })
+                .collect(),
+            aliases: group
+                .aliases
+                .iter()
+                .map(|(alias, canonical)| (alias.clone(), canonical.clone()))
+                .collect(),
         }
     }
 }
@@ -123,6 +209,12 @@ impl From<&PromptTemplate> for TemplateDto {
             description: template.description.clone(),
             engine_hint: template.engine_hint.clone(),
             source: template_to_source(&template.ast),
+            default_slots: template
+                .default_slots
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            tags: template.tags.clone(),
         }
     }
 }
@@ -130,88 +222,12 @@ impl From<&PromptTemplate> for TemplateDto {
 impl From<&Library> for PackDto {
     fn from(library: &Library) -> Self {
         PackDto {
-            id: library.id.clone(),
+            id: Some(library.id.clone()),
             name: library.name.clone(),
             description: library.description.clone(),
             groups: library.groups.iter().map(Into::into).collect(),
             templates: library.templates.iter().map(Into::into).collect(),
-        }
-    }
-}
-
-/// Reconstruct source text from a parsed template AST.
-fn template_to_source(template: &crate::ast::Template) -> String {
-    let mut source = String::new();
-
-    for (node, _span) in &template.nodes {
-        node_to_source(node, &mut source);
-    }
-
-    source
-}
-
-/// Convert a single node to its source representation.
-fn node_to_source(node: &Node, output: &mut String) {
-    match node {
-        Node::Text(text) => output.push_str(text),
-
-        Node::Comment(text) => {
-            output.push_str("# ");
-            output.push_str(text);
-        }
-
-        Node::Slot(name) => {
-            output.push_str("{{ ");
-            output.push_str(name);
-            output.push_str(" }}");
-        }
-
-        Node::LibraryRef(lib_ref) => {
-            library_ref_to_source(lib_ref, output);
-        }
-
-        Node::InlineOptions(options) => {
-            output.push('{');
-            for (i, option) in options.iter().enumerate() {
-                if i > 0 {
-                    output.push('|');
-                }
-                option_item_to_source(option, output);
-            }
-            output.push('}');
-        }
-    }
-}
-
-/// Convert a library reference to source.
-fn library_ref_to_source(lib_ref: &LibraryRef, output: &mut String) {
-    output.push('@');
-
-    let needs_quotes = lib_ref.library.is_some()
-        || lib_ref.group.contains(' ')
-        || lib_ref.group.contains(':');
-
-    if needs_quotes {
-        output.push('"');
-        if let Some(lib) = &lib_ref.library {
-            output.push_str(lib);
-            output.push(':');
-        }
-        output.push_str(&lib_ref.group);
-        output.push('"');
-    } else {
-        output.push_str(&lib_ref.group);
-    }
-}
-
-/// Convert an option item to source.
-fn option_item_to_source(item: &OptionItem, output: &mut String) {
-    match item {
-        OptionItem::Text(text) => output.push_str(text),
-        OptionItem::Nested(nodes) => {
-            for (node, _span) in nodes {
-                node_to_source(node, output);
-            }
+            default_seed: library.default_seed,
         }
     }
 }
@@ -239,56 +255,231 @@ pub fn save_library(library: &Library, path: &Path) -> Result<(), IoError> {
 // ============================================================================
 
 /// Load a library from a pack file (single YAML file).
+///
+/// A file without an `id` gets one derived from its path, so loading the
+/// same file again gives the same library ID.
+///
+/// Duplicate group names are not an error here, so a hand-edited file still
+/// opens; they are reported by [`Library::warnings`]. Use [`parse_pack`] to
+/// reject them.
 pub fn load_pack(path: &Path) -> Result<Library, IoError> {
     let content = fs::read_to_string(path)?;
-    let pack: PackDto = serde_yaml_ng::from_str(&content)?;
-
-    let mut templates = Vec::new();
-    for template_dto in pack.templates {
-        templates.push(template_dto.try_into_template()?);
-    }
+    let pack = read_pack_dto(&content)?;
 
-    Ok(Library {
-        id: pack.id,
-        name: pack.name,
-        description: pack.description,
-        groups: pack.groups.into_iter().map(Into::into).collect(),
-        templates,
-    })
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    pack.try_into_library(|| derived_id(&path.to_string_lossy()))
 }
 
 /// Save a library as a pack file (single YAML file).
+///
+/// The file is written next to `path` under a temporary name and then
+/// renamed over it, so a reader never sees a half-written library and two
+/// saves racing for the same path leave one of them whole.
 pub fn save_pack(library: &Library, path: &Path) -> Result<(), IoError> {
     let pack: PackDto = library.into();
     let content = serde_yaml_ng::to_string(&pack)?;
-    fs::write(path, content)?;
+    write_atomic(path, &content)?;
     Ok(())
 }
 
+/// Write `content` to a fresh temporary file beside `path`, then rename it
+/// into place.
+fn write_atomic(path: &Path, content: &str) -> std::io::Result<()> {
+    let temp = temp_path(path);
+    if let Err(err) = fs::write(&temp, content).and_then(|()| fs::rename(&temp, path)) {
+        let _ = fs::remove_file(&temp);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// A hidden path beside `path`, unique to this call so concurrent saves
+/// don't share a temporary file.
+fn temp_path(path: &Path) -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let file_name = path.file_name().map_or_else(Default::default, |n| n.to_string_lossy());
+    path.with_file_name(format!(
+        ".{}.{}-{}.tmp",
+        file_name,
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
 /// Parse a library from a YAML string (pack format).
 pub fn parse_pack(yaml: &str) -> Result<Library, IoError> {
-    let pack: PackDto = serde_yaml_ng::from_str(yaml)?;
+    pack_into_checked_library(read_pack_dto(yaml)?)
+}
+
+/// Convert a pack to a library, rejecting duplicate group names.
+fn pack_into_checked_library(pack: PackDto) -> Result<Library, IoError> {
+    let library = pack.try_into_library(new_id)?;
+
+    if let Some(name) = library.duplicate_group_names().into_iter().next() {
+        return Err(IoError::DuplicateGroupName(name));
+    }
+
+    Ok(library)
+}
 
-    // Check for duplicate group names
-    let mut seen_names = std::collections::HashSet::new();
-    for group in &pack.groups {
-        if !seen_names.insert(&group.name) {
-            return Err(IoError::DuplicateGroupName(group.name.clone()));
+/// Deserialize a pack, explaining common mistakes in the file's structure.
+fn read_pack_dto(yaml: &str) -> Result<PackDto, IoError> {
+    serde_yaml_ng::from_str(yaml).map_err(|err| {
+        // Only look closer once serde has failed; its message is the
+        // fallback for anything the checks don't recognize
+        match serde_yaml_ng::from_str::<Value>(yaml) {
+            Ok(value) => check_pack_schema(&value).err().unwrap_or(err.into()),
+            Err(_) => err.into(),
         }
+    })
+}
+
+// ============================================================================
+// Schema checks
+// ============================================================================
+
+fn schema_error(path: impl Into<String>, message: impl Into<String>) -> IoError {
+    IoError::Schema {
+        path: path.into(),
+        message: message.into(),
     }
+}
 
-    let mut templates = Vec::new();
-    for template_dto in pack.templates {
-        templates.push(template_dto.try_into_template()?);
+/// Describe a YAML value's type for error messages.
+fn describe(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "nothing",
+        Value::Bool(_) => "true/false",
+        Value::Number(_) => "a number",
+        Value::String(_) => "text",
+        Value::Sequence(_) => "a list",
+        Value::Mapping(_) => "a mapping",
+        Value::Tagged(_) => "a tagged value",
     }
+}
 
-    Ok(Library {
-        id: pack.id,
-        name: pack.name,
-        description: pack.description,
-        groups: pack.groups.into_iter().map(Into::into).collect(),
-        templates,
-    })
+/// Check a field that must be text, if `required` or present.
+fn check_text(parent: &Value, field: &str, path: &str, required: bool) -> Result<(), IoError> {
+    match parent.get(field) {
+        None | Some(Value::Null) if required => {
+            Err(schema_error(path, format!("`{}` is missing", field)))
+        }
+        None | Some(Value::Null) | Some(Value::String(_)) => Ok(()),
+        Some(other) => Err(schema_error(
+            format!("{}.{}", path, field),
+            format!("expected text but found {}; put it in quotes", describe(other)),
+        )),
+    }
+}
+
+/// Find the first structural mistake in a parsed pack file.
+fn check_pack_schema(pack: &Value) -> Result<(), IoError> {
+    if !pack.is_mapping() {
+        return Err(schema_error(
+            "(top level)",
+            format!(
+                "expected a mapping with `name`, `groups` and `templates` but found {}",
+                describe(pack)
+            ),
+        ));
+    }
+    check_text(pack, "name", "(top level)", true)?;
+    check_text(pack, "description", "(top level)", false)?;
+
+    if let Some(groups) = pack.get("groups") {
+        let Some(groups) = groups.as_sequence() else {
+            return Err(schema_error(
+                "groups",
+                format!(
+                    "expected a list of groups but found {}; start each group with \"- name:\"",
+                    describe(groups)
+                ),
+            ));
+        };
+        for (i, group) in groups.iter().enumerate() {
+            check_group(group, &format!("groups[{}]", i))?;
+        }
+    }
+
+    if let Some(templates) = pack.get("templates") {
+        let Some(templates) = templates.as_sequence() else {
+            return Err(schema_error(
+                "templates",
+                format!(
+                    "expected a list of templates but found {}; start each template with \"- name:\"",
+                    describe(templates)
+                ),
+            ));
+        };
+        for (i, template) in templates.iter().enumerate() {
+            let path = format!("templates[{}]", i);
+            if !template.is_mapping() {
+                return Err(schema_error(
+                    path,
+                    format!(
+                        "expected a template with `name` and `source` but found {}",
+                        describe(template)
+                    ),
+                ));
+            }
+            check_text(template, "name", &path, true)?;
+            check_text(template, "source", &path, true)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn check_group(group: &Value, path: &str) -> Result<(), IoError> {
+    if !group.is_mapping() {
+        return Err(schema_error(
+            path,
+            format!("expected a group with `name` and `options` but found {}", describe(group)),
+        ));
+    }
+    check_text(group, "name", path, true)?;
+
+    let options_path = format!("{}.options", path);
+    match group.get("options") {
+        None | Some(Value::Null) | Some(Value::Sequence(_)) => {}
+        Some(options) => {
+            return Err(schema_error(
+                options_path,
+                format!(
+                    "expected a list of options but found {}; put each option on its own line starting with \"- \"",
+                    describe(options)
+                ),
+            ));
+        }
+    }
+
+    let options = group.get("options").and_then(Value::as_sequence);
+    for (i, option) in options.into_iter().flatten().enumerate() {
+        let path = format!("{}[{}]", options_path, i);
+        match option {
+            Value::String(_) => {}
+            Value::Mapping(_) if option.get("text").is_some() => {
+                check_text(option, "text", &path, true)?
+            }
+            Value::Mapping(_) => {
+                return Err(schema_error(
+                    path,
+                    "expected option text but found a mapping; an option starting with `{` needs quotes",
+                ));
+            }
+            other => {
+                return Err(schema_error(
+                    path,
+                    format!(
+                        "expected option text but found {}; put it in quotes",
+                        describe(other)
+                    ),
+                ));
+            }
+        }
+    }
+
+    Ok(())
 }
 
 /// Serialize a library to a YAML string (pack format).
@@ -297,6 +488,36 @@ pub fn serialize_pack(library: &Library) -> Result<String, IoError> {
     Ok(serde_yaml_ng::to_string(&pack)?)
 }
 
+// ============================================================================
+// Bundles (several packs in one file)
+// ============================================================================
+
+/// Serialize several libraries into one bundle: a YAML stream holding one
+/// pack document per library, each starting with `---`.
+pub fn serialize_bundle(libraries: &[Library]) -> Result<String, IoError> {
+    let mut output = String::new();
+    for library in libraries {
+        output.push_str("---\n");
+        output.push_str(&serialize_pack(library)?);
+    }
+    Ok(output)
+}
+
+/// Parse the libraries in a bundle, in order.
+///
+/// Each document is read like [`parse_pack`], so an id missing from a
+/// hand-written document is generated.
+pub fn parse_bundle(yaml: &str) -> Result<Vec<Library>, IoError> {
+    let mut libraries = Vec::new();
+    for document in serde_yaml_ng::Deserializer::from_str(yaml) {
+        let value = Value::deserialize(document)?;
+        let pack = serde_yaml_ng::from_value(value.clone())
+            .map_err(|err| check_pack_schema(&value).err().unwrap_or(err.into()))?;
+        libraries.push(pack_into_checked_library(pack)?);
+    }
+    Ok(libraries)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -339,6 +560,254 @@ templates:
         assert_eq!(loaded.templates[0].name, "Character");
     }
 
+    #[test]
+    fn test_bundle_round_trip() {
+        let first = make_test_library();
+        let second = parse_pack("id: scenes\nname: Scenes\ngroups:\n  - name: Place\n    options: [a forest]\n").unwrap();
+
+        let bundle = serialize_bundle(&[first.clone(), second.clone()]).unwrap();
+        let loaded = parse_bundle(&bundle).unwrap();
+
+        assert_eq!(loaded.len(), 2);
+        for (loaded, original) in loaded.iter().zip([&first, &second]) {
+            assert_eq!(serialize_pack(loaded).unwrap(), serialize_pack(original).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_bundle_reports_schema_errors() {
+        let err = parse_bundle("---\nname: Fine\n---\nname: Broken\ngroups: nope\n").unwrap_err();
+        assert!(matches!(err, IoError::Schema { .. }), "{err}");
+    }
+
+    #[test]
+    fn test_default_slots_round_trip() {
+        let yaml = r#"
+name: Slots
+templates:
+  - name: Scene
+    source: "{{ Subject }} in {{ Place }}"
+    default_slots:
+      Subject: a cat
+      Place: a forest
+"#;
+        let lib = parse_pack(yaml).unwrap();
+        let template = &lib.templates[0];
+        assert_eq!(template.default_slots["Subject"], "a cat");
+        assert_eq!(template.default_slots["Place"], "a forest");
+
+        let loaded = parse_pack(&serialize_pack(&lib).unwrap()).unwrap();
+        assert_eq!(loaded.templates[0].default_slots, template.default_slots);
+
+        // Templates without defaults don't write the key
+        let plain = make_test_library();
+        assert!(!serialize_pack(&plain).unwrap().contains("default_slots"));
+    }
+
+    #[test]
+    fn test_serialize_pack_is_byte_identical() {
+        let names = ["Zebra", "Apple", "Mango", "Kiwi", "Fig", "Date", "Lime", "Pear"];
+        let build = |reversed: bool| {
+            let mut order = names.to_vec();
+            if reversed {
+                order.reverse();
+            }
+            let mut lib = Library::with_id("lib", "Stable");
+            let mut group = PromptGroup::new("Fruit", names.map(str::to_string).to_vec());
+            let ast = parse_template("{{ A }}").unwrap();
+            let mut template = PromptTemplate::with_id("t", "T", ast);
+            for name in order {
+                group.aliases.insert(name.to_lowercase(), name.to_string());
+                group.option_meta.insert(
+                    name.to_string(),
+                    OptionMeta { note: Some(format!("{name} note")), source: None },
+                );
+                template.default_slots.insert(name.to_string(), name.to_lowercase());
+            }
+            lib.groups.push(group);
+            lib.templates.push(template);
+            lib
+        };
+
+        let first = serialize_pack(&build(false)).unwrap();
+        assert_eq!(first, serialize_pack(&build(false)).unwrap());
+        assert_eq!(first, serialize_pack(&build(true)).unwrap());
+    }
+
+    #[test]
+    fn test_template_tags_round_trip() {
+        let yaml = r#"
+name: Tags
+templates:
+  - name: Tagged
+    source: "a portrait"
+    tags: [portrait, wip]
+  - name: Untagged
+    source: "a landscape"
+"#;
+        let lib = parse_pack(yaml).unwrap();
+        assert_eq!(lib.templates[0].tags, vec!["portrait", "wip"]);
+        assert!(lib.templates[1].tags.is_empty());
+
+        let loaded = parse_pack(&serialize_pack(&lib).unwrap()).unwrap();
+        assert_eq!(loaded.templates[0].tags, vec!["portrait", "wip"]);
+        assert!(loaded.templates[1].tags.is_empty());
+    }
+
+    #[test]
+    fn test_group_aliases_round_trip() {
+        let yaml = r#"
+name: Test Library
+groups:
+  - name: Hair
+    options:
+      - blonde hair
+      - blond hair
+    aliases:
+      blond hair: blonde hair
+"#;
+        let lib = parse_pack(yaml).unwrap();
+        let hair = lib.find_group("Hair").unwrap();
+        assert_eq!(hair.canonical_option("blond hair"), "blonde hair");
+        assert_eq!(hair.canonical_option("blonde hair"), "blonde hair");
+
+        let reloaded = parse_pack(&serialize_pack(&lib).unwrap()).unwrap();
+        assert_eq!(reloaded.find_group("Hair").unwrap().aliases, hair.aliases);
+
+        // Groups without aliases don't write the key
+        assert!(!serialize_pack(&make_test_library()).unwrap().contains("aliases"));
+    }
+
+    #[test]
+    fn test_option_meta_round_trip() {
+        let yaml = r#"
+name: Test Library
+groups:
+  - name: Hair
+    options:
+      - red hair
+      - text: auburn hair
+        note: reads as red in most models
+        source: https://example.com/colors
+      - text: silver hair
+        note: pairs well with @Age
+      - black hair
+"#;
+        let lib = parse_pack(yaml).unwrap();
+        let hair = lib.find_group("Hair").unwrap();
+        assert_eq!(
+            hair.options,
+            vec!["red hair", "auburn hair", "silver hair", "black hair"]
+        );
+        assert_eq!(hair.option_meta.len(), 2);
+        assert_eq!(
+            hair.option_meta["auburn hair"],
+            OptionMeta {
+                note: Some("reads as red in most models".to_string()),
+                source: Some("https://example.com/colors".to_string()),
+            }
+        );
+        assert_eq!(hair.option_meta["silver hair"].source, None);
+
+        let saved = serialize_pack(&lib).unwrap();
+        assert!(saved.contains("- red hair"));
+        let reloaded = parse_pack(&saved).unwrap();
+        let reloaded_hair = reloaded.find_group("Hair").unwrap();
+        assert_eq!(reloaded_hair.options, hair.options);
+        assert_eq!(reloaded_hair.option_meta, hair.option_meta);
+    }
+
+    #[test]
+    fn test_option_meta_is_not_rendered() {
+        let yaml = r#"
+name: Test Library
+groups:
+  - name: Hair
+    options:
+      - text: auburn hair
+        note: a note
+templates:
+  - name: Portrait
+    source: "@Hair portrait"
+"#;
+        let lib = parse_pack(yaml).unwrap();
+        let template = lib.find_template("Portrait").unwrap();
+        let mut ctx = crate::eval::EvalContext::with_seed(&lib, 1);
+        let result = crate::eval::render(template, &mut ctx).unwrap();
+        assert_eq!(result.text, "auburn hair portrait");
+    }
+
+    fn schema_error_message(yaml: &str) -> String {
+        match parse_pack(yaml) {
+            Err(err @ IoError::Schema { .. }) => err.to_string(),
+            other => panic!("expected a schema error, got {:?}", other.map(|lib| lib.name)),
+        }
+    }
+
+    #[test]
+    fn test_schema_error_options_not_a_list() {
+        let message = schema_error_message(
+            "name: Test\ngroups:\n  - name: Hair\n    options: red hair\n",
+        );
+        assert_eq!(
+            message,
+            "groups[0].options: expected a list of options but found text; \
+             put each option on its own line starting with \"- \""
+        );
+    }
+
+    #[test]
+    fn test_schema_error_missing_names() {
+        assert_eq!(
+            schema_error_message("groups: []\n"),
+            "(top level): `name` is missing"
+        );
+        assert_eq!(
+            schema_error_message("name: Test\ngroups:\n  - options: [a]\n"),
+            "groups[0]: `name` is missing"
+        );
+        assert_eq!(
+            schema_error_message("name: Test\ntemplates:\n  - name: Portrait\n"),
+            "templates[0]: `source` is missing"
+        );
+    }
+
+    #[test]
+    fn test_schema_error_unquoted_options() {
+        assert!(
+            schema_error_message("name: Test\ngroups:\n  - name: Age\n    options: [young, 42]\n")
+                .starts_with("groups[0].options[1]: expected option text but found a number")
+        );
+        assert!(
+            schema_error_message("name: Test\ngroups:\n  - name: Hair\n    options:\n      - {red|blue}\n")
+                .contains("an option starting with `{` needs quotes")
+        );
+    }
+
+    #[test]
+    fn test_schema_error_groups_not_a_list() {
+        let message = schema_error_message("name: Test\ngroups:\n  Hair: [red hair]\n");
+        assert!(message.starts_with("groups: expected a list of groups but found a mapping"));
+    }
+
+    #[test]
+    fn test_unrecognized_mistakes_keep_yaml_error() {
+        let err = parse_pack("name: Test\ndefault_seed: soon\n").unwrap_err();
+        assert!(matches!(err, IoError::Yaml(_)));
+        assert!(err.to_string().contains("default_seed"));
+    }
+
+    #[test]
+    fn test_default_seed_round_trip() {
+        let mut lib = make_test_library();
+        assert_eq!(lib.default_seed, None);
+        assert!(!serialize_pack(&lib).unwrap().contains("default_seed"));
+
+        lib.default_seed = Some(1234);
+        let loaded = parse_pack(&serialize_pack(&lib).unwrap()).unwrap();
+        assert_eq!(loaded.default_seed, Some(1234));
+    }
+
     #[test]
     fn test_library_file_round_trip() {
         let lib = make_test_library();
@@ -367,6 +836,35 @@ templates:
         assert_eq!(loaded.name, lib.name);
     }
 
+    #[test]
+    fn test_racing_saves_leave_a_whole_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("library.yml");
+
+        let small = make_test_library();
+        let mut large = make_test_library();
+        large.groups[0].options = (0..2000).map(|i| format!("option {}", i)).collect();
+
+        std::thread::scope(|scope| {
+            for lib in [&small, &large] {
+                let path = &path;
+                scope.spawn(move || {
+                    for _ in 0..20 {
+                        save_pack(lib, path).unwrap();
+                    }
+                });
+            }
+        });
+
+        let loaded = load_pack(&path).unwrap();
+        let option_count = loaded.groups[0].options.len();
+        assert!(option_count == small.groups[0].options.len() || option_count == 2000);
+
+        // No temporary files are left behind
+        let entries: Vec<_> = fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+    }
+
     #[test]
     fn test_ids_auto_generated_when_missing() {
         let yaml = r#"
@@ -391,52 +889,33 @@ templates:
     }
 
     #[test]
-    fn test_template_source_reconstruction() {
-        let source = r#"@Hair with {{ EyeColor }} and {red|blue|green}"#;
-        let ast = parse_template(source).unwrap();
-        let reconstructed = template_to_source(&ast);
-
-        // Parse the reconstructed source and verify it works
-        let reparsed = parse_template(&reconstructed).unwrap();
-        assert_eq!(reparsed.nodes.len(), ast.nodes.len());
-    }
-
-    #[test]
-    fn test_template_source_reconstruction_qualified_ref() {
-        let source = r#"@"MyLib:Hair Color" with @Eyes"#;
-        let ast = parse_template(source).unwrap();
-        let reconstructed = template_to_source(&ast);
-
-        // Verify the qualified reference is preserved
-        assert!(reconstructed.contains(r#"@"MyLib:Hair Color""#));
-        assert!(reconstructed.contains("@Eyes"));
-    }
-
-    #[test]
-    fn test_template_source_reconstruction_inline_options() {
-        let source = r#"A {big|small} {red|blue|green} car"#;
-        let ast = parse_template(source).unwrap();
-        let reconstructed = template_to_source(&ast);
+    fn test_load_pack_derives_stable_id_from_path() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("no-id.yml");
+        fs::write(&path, "name: No Id\n").unwrap();
 
-        assert_eq!(reconstructed, source);
-    }
+        let first = load_pack(&path).unwrap();
+        let second = load_pack(&path).unwrap();
+        assert_eq!(first.id, second.id);
 
-    #[test]
-    fn test_template_source_reconstruction_slot() {
-        let source = r#"Hello {{ Name }}, welcome!"#;
-        let ast = parse_template(source).unwrap();
-        let reconstructed = template_to_source(&ast);
+        let other_path = dir.path().join("other.yml");
+        fs::write(&other_path, "name: No Id\n").unwrap();
+        assert_ne!(load_pack(&other_path).unwrap().id, first.id);
 
-        assert_eq!(reconstructed, source);
+        // An explicit id is kept
+        fs::write(&path, "id: my-lib\nname: No Id\n").unwrap();
+        assert_eq!(load_pack(&path).unwrap().id, "my-lib");
     }
 
     #[test]
-    fn test_template_source_reconstruction_comment() {
-        let source = "# This is a comment";
-        let ast = parse_template(source).unwrap();
-        let reconstructed = template_to_source(&ast);
-
-        assert_eq!(reconstructed, source);
+    fn test_saved_source_matches_template_to_source() {
+        let source = r#"@"MyLib:Hair" ?? "bald", {a|} {{ Scene! }} # note"#;
+        let mut lib = Library::with_id("lib", "Test");
+        lib.templates.push(PromptTemplate::new("T", parse_template(source).unwrap()));
+
+        let pack: PackDto = (&lib).into();
+        assert_eq!(pack.templates[0].source, template_to_source(&lib.templates[0].ast));
+        assert_eq!(pack.templates[0].source, source);
     }
 
     #[test]
@@ -455,4 +934,30 @@ groups:
         let result = parse_pack(yaml);
         assert!(matches!(result, Err(IoError::DuplicateGroupName(name)) if name == "Color"));
     }
+
+    #[test]
+    fn test_load_pack_reports_duplicate_group_names() {
+        let yaml = r#"
+name: Test Library
+groups:
+  - name: Color
+    options:
+      - red
+  - name: Color
+    options:
+      - blue
+"#;
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("library.yml");
+        fs::write(&path, yaml).unwrap();
+
+        let loaded = load_pack(&path).unwrap();
+        assert_eq!(loaded.duplicate_group_names(), vec!["Color"]);
+        assert!(
+            loaded
+                .warnings()
+                .iter()
+                .any(|w| w.kind == crate::WarningKind::DuplicateName)
+        );
+    }
 }
@@ -1,24 +1,61 @@
 pub mod ast;
+pub mod completion;
+pub mod diagnostics;
+pub mod diff;
 pub mod eval;
+pub mod flatten;
+pub mod graph;
+pub mod highlight;
+pub mod interop;
 #[cfg(feature = "serde")]
 pub mod io; // TODO: Commented out internally, needs update for new grammar
 pub mod library;
+#[cfg(feature = "lsp")]
+pub mod lsp;
+pub mod merge;
+pub mod output;
 pub mod parser;
+pub mod rename;
+pub mod sexpr;
+pub mod source;
 pub mod span;
 
 // Re-exports for convenience
-pub use ast::{LibraryRef, Node, OptionItem, Spanned, Template};
+pub use ast::{CaseKind, LibraryRef, MaybeOption, Node, OptionItem, Slot, Spanned, Template};
+
+pub use completion::{CompletionItem, CompletionKind};
+
+pub use diagnostics::{DiagnosticError, DiagnosticWarning, ParseResult, WarningKind};
+
+pub use diff::{GroupDiff, LibraryDiff, diff_libraries};
+
+pub use flatten::{Enumeration, enumerate};
+
+pub use graph::dependency_dot;
+pub use interop::{from_wildcards, parse_prompt_a1111, to_wildcards};
 
 // Eval module exports
-pub use eval::{ChosenOption, EvalContext, RenderError, RenderResult, render};
+pub use eval::{
+    ChosenOption, EvalContext, MissingRefPolicy, OutputSegment, RenderError, RenderResult,
+    PickRng, RenderSummary, SegmentKind, StableRng, normalize_whitespace, render, render_cancellable,
+    render_to_writer, seed_from_label,
+};
 
 #[cfg(feature = "serde")]
 pub use io::{
-    IoError, load_library, load_pack, parse_pack, save_library, save_pack, serialize_pack,
+    IoError, load_library, load_pack, parse_bundle, parse_pack, save_library, save_pack,
+    serialize_bundle, serialize_pack,
 };
 
 pub use library::{
-    EngineHint, Library, PromptGroup, PromptTemplate, SlotKind, TemplateSlot, new_id,
+    EngineHint, Library, OptionMeta, PromptGroup, PromptTemplate, SlotKind, TemplateSlot, ambiguous_group_names,
+    derived_id, new_id,
+};
+pub use merge::{ConflictStrategy, MergeConflicts, MergeError};
+pub use output::OutputBuilder;
+pub use parser::{
+    Limits, ParseError, parse_template, parse_template_recover, parse_template_with_limits,
 };
-pub use parser::{ParseError, parse_template};
+pub use rename::RenameReport;
+pub use source::template_to_source;
 pub use span::Span;
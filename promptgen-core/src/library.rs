@@ -3,16 +3,33 @@
 //! A Library contains reusable prompt groups and templates that can be
 //! evaluated to produce final prompts.
 
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-use crate::ast::{Node, Template};
+use crate::ast::{Node, OptionItem, Spanned, Template};
+use crate::diagnostics::{
+    DiagnosticError, DiagnosticWarning, ParseResult, WarningKind, closest_match,
+};
+use crate::eval::fnv1a;
+use crate::parser::parse_template;
 
 /// Generate a new CUID for use as an ID.
 pub fn new_id() -> String {
     cuid::cuid1().expect("CUID generation should not fail")
 }
 
+/// Derive a stable ID from a key such as a file path.
+///
+/// The same key always gives the same ID, across runs and platforms.
+pub fn derived_id(key: &str) -> String {
+    format!("lib-{:016x}", fnv1a(key.bytes()))
+}
+
 /// Target engine hint for a template.
 /// Determines how the final prompt should be formatted.
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
@@ -31,6 +48,9 @@ pub struct Library {
     pub description: String,
     pub groups: Vec<PromptGroup>,
     pub templates: Vec<PromptTemplate>,
+    /// Seed used when a render doesn't specify one, so shared libraries
+    /// render the same way out of the box.
+    pub default_seed: Option<u64>,
 }
 
 impl Library {
@@ -42,9 +62,16 @@ impl Library {
             description: String::new(),
             groups: Vec::new(),
             templates: Vec::new(),
+            default_seed: None,
         }
     }
 
+    /// Create a new library whose ID is derived from `key`, so the same key
+    /// (e.g. a file path) always gives the same ID.
+    pub fn with_derived_id(key: &str, name: impl Into<String>) -> Self {
+        Self::with_id(derived_id(key), name)
+    }
+
     /// Create a new library with a specific ID (useful for testing or imports).
     pub fn with_id(id: impl Into<String>, name: impl Into<String>) -> Self {
         Self {
@@ -53,18 +80,315 @@ impl Library {
             description: String::new(),
             groups: Vec::new(),
             templates: Vec::new(),
+            default_seed: None,
         }
     }
 
+    /// Whether a reference qualifier such as the `Mine` of `@"Mine:Hair"`
+    /// names this library, by name or id.
+    pub fn is_named(&self, qualifier: &str) -> bool {
+        qualifier == self.name || qualifier == self.id
+    }
+
     /// Find a group by name.
     pub fn find_group(&self, name: &str) -> Option<&PromptGroup> {
         self.groups.iter().find(|g| g.name == name)
     }
 
+    /// Find the groups whose names match `name` ignoring case.
+    ///
+    /// More than one result means the library has groups that differ only
+    /// by case, so the name is ambiguous.
+    pub fn find_group_ci(&self, name: &str) -> Vec<&PromptGroup> {
+        let name = name.to_lowercase();
+        self.groups
+            .iter()
+            .filter(|g| g.name.to_lowercase() == name)
+            .collect()
+    }
+
     /// Find a template by name.
     pub fn find_template(&self, name: &str) -> Option<&PromptTemplate> {
         self.templates.iter().find(|t| t.name == name)
     }
+
+    /// The group name closest to `name`, for a "did you mean" hint when
+    /// `name` isn't found. Only names within a few edits are suggested.
+    pub fn suggest_group(&self, name: &str) -> Option<String> {
+        closest_match(name, self.groups.iter().map(|g| g.name.as_str())).map(str::to_string)
+    }
+
+    /// The template name closest to `name`; see [`Library::suggest_group`].
+    pub fn suggest_template(&self, name: &str) -> Option<String> {
+        closest_match(name, self.templates.iter().map(|t| t.name.as_str())).map(str::to_string)
+    }
+
+    /// The seed to render with: an explicit seed wins over the library's
+    /// `default_seed`. `None` means the render should be random.
+    pub fn resolve_seed(&self, explicit: Option<u64>) -> Option<u64> {
+        explicit.or(self.default_seed)
+    }
+
+    /// Parse a template source and check its references against this library.
+    ///
+    /// Syntax errors and references to unknown groups are reported as
+    /// diagnostics with spans, rather than stopping at the first failure.
+    pub fn analyze_template(&self, source: &str) -> ParseResult {
+        let template = match parse_template(source) {
+            Ok(template) => template,
+            Err(err) => {
+                return ParseResult {
+                    template: None,
+                    errors: err.diagnostics(),
+                    warnings: Vec::new(),
+                };
+            }
+        };
+
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+        self.check_references(&template.nodes, &mut errors, &mut warnings);
+
+        ParseResult {
+            template: Some(template),
+            errors,
+            warnings,
+        }
+    }
+
+    /// Find groups that no template uses.
+    ///
+    /// A group counts as used if a template references it directly, or if it
+    /// is referenced from the options of a group that is itself used.
+    /// Returns group names in library order.
+    pub fn find_unused_groups(&self) -> Vec<String> {
+        let mut used: HashSet<String> = HashSet::new();
+        let mut pending: Vec<String> = Vec::new();
+
+        for template in &self.templates {
+            collect_group_refs(&template.ast.nodes, &mut pending);
+        }
+
+        while let Some(name) = pending.pop() {
+            if !used.insert(name.clone()) {
+                continue;
+            }
+            if let Some(group) = self.find_group(&name) {
+                for option in &group.options {
+                    if let Ok(ast) = parse_template(option) {
+                        collect_group_refs(&ast.nodes, &mut pending);
+                    }
+                }
+            }
+        }
+
+        self.groups
+            .iter()
+            .filter(|g| !used.contains(&g.name))
+            .map(|g| g.name.clone())
+            .collect()
+    }
+
+    /// Names used by more than one group, each listed once in library order.
+    ///
+    /// Lookups such as [`Library::find_group`] only ever see the first group
+    /// with a given name.
+    pub fn duplicate_group_names(&self) -> Vec<String> {
+        let mut seen = HashSet::new();
+        let mut duplicates: Vec<String> = Vec::new();
+
+        for group in &self.groups {
+            if !seen.insert(group.name.as_str()) && !duplicates.contains(&group.name) {
+                duplicates.push(group.name.clone());
+            }
+        }
+
+        duplicates
+    }
+
+    /// Groups with an option that references the group directly, such as
+    /// `@A` among the options of `A`. Rendering fails with a circular
+    /// reference whenever such an option is picked. Returns group names in
+    /// library order.
+    pub fn self_referential_groups(&self) -> Vec<String> {
+        self.groups
+            .iter()
+            .filter(|group| {
+                group.options.iter().any(|option| {
+                    let mut refs = Vec::new();
+                    if let Ok(ast) = parse_template(option) {
+                        collect_group_refs(&ast.nodes, &mut refs);
+                    }
+                    refs.contains(&group.name)
+                })
+            })
+            .map(|group| group.name.clone())
+            .collect()
+    }
+
+    /// Library-level warnings, independent of any single template.
+    pub fn warnings(&self) -> Vec<DiagnosticWarning> {
+        let duplicates = self.duplicate_group_names().into_iter().map(|name| DiagnosticWarning {
+            kind: WarningKind::DuplicateName,
+            message: format!(
+                "group name '{}' is used more than once; references use the first group",
+                name
+            ),
+            span: None,
+        });
+        let unused = self.find_unused_groups().into_iter().map(|name| DiagnosticWarning {
+            kind: WarningKind::Unused,
+            message: format!("group '{}' is not used by any template", name),
+            span: None,
+        });
+
+        let self_refs = self.self_referential_groups().into_iter().map(|name| DiagnosticWarning {
+            kind: WarningKind::SelfReference,
+            message: format!(
+                "group '{}' has an option that references the group itself",
+                name
+            ),
+            span: None,
+        });
+
+        duplicates.chain(self_refs).chain(unused).collect()
+    }
+
+    /// Report library references in `nodes` that don't name a group, and
+    /// warn about qualifiers naming another library and inline options
+    /// that can only render empty.
+    fn check_references(
+        &self,
+        nodes: &[Spanned<Node>],
+        errors: &mut Vec<DiagnosticError>,
+        warnings: &mut Vec<DiagnosticWarning>,
+    ) {
+        for (node, span) in nodes {
+            match node {
+                Node::LibraryRef(lib_ref) => {
+                    let here = lib_ref.library.as_deref().is_none_or(|lib| self.is_named(lib));
+                    if let Some(library) = &lib_ref.library
+                        && !here
+                    {
+                        warnings.push(DiagnosticWarning {
+                            kind: WarningKind::IgnoredQualifier,
+                            message: format!(
+                                "qualifier '{}' doesn't name this library ('{}'), so the \
+                                 reference is rendered as a missing group",
+                                library, self.name
+                            ),
+                            span: Some(span.clone()),
+                        });
+                    }
+                    // A reference with a fallback may name a missing group
+                    if lib_ref.fallback.is_none()
+                        && (!here || self.find_group(&lib_ref.group).is_none())
+                    {
+                        errors.push(DiagnosticError {
+                            message: format!("unknown group '{}'", lib_ref.group),
+                            span: span.clone(),
+                            suggestion: if here {
+                                self.suggest_group(&lib_ref.group)
+                            } else {
+                                None
+                            },
+                        });
+                    }
+                }
+                Node::InlineOptions(options) => {
+                    let all_empty = options.iter().all(|option| match option {
+                        OptionItem::Text(text) => text.is_empty(),
+                        OptionItem::Nested(nested) => nested.is_empty(),
+                    });
+                    if all_empty {
+                        warnings.push(DiagnosticWarning {
+                            kind: WarningKind::EmptyInlineOptions,
+                            message: "inline options are all empty, so they never render anything"
+                                .to_string(),
+                            span: Some(span.clone()),
+                        });
+                    }
+
+                    for option in options {
+                        if let OptionItem::Nested(nested) = option {
+                            self.check_references(nested, errors, warnings);
+                        }
+                    }
+                }
+                Node::MaybeOption(maybe) => {
+                    // The text is only parsed here, so its problems point at
+                    // the whole maybe-option
+                    if let Ok(ast) = parse_template(&maybe.text) {
+                        let mut inner_errors = Vec::new();
+                        let mut inner_warnings = Vec::new();
+                        self.check_references(&ast.nodes, &mut inner_errors, &mut inner_warnings);
+                        errors.extend(inner_errors.into_iter().map(|error| DiagnosticError {
+                            span: span.clone(),
+                            ..error
+                        }));
+                        warnings.extend(inner_warnings.into_iter().map(|warning| {
+                            DiagnosticWarning {
+                                span: Some(span.clone()),
+                                ..warning
+                            }
+                        }));
+                    }
+                }
+                Node::Text(_) | Node::Slot(_) | Node::SlotRef(_) | Node::Comment(_) => {}
+            }
+        }
+    }
+}
+
+/// Collect the group names referenced in `nodes`, including references inside
+/// inline options that are only parsed at render time.
+pub(crate) fn collect_group_refs(nodes: &[Spanned<Node>], refs: &mut Vec<String>) {
+    for (node, _span) in nodes {
+        match node {
+            Node::LibraryRef(lib_ref) => refs.push(lib_ref.group.clone()),
+            Node::InlineOptions(options) => {
+                for option in options {
+                    match option {
+                        OptionItem::Text(text) => {
+                            if let Ok(ast) = parse_template(text) {
+                                collect_group_refs(&ast.nodes, refs);
+                            }
+                        }
+                        OptionItem::Nested(nested) => collect_group_refs(nested, refs),
+                    }
+                }
+            }
+            Node::MaybeOption(maybe) => {
+                if let Ok(ast) = parse_template(&maybe.text) {
+                    collect_group_refs(&ast.nodes, refs);
+                }
+            }
+            Node::Text(_) | Node::Slot(_) | Node::SlotRef(_) | Node::Comment(_) => {}
+        }
+    }
+}
+
+/// Find group names defined in more than one of `libraries`.
+///
+/// An unqualified `@Name` is ambiguous when several libraries are in use
+/// together. Returns each such name with the names of the libraries that
+/// define it, in the order given; names are sorted.
+pub fn ambiguous_group_names<'a>(
+    libraries: impl IntoIterator<Item = &'a Library>,
+) -> Vec<(String, Vec<String>)> {
+    let mut owners: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for library in libraries {
+        let names: HashSet<&str> = library.groups.iter().map(|g| g.name.as_str()).collect();
+        for name in names {
+            owners.entry(name).or_default().push(library.name.clone());
+        }
+    }
+
+    owners
+        .into_iter()
+        .filter(|(_, libraries)| libraries.len() > 1)
+        .map(|(name, libraries)| (name.to_string(), libraries))
+        .collect()
 }
 
 /// A prompt group is a collection of related prompt options.
@@ -80,6 +404,20 @@ pub struct PromptGroup {
     /// Options stored as strings, parsed lazily at render time.
     /// Options can contain nested grammar (e.g., `@Color eyes`).
     pub options: Vec<String>,
+    /// Alternate spellings (alias -> canonical option). Picking an alias
+    /// renders the canonical text instead.
+    pub aliases: HashMap<String, String>,
+    /// Notes on individual options (option text -> metadata). Never rendered.
+    pub option_meta: HashMap<String, OptionMeta>,
+}
+
+/// Author annotations on a group option.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OptionMeta {
+    /// Free-form note, e.g. when to use the option.
+    pub note: Option<String>,
+    /// Where the option came from, such as a URL.
+    pub source: Option<String>,
 }
 
 impl PromptGroup {
@@ -88,6 +426,8 @@ impl PromptGroup {
         Self {
             name: name.into(),
             options,
+            aliases: HashMap::new(),
+            option_meta: HashMap::new(),
         }
     }
 
@@ -96,8 +436,40 @@ impl PromptGroup {
         Self {
             name: name.into(),
             options: options.into_iter().map(Into::into).collect(),
+            aliases: HashMap::new(),
+            option_meta: HashMap::new(),
         }
     }
+
+    /// The text an option renders as: its canonical form if it is an alias,
+    /// otherwise the option itself.
+    pub fn canonical_option<'a>(&'a self, option: &'a str) -> &'a str {
+        self.aliases.get(option).map_or(option, String::as_str)
+    }
+
+    /// Remove options that exactly repeat an earlier one, keeping the first
+    /// occurrence in place. Returns how many were removed.
+    pub fn dedupe(&mut self) -> usize {
+        let before = self.options.len();
+        let mut seen = HashSet::new();
+        self.options.retain(|option| seen.insert(option.clone()));
+        before - self.options.len()
+    }
+
+    /// The options in a shuffled order that depends only on `seed`.
+    pub fn shuffled(&self, seed: u64) -> Vec<String> {
+        let mut options = self.options.clone();
+        options.shuffle(&mut StdRng::seed_from_u64(seed));
+        options
+    }
+
+    /// Up to `len` options starting at `offset`, so a long group can be
+    /// shown a page at a time. Empty once `offset` is past the end.
+    pub fn options_page(&self, offset: usize, len: usize) -> &[String] {
+        let start = offset.min(self.options.len());
+        let end = start.saturating_add(len).min(self.options.len());
+        &self.options[start..end]
+    }
 }
 
 /// A prompt template that can be evaluated against a library.
@@ -108,6 +480,10 @@ pub struct PromptTemplate {
     pub description: String,
     pub engine_hint: EngineHint,
     pub ast: Template,
+    /// Slot values used when a render doesn't supply its own.
+    pub default_slots: HashMap<String, String>,
+    /// Free-form labels for organizing templates.
+    pub tags: Vec<String>,
 }
 
 impl PromptTemplate {
@@ -119,6 +495,8 @@ impl PromptTemplate {
             description: String::new(),
             engine_hint: EngineHint::default(),
             ast,
+            default_slots: HashMap::new(),
+            tags: Vec::new(),
         }
     }
 
@@ -130,20 +508,45 @@ impl PromptTemplate {
             description: String::new(),
             engine_hint: EngineHint::default(),
             ast,
+            default_slots: HashMap::new(),
+            tags: Vec::new(),
         }
     }
 
+    /// Whether this template has `tag`, ignoring case.
+    pub fn has_tag(&self, tag: &str) -> bool {
+        let tag = tag.to_lowercase();
+        self.tags.iter().any(|t| t.to_lowercase() == tag)
+    }
+
+    /// Slot values for a render: the template's defaults, with `overrides`
+    /// replacing them key by key.
+    pub fn slots_with_defaults(
+        &self,
+        overrides: &HashMap<String, String>,
+    ) -> HashMap<String, String> {
+        let mut slots = self.default_slots.clone();
+        slots.extend(overrides.iter().map(|(k, v)| (k.clone(), v.clone())));
+        slots
+    }
+
     /// Extract all slots from this template.
-    /// Returns slots defined by `{{ Name }}` syntax.
+    /// Returns slots defined by `{{ Name }}` or `{{ Name! }}` syntax, once
+    /// each in the order they first appear. A slot is required if any of
+    /// its blocks is.
     pub fn slots(&self) -> Vec<TemplateSlot> {
-        let mut slots = Vec::new();
+        let mut slots: Vec<TemplateSlot> = Vec::new();
 
         for (node, _span) in &self.ast.nodes {
-            if let Node::Slot(name) = node {
-                slots.push(TemplateSlot {
-                    name: name.clone(),
-                    kind: SlotKind::Freeform,
-                });
+            if let Node::Slot(slot) = node {
+                match slots.iter_mut().find(|s| s.name == slot.name) {
+                    Some(existing) => existing.required |= slot.required,
+                    None => slots.push(TemplateSlot {
+                        name: slot.name.clone(),
+                        kind: SlotKind::Freeform,
+                        required: slot.required,
+                    }),
+                }
             }
         }
 
@@ -170,6 +573,8 @@ impl PromptTemplate {
 pub struct TemplateSlot {
     pub name: String,
     pub kind: SlotKind,
+    /// Whether rendering fails without a value (`{{ Name! }}`).
+    pub required: bool,
 }
 
 /// The kind of slot in a template.
@@ -184,6 +589,31 @@ mod tests {
     use super::*;
     use crate::parser::parse_template;
 
+    #[test]
+    fn test_ambiguous_group_names() {
+        let mut characters = Library::new("Characters");
+        characters.groups.push(PromptGroup::new("Hair", vec![]));
+        characters.groups.push(PromptGroup::new("Eyes", vec![]));
+        // Defined twice within one library: not ambiguous across libraries
+        characters.groups.push(PromptGroup::new("Eyes", vec![]));
+
+        let mut styles = Library::new("Styles");
+        styles.groups.push(PromptGroup::new("Lighting", vec![]));
+        styles.groups.push(PromptGroup::new("Hair", vec![]));
+
+        let mut scenes = Library::new("Scenes");
+        scenes.groups.push(PromptGroup::new("Lighting", vec![]));
+
+        assert_eq!(
+            ambiguous_group_names([&characters, &styles, &scenes]),
+            vec![
+                ("Hair".to_string(), vec!["Characters".to_string(), "Styles".to_string()]),
+                ("Lighting".to_string(), vec!["Styles".to_string(), "Scenes".to_string()]),
+            ]
+        );
+        assert!(ambiguous_group_names([&characters, &scenes]).is_empty());
+    }
+
     #[test]
     fn test_library_new() {
         let lib = Library::new("My Library");
@@ -205,6 +635,246 @@ mod tests {
         assert!(lib.find_group("Nose").is_none());
     }
 
+    #[test]
+    fn test_library_suggest_names() {
+        let mut lib = Library::new("Test");
+        lib.groups.push(PromptGroup::new("Hair", vec![]));
+        lib.groups.push(PromptGroup::new("Eye Color", vec![]));
+        lib.templates.push(PromptTemplate::new("Portrait", parse_template("x").unwrap()));
+
+        assert_eq!(lib.suggest_group("Eye Colour"), Some("Eye Color".to_string()));
+        assert_eq!(lib.suggest_group("Hiar"), Some("Hair".to_string()));
+        assert_eq!(lib.suggest_group("Background"), None);
+        assert_eq!(lib.suggest_template("Portrat"), Some("Portrait".to_string()));
+    }
+
+    #[test]
+    fn test_analyze_template_qualifier_for_another_library() {
+        let mut lib = Library::with_id("test-id", "Test");
+        lib.groups.push(PromptGroup::with_options("Hair", vec!["red hair"]));
+
+        let result = lib.analyze_template(r#"@"Test:Hair" @"test-id:Hair""#);
+        assert!(result.is_ok());
+        assert!(result.warnings.is_empty());
+
+        // Rendering treats the group as missing, so only a fallback helps
+        let result = lib.analyze_template(r#"A @"Tset:Hair""#);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].span, 2..14);
+        assert_eq!(result.warnings.len(), 1);
+        assert_eq!(result.warnings[0].kind, WarningKind::IgnoredQualifier);
+        assert_eq!(result.warnings[0].span, Some(2..14));
+        assert_eq!(
+            result.warnings[0].message,
+            "qualifier 'Tset' doesn't name this library ('Test'), so the reference is \
+             rendered as a missing group"
+        );
+        let result = lib.analyze_template(r#"A @"Tset:Hair" ?? "hair""#);
+        assert!(result.is_ok());
+        assert_eq!(result.warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_analyze_template_warns_on_empty_inline_options() {
+        let lib = Library::new("Test");
+
+        for source in ["a {red|} ball", "a {red} ball", "{\" \"|}"] {
+            let result = lib.analyze_template(source);
+            assert!(result.warnings.is_empty(), "{source}");
+        }
+
+        let result = lib.analyze_template("a {} ball {|  }");
+        assert!(result.is_ok());
+        let kinds: Vec<_> = result.warnings.iter().map(|w| w.kind).collect();
+        assert_eq!(kinds, vec![WarningKind::EmptyInlineOptions; 2]);
+        assert_eq!(result.warnings[0].span, Some(2..4));
+        assert_eq!(result.warnings[1].span, Some(10..15));
+    }
+
+    #[test]
+    fn test_analyze_template_unknown_ref_with_suggestion() {
+        let mut lib = Library::new("Test");
+        lib.groups.push(PromptGroup::new("Hair", vec![]));
+
+        let result = lib.analyze_template("A girl with @Hiar and @Hair");
+        assert!(result.template.is_some());
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].message, "unknown group 'Hiar'");
+        assert_eq!(result.errors[0].span, 12..17);
+        assert_eq!(result.errors[0].suggestion, Some("Hair".to_string()));
+        assert!(!result.is_ok());
+    }
+
+    #[test]
+    fn test_analyze_template_unknown_ref_in_maybe_option() {
+        let mut lib = Library::new("Test");
+        lib.groups.push(PromptGroup::new("Hair", vec![]));
+
+        let result = lib.analyze_template("a {@Hiar?} girl");
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].message, "unknown group 'Hiar'");
+        assert_eq!(result.errors[0].span, 2..10);
+    }
+
+    #[test]
+    fn test_analyze_template_syntax_error() {
+        let lib = Library::new("Test");
+
+        let result = lib.analyze_template("{red|blue");
+        assert!(result.template.is_none());
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].span, 9..9);
+    }
+
+    #[test]
+    fn test_analyze_template_allows_missing_group_with_fallback() {
+        let lib = Library::new("Test");
+
+        let result = lib.analyze_template(r#"@Hat ?? "no hat""#);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_library_find_group_ci() {
+        let mut lib = Library::new("Test");
+        lib.groups.push(PromptGroup::new("Eye Color", vec![]));
+        lib.groups.push(PromptGroup::new("Hair", vec![]));
+        lib.groups.push(PromptGroup::new("HAIR", vec![]));
+
+        let names = |groups: Vec<&PromptGroup>| -> Vec<String> {
+            groups.into_iter().map(|g| g.name.clone()).collect()
+        };
+        assert_eq!(names(lib.find_group_ci("eye color")), vec!["Eye Color"]);
+        assert_eq!(names(lib.find_group_ci("hair")), vec!["Hair", "HAIR"]);
+        assert!(lib.find_group_ci("nose").is_empty());
+    }
+
+    #[test]
+    fn test_resolve_seed_precedence() {
+        let mut lib = Library::new("Test");
+        assert_eq!(lib.resolve_seed(None), None);
+        assert_eq!(lib.resolve_seed(Some(7)), Some(7));
+
+        lib.default_seed = Some(42);
+        assert_eq!(lib.resolve_seed(None), Some(42));
+        assert_eq!(lib.resolve_seed(Some(7)), Some(7));
+    }
+
+    #[test]
+    fn test_slots_with_defaults_overrides_by_key() {
+        let mut template = PromptTemplate::new("t", parse_template("{{ A }} {{ B }}").unwrap());
+        template.default_slots.insert("A".to_string(), "default a".to_string());
+        template.default_slots.insert("B".to_string(), "default b".to_string());
+
+        let overrides = HashMap::from([("B".to_string(), "user b".to_string())]);
+        let slots = template.slots_with_defaults(&overrides);
+
+        assert_eq!(slots.len(), 2);
+        assert_eq!(slots["A"], "default a");
+        assert_eq!(slots["B"], "user b");
+    }
+
+    #[test]
+    fn test_template_has_tag_ignores_case() {
+        let mut template = PromptTemplate::new("t", parse_template("text").unwrap());
+        assert!(!template.has_tag("portrait"));
+
+        template.tags = vec!["Portrait".to_string(), "wip".to_string()];
+        assert!(template.has_tag("portrait"));
+        assert!(template.has_tag("WIP"));
+        assert!(!template.has_tag("landscape"));
+    }
+
+    #[test]
+    fn test_derived_id_is_stable() {
+        let a = Library::with_derived_id("/libs/portraits.yml", "Portraits");
+        let b = Library::with_derived_id("/libs/portraits.yml", "Renamed");
+        assert_eq!(a.id, b.id);
+        assert_ne!(a.id, derived_id("/libs/landscapes.yml"));
+
+        // Pinned so a change to the hash shows up as a test failure
+        assert_eq!(derived_id(""), "lib-cbf29ce484222325");
+    }
+
+    #[test]
+    fn test_duplicate_group_names() {
+        let mut lib = Library::new("Test");
+        lib.groups.push(PromptGroup::with_options("Color", vec!["red"]));
+        lib.groups.push(PromptGroup::with_options("Hair", vec!["long"]));
+        lib.groups.push(PromptGroup::with_options("Color", vec!["blue"]));
+        lib.groups.push(PromptGroup::with_options("Color", vec!["green"]));
+        assert_eq!(lib.duplicate_group_names(), vec!["Color"]);
+
+        // The first group wins lookups
+        assert_eq!(lib.find_group("Color").unwrap().options, vec!["red"]);
+
+        let warnings = lib.warnings();
+        assert_eq!(warnings[0].kind, WarningKind::DuplicateName);
+        assert!(warnings[0].message.contains("'Color'"));
+    }
+
+    #[test]
+    fn test_find_unused_groups() {
+        let mut lib = Library::new("Test");
+        lib.groups
+            .push(PromptGroup::with_options("Eyes", vec!["@Color eyes"]));
+        lib.groups
+            .push(PromptGroup::with_options("Color", vec!["red", "blue"]));
+        lib.groups
+            .push(PromptGroup::with_options("Orphan", vec!["unused"]));
+        lib.groups
+            .push(PromptGroup::with_options("Mood", vec!["happy"]));
+        lib.templates.push(PromptTemplate::new(
+            "Portrait",
+            parse_template("@Eyes, {smiling|@Mood}").unwrap(),
+        ));
+
+        // Color is only used through Eyes' options; Mood only inside inline options
+        assert_eq!(lib.find_unused_groups(), vec!["Orphan".to_string()]);
+
+        let warnings = lib.warnings();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, WarningKind::Unused);
+        assert!(warnings[0].message.contains("Orphan"));
+    }
+
+    #[test]
+    fn test_group_referenced_only_by_unused_group_is_unused() {
+        let mut lib = Library::new("Test");
+        lib.groups
+            .push(PromptGroup::with_options("Outer", vec!["@Inner"]));
+        lib.groups
+            .push(PromptGroup::with_options("Inner", vec!["x"]));
+
+        assert_eq!(
+            lib.find_unused_groups(),
+            vec!["Outer".to_string(), "Inner".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_self_referential_groups() {
+        let mut lib = Library::new("Test");
+        lib.groups
+            .push(PromptGroup::with_options("Loop", vec!["end", "more {@Loop|again}"]));
+        lib.groups
+            .push(PromptGroup::with_options("Outer", vec!["@Inner"]));
+        lib.groups
+            .push(PromptGroup::with_options("Inner", vec!["x"]));
+        lib.templates.push(PromptTemplate::new(
+            "Portrait",
+            parse_template("@Loop @Outer").unwrap(),
+        ));
+
+        // Outer only reaches itself through another group, which is fine
+        assert_eq!(lib.self_referential_groups(), vec!["Loop".to_string()]);
+
+        let warnings = lib.warnings();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, WarningKind::SelfReference);
+        assert!(warnings[0].message.contains("'Loop'"));
+    }
+
     #[test]
     fn test_group_with_options() {
         let group = PromptGroup::with_options(
@@ -216,6 +886,55 @@ mod tests {
         assert_eq!(group.options[0], "blonde hair");
     }
 
+    #[test]
+    fn test_group_dedupe_keeps_first_occurrence() {
+        let mut group = PromptGroup::with_options(
+            "Hair",
+            vec!["red hair", "blonde hair", "red hair", "black hair", "blonde hair", "red hair"],
+        );
+        assert_eq!(group.dedupe(), 3);
+        assert_eq!(group.options, vec!["red hair", "blonde hair", "black hair"]);
+        assert_eq!(group.dedupe(), 0);
+    }
+
+    #[test]
+    fn test_group_shuffled_is_deterministic() {
+        let options: Vec<String> = (0..20).map(|i| format!("color {i}")).collect();
+        let group = PromptGroup::new("Color", options);
+
+        let first = group.shuffled(7);
+        assert_eq!(first, group.shuffled(7));
+        assert_ne!(first, group.options);
+        assert_ne!(first, group.shuffled(8));
+
+        let mut sorted = first.clone();
+        sorted.sort();
+        let mut original = group.options.clone();
+        original.sort();
+        assert_eq!(sorted, original);
+        assert_eq!(group.options[0], "color 0");
+    }
+
+    #[test]
+    fn test_group_options_page_bounds() {
+        let options: Vec<String> = (0..5).map(|i| i.to_string()).collect();
+        let group = PromptGroup::new("Digits", options);
+
+        assert_eq!(group.options_page(0, 2), ["0", "1"]);
+        assert_eq!(group.options_page(3, 10), ["3", "4"]);
+        assert!(group.options_page(5, 2).is_empty());
+        assert!(group.options_page(9, 2).is_empty());
+        assert!(group.options_page(1, 0).is_empty());
+        assert_eq!(group.options_page(4, usize::MAX), ["4"]);
+    }
+
+    #[test]
+    fn test_group_dedupe_is_exact() {
+        let mut group = PromptGroup::with_options("Hair", vec!["red hair", "Red hair", "red hair "]);
+        assert_eq!(group.dedupe(), 0);
+        assert_eq!(group.options.len(), 3);
+    }
+
     #[test]
     fn test_template_slots_freeform() {
         let ast = parse_template("Hello {{ Name }}, welcome to {{ Place }}!").unwrap();
@@ -229,6 +948,22 @@ mod tests {
         assert_eq!(slots[1].kind, SlotKind::Freeform);
     }
 
+    #[test]
+    fn test_template_slots_keep_document_order() {
+        let ast = parse_template("{{ c }} {{ a }} {{ b }}").unwrap();
+        let template = PromptTemplate::new("ordered", ast);
+
+        let names: Vec<String> = template.slots().into_iter().map(|s| s.name).collect();
+        assert_eq!(names, vec!["c", "a", "b"]);
+
+        // Repeated slots are listed where they first appear
+        let ast = parse_template("{{ a }} {{ b }} {{ a! }}").unwrap();
+        let slots = PromptTemplate::new("repeated", ast).slots();
+        let names: Vec<&str> = slots.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["a", "b"]);
+        assert!(slots[0].required);
+    }
+
     #[test]
     fn test_template_referenced_groups() {
         let ast = parse_template(r#"@Hair and @"Eye Color""#).unwrap();
@@ -0,0 +1,198 @@
+//! Diagnostics in the shape the Language Server Protocol expects.
+//!
+//! Spans in [`DiagnosticError`] and [`DiagnosticWarning`] are byte offsets;
+//! LSP positions are a zero-based line and a column counted in UTF-16 code
+//! units, so `🎨` moves the column by 2 although it takes 4 bytes.
+
+use serde::{Serialize, Serializer};
+
+use crate::diagnostics::{DiagnosticError, DiagnosticWarning, ParseResult};
+use crate::span::Span;
+
+/// A zero-based line and UTF-16 column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
+
+/// The text a diagnostic covers, end exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+/// How serious a diagnostic is; serialized as the LSP severity number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error = 1,
+    Warning = 2,
+}
+
+impl Serialize for Severity {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(*self as u8)
+    }
+}
+
+/// A diagnostic ready to send to an editor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LspDiagnostic {
+    pub range: Range,
+    pub severity: Severity,
+    /// Always `"promptgen"`.
+    pub source: String,
+    /// The diagnostic's message, with any suggestion appended.
+    pub message: String,
+}
+
+/// Convert the errors and then the warnings of a checked template.
+pub fn to_lsp_diagnostics(source: &str, result: &ParseResult) -> Vec<LspDiagnostic> {
+    let errors = result
+        .errors
+        .iter()
+        .map(|error| error_to_lsp(source, error));
+    let warnings = result
+        .warnings
+        .iter()
+        .map(|warning| warning_to_lsp(source, warning));
+    errors.chain(warnings).collect()
+}
+
+/// Convert one error; `source` is the text its span points into.
+pub fn error_to_lsp(source: &str, error: &DiagnosticError) -> LspDiagnostic {
+    let message = match &error.suggestion {
+        Some(suggestion) => format!("{} (did you mean '{}'?)", error.message, suggestion),
+        None => error.message.clone(),
+    };
+    diagnostic(source, &error.span, Severity::Error, message)
+}
+
+/// Convert one warning. A warning without a span, such as one about the
+/// library as a whole, is placed at the start of the source.
+pub fn warning_to_lsp(source: &str, warning: &DiagnosticWarning) -> LspDiagnostic {
+    let span = warning.span.clone().unwrap_or(0..0);
+    diagnostic(source, &span, Severity::Warning, warning.message.clone())
+}
+
+fn diagnostic(source: &str, span: &Span, severity: Severity, message: String) -> LspDiagnostic {
+    LspDiagnostic {
+        range: Range {
+            start: position_at(source, span.start),
+            end: position_at(source, span.end),
+        },
+        severity,
+        source: "promptgen".to_string(),
+        message,
+    }
+}
+
+/// The LSP position of byte `offset` in `source`.
+///
+/// An offset past the end maps to the end of the source, and one inside a
+/// multi-byte character to the start of that character.
+pub fn position_at(source: &str, offset: usize) -> Position {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Position {
+        line: before.matches('\n').count() as u32,
+        character: before[line_start..].encode_utf16().count() as u32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::{Library, PromptGroup};
+
+    #[test]
+    fn test_position_counts_utf16_units() {
+        let source = "first line\n🎨 é @Hiar";
+        assert_eq!(
+            position_at(source, 0),
+            Position {
+                line: 0,
+                character: 0
+            }
+        );
+        assert_eq!(
+            position_at(source, 11),
+            Position {
+                line: 1,
+                character: 0
+            }
+        );
+
+        // 🎨 is 4 bytes and 2 UTF-16 units, é is 2 bytes and 1 unit
+        let at = source.find('@').unwrap();
+        assert_eq!(
+            position_at(source, at),
+            Position {
+                line: 1,
+                character: 5
+            }
+        );
+        assert_eq!(
+            position_at(source, source.len()),
+            Position {
+                line: 1,
+                character: 10
+            }
+        );
+
+        // Inside the emoji, and past the end
+        assert_eq!(
+            position_at(source, 13),
+            Position {
+                line: 1,
+                character: 0
+            }
+        );
+        assert_eq!(
+            position_at(source, 100),
+            Position {
+                line: 1,
+                character: 10
+            }
+        );
+    }
+
+    #[test]
+    fn test_to_lsp_diagnostics_with_emoji() {
+        let mut lib = Library::new("Test");
+        lib.groups
+            .push(PromptGroup::with_options("Hair", vec!["red hair"]));
+        let source = "portrait\n🎨 with @Hiar";
+        let result = lib.analyze_template(source);
+
+        let diagnostics = to_lsp_diagnostics(source, &result);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].range,
+            Range {
+                start: Position {
+                    line: 1,
+                    character: 8
+                },
+                end: Position {
+                    line: 1,
+                    character: 13
+                },
+            }
+        );
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(
+            diagnostics[0].message,
+            "unknown group 'Hiar' (did you mean 'Hair'?)"
+        );
+
+        let value = serde_yaml_ng::to_value(&diagnostics[0]).unwrap();
+        assert_eq!(value["severity"], serde_yaml_ng::Value::from(1));
+    }
+}
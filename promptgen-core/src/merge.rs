@@ -0,0 +1,188 @@
+//! Combining several libraries into one.
+//!
+//! Groups and templates are matched by name. When both libraries define the
+//! same name, a [`ConflictStrategy`] decides which definition is kept.
+
+use crate::library::Library;
+
+/// How [`Library::merge`] resolves a name defined in both libraries.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictStrategy {
+    /// Keep the definition already in the library being merged into.
+    First,
+    /// Replace it with the definition from the other library, in place.
+    Last,
+    /// Refuse to merge if any name is defined in both.
+    #[default]
+    Error,
+}
+
+/// Group and template names defined in both libraries.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergeConflicts {
+    pub groups: Vec<String>,
+    pub templates: Vec<String>,
+}
+
+impl MergeConflicts {
+    /// Returns true if the libraries had no names in common.
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty() && self.templates.is_empty()
+    }
+}
+
+/// Returned by [`Library::merge`] under [`ConflictStrategy::Error`].
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "libraries both define {} group(s) and {} template(s)",
+    .0.groups.len(),
+    .0.templates.len()
+)]
+pub struct MergeError(pub MergeConflicts);
+
+impl Library {
+    /// Add the groups and templates of `other` to this library.
+    ///
+    /// The library keeps its own id, name and other metadata. New items are
+    /// appended in `other`'s order. Returns the names that were defined in
+    /// both; with [`ConflictStrategy::Error`] the library is left unchanged
+    /// if there are any.
+    pub fn merge(
+        &mut self,
+        other: Library,
+        strategy: ConflictStrategy,
+    ) -> Result<MergeConflicts, MergeError> {
+        let conflicts = MergeConflicts {
+            groups: other
+                .groups
+                .iter()
+                .filter(|g| self.find_group(&g.name).is_some())
+                .map(|g| g.name.clone())
+                .collect(),
+            templates: other
+                .templates
+                .iter()
+                .filter(|t| self.find_template(&t.name).is_some())
+                .map(|t| t.name.clone())
+                .collect(),
+        };
+        if strategy == ConflictStrategy::Error && !conflicts.is_empty() {
+            return Err(MergeError(conflicts));
+        }
+
+        for group in other.groups {
+            match self.groups.iter_mut().find(|g| g.name == group.name) {
+                Some(existing) => {
+                    if strategy == ConflictStrategy::Last {
+                        *existing = group;
+                    }
+                }
+                None => self.groups.push(group),
+            }
+        }
+        for template in other.templates {
+            match self.templates.iter_mut().find(|t| t.name == template.name) {
+                Some(existing) => {
+                    if strategy == ConflictStrategy::Last {
+                        *existing = template;
+                    }
+                }
+                None => self.templates.push(template),
+            }
+        }
+
+        Ok(conflicts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::{PromptGroup, PromptTemplate};
+    use crate::parser::parse_template;
+
+    fn library(name: &str, groups: &[(&str, &[&str])], templates: &[(&str, &str)]) -> Library {
+        let mut lib = Library::with_id(name, name);
+        for (group, options) in groups {
+            lib.groups.push(PromptGroup::with_options(*group, options.to_vec()));
+        }
+        for (template, source) in templates {
+            let ast = parse_template(source).unwrap();
+            lib.templates.push(PromptTemplate::new(*template, ast));
+        }
+        lib
+    }
+
+    fn base() -> Library {
+        library(
+            "base",
+            &[("Hair", &["red hair"]), ("Eyes", &["blue eyes"])],
+            &[("Portrait", "@Hair")],
+        )
+    }
+
+    fn other() -> Library {
+        library(
+            "other",
+            &[("Hair", &["black hair"]), ("Hat", &["top hat"])],
+            &[("Portrait", "@Hair, @Hat"), ("Landscape", "a valley")],
+        )
+    }
+
+    fn group_options(lib: &Library, name: &str) -> Vec<String> {
+        lib.find_group(name).unwrap().options.clone()
+    }
+
+    #[test]
+    fn test_merge_without_conflicts_appends_everything() {
+        let mut lib = base();
+        let extra = library("extra", &[("Hat", &["cap"])], &[("Landscape", "a valley")]);
+
+        let conflicts = lib.merge(extra, ConflictStrategy::Error).unwrap();
+        assert!(conflicts.is_empty());
+
+        let groups: Vec<&str> = lib.groups.iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(groups, vec!["Hair", "Eyes", "Hat"]);
+        let templates: Vec<&str> = lib.templates.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(templates, vec!["Portrait", "Landscape"]);
+        assert_eq!(lib.id, "base");
+    }
+
+    #[test]
+    fn test_merge_keep_first() {
+        let mut lib = base();
+        let conflicts = lib.merge(other(), ConflictStrategy::First).unwrap();
+
+        assert_eq!(conflicts.groups, vec!["Hair"]);
+        assert_eq!(conflicts.templates, vec!["Portrait"]);
+        assert_eq!(group_options(&lib, "Hair"), vec!["red hair"]);
+        assert_eq!(group_options(&lib, "Hat"), vec!["top hat"]);
+        assert_eq!(lib.find_template("Portrait").unwrap().ast.nodes.len(), 1);
+        assert!(lib.find_template("Landscape").is_some());
+    }
+
+    #[test]
+    fn test_merge_keep_last_replaces_in_place() {
+        let mut lib = base();
+        lib.merge(other(), ConflictStrategy::Last).unwrap();
+
+        assert_eq!(lib.groups[0].name, "Hair");
+        assert_eq!(group_options(&lib, "Hair"), vec!["black hair"]);
+        assert_eq!(lib.templates[0].name, "Portrait");
+        assert_eq!(lib.templates[0].ast.nodes.len(), 3);
+        assert_eq!(lib.groups.len(), 3);
+        assert_eq!(lib.templates.len(), 2);
+    }
+
+    #[test]
+    fn test_merge_error_leaves_library_unchanged() {
+        let mut lib = base();
+        let err = lib.merge(other(), ConflictStrategy::Error).unwrap_err();
+
+        assert_eq!(err.0.groups, vec!["Hair"]);
+        assert_eq!(err.0.templates, vec!["Portrait"]);
+        assert_eq!(err.to_string(), "libraries both define 1 group(s) and 1 template(s)");
+        assert_eq!(lib.groups.len(), 2);
+        assert_eq!(lib.templates.len(), 1);
+    }
+}
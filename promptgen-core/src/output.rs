@@ -0,0 +1,149 @@
+//! Assembling rendered text from a template's top-level nodes.
+//!
+//! With [`EvalContext::skip_empty_separators`](crate::EvalContext) set, an
+//! expansion that comes out empty takes the separator after it along, so
+//! `@Adj, @Noun` doesn't leave a stray comma when `@Adj` has an empty option.
+
+/// Characters that count as a separator between expansions.
+fn is_separator(c: char) -> bool {
+    matches!(c, ',' | ' ' | '\t')
+}
+
+/// Collects the output of each node in order.
+///
+/// Literal text is pushed with [`OutputBuilder::push_text`] and the result
+/// of a reference, inline options or slot with
+/// [`OutputBuilder::push_expansion`]. When separators are skipped, the
+/// commas and spaces at the start of the text following an empty expansion
+/// are dropped, as are any left at the end of the output.
+#[derive(Debug, Default)]
+pub struct OutputBuilder {
+    text: String,
+    skip_empty_separators: bool,
+    /// The last expansion was empty and no text has followed it yet
+    after_empty: bool,
+}
+
+impl OutputBuilder {
+    /// Create a builder; without `skip_empty_separators` it only
+    /// concatenates.
+    pub fn new(skip_empty_separators: bool) -> Self {
+        Self {
+            skip_empty_separators,
+            ..Self::default()
+        }
+    }
+
+    /// Append literal template text.
+    pub fn push_text(&mut self, text: &str) {
+        if !self.after_empty {
+            self.text.push_str(text);
+            return;
+        }
+
+        let rest = text.trim_start_matches(is_separator);
+        if !rest.is_empty() {
+            self.text.push_str(rest);
+            self.after_empty = false;
+        }
+    }
+
+    /// Append the text an expansion produced.
+    pub fn push_expansion(&mut self, text: &str) {
+        if self.skip_empty_separators && text.trim().is_empty() {
+            self.after_empty = true;
+            return;
+        }
+        self.text.push_str(text);
+        self.after_empty = false;
+    }
+
+    /// Length in bytes of the text so far. Separators at the end may still
+    /// be trimmed by [`OutputBuilder::finish`].
+    pub fn len(&self) -> usize {
+        self.text.len()
+    }
+
+    /// Returns true if nothing has been added yet.
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Take the text [`OutputBuilder::finish`] can no longer change, for
+    /// writing out as it is produced. Only separators at the end are held
+    /// back, since an empty expansion after them trims them.
+    pub fn take_ready(&mut self) -> String {
+        let ready = if self.skip_empty_separators {
+            self.text.trim_end_matches(is_separator).len()
+        } else {
+            self.text.len()
+        };
+        let rest = self.text.split_off(ready);
+        std::mem::replace(&mut self.text, rest)
+    }
+
+    /// The assembled text.
+    pub fn finish(self) -> String {
+        if self.after_empty {
+            self.text.trim_end_matches(is_separator).to_string()
+        } else {
+            self.text
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build from alternating text and expansions, starting with text.
+    fn build(chunks: &[&str], skip: bool) -> String {
+        let mut builder = OutputBuilder::new(skip);
+        for (i, chunk) in chunks.iter().enumerate() {
+            if i % 2 == 0 {
+                builder.push_text(chunk);
+            } else {
+                builder.push_expansion(chunk);
+            }
+        }
+        builder.finish()
+    }
+
+    #[test]
+    fn test_empty_expansion_in_the_middle() {
+        let chunks = ["", "red hair", ", ", "", ", ", "blue eyes", ", portrait"];
+        assert_eq!(build(&chunks, true), "red hair, blue eyes, portrait");
+        assert_eq!(build(&chunks, false), "red hair, , blue eyes, portrait");
+    }
+
+    #[test]
+    fn test_empty_expansions_at_the_edges() {
+        assert_eq!(build(&["", "", ", ", "cat", ", ", ""], true), "cat");
+        assert_eq!(build(&["a ", "", " ", "", " cat"], true), "a cat");
+        assert_eq!(build(&["", "", ", ", "", ", ", ""], true), "");
+    }
+
+    #[test]
+    fn test_text_after_empty_expansion_is_kept() {
+        assert_eq!(build(&["(", "", ") and ", "dog"], true), "() and dog");
+    }
+
+    #[test]
+    fn test_take_ready_adds_up_to_finish() {
+        let chunks = ["", "cat", ", ", "", ", ", "dog", ", ", ""];
+        for skip in [false, true] {
+            let mut builder = OutputBuilder::new(skip);
+            let mut taken = String::new();
+            for (i, chunk) in chunks.iter().enumerate() {
+                if i % 2 == 0 {
+                    builder.push_text(chunk);
+                } else {
+                    builder.push_expansion(chunk);
+                }
+                taken.push_str(&builder.take_ready());
+            }
+            taken.push_str(&builder.finish());
+            assert_eq!(taken, build(&chunks, skip));
+        }
+    }
+}
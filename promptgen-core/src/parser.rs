@@ -1,13 +1,69 @@
 use chumsky::prelude::*;
 use chumsky::{error::Simple, extra, span::SimpleSpan};
 
-use crate::ast::{LibraryRef, Node, OptionItem, Template};
+use crate::ast::{CaseKind, LibraryRef, MaybeOption, Node, OptionItem, Slot, Spanned, Template};
+use crate::diagnostics::DiagnosticError;
 use crate::span::Span;
 
 #[derive(Debug, thiserror::Error)]
 pub enum ParseError<'a> {
     #[error("parse error(s): {0:?}")]
     Chumsky(Vec<Simple<'a, char>>),
+
+    #[error("input is {len} bytes, more than the limit of {max}")]
+    InputTooLarge { len: usize, max: usize },
+
+    #[error("braces are nested more than {max} deep")]
+    TooDeeplyNested {
+        max: usize,
+        /// Byte offset of the brace that went over the limit
+        at: usize,
+    },
+}
+
+impl ParseError<'_> {
+    /// Convert to diagnostics carrying the span of each syntax error.
+    pub fn diagnostics(&self) -> Vec<DiagnosticError> {
+        match self {
+            ParseError::Chumsky(errs) => errs.iter().map(to_diagnostic).collect(),
+            ParseError::InputTooLarge { len, max } => {
+                vec![DiagnosticError::new(self.to_string(), *max..*len)]
+            }
+            ParseError::TooDeeplyNested { at, .. } => {
+                vec![DiagnosticError::new(self.to_string(), *at..*at + 1)]
+            }
+        }
+    }
+}
+
+/// Bounds on the input [`parse_template_with_limits`] will parse, so
+/// untrusted text can't make parsing slow. [`parse_template`] and
+/// [`parse_template_recover`] apply the defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Longest accepted source, in bytes.
+    pub max_len: usize,
+    /// Deepest accepted nesting of `{` braces, counting each brace of a
+    /// `{{ slot }}`.
+    pub max_depth: usize,
+}
+
+impl Default for Limits {
+    /// 1 MiB of source and 64 levels of braces, far beyond any real template.
+    fn default() -> Self {
+        Self {
+            max_len: 1024 * 1024,
+            max_depth: 64,
+        }
+    }
+}
+
+fn to_diagnostic(err: &Simple<'_, char>) -> DiagnosticError {
+    let message = match err.found() {
+        Some(c) => format!("unexpected '{}'", c),
+        None => "unexpected end of input".to_string(),
+    };
+    DiagnosticError::new(message, to_range(*err.span()))
 }
 
 /// Helper to convert Chumsky spans to our custom Span
@@ -31,7 +87,47 @@ fn parse_library_ref_string(s: &str) -> LibraryRef {
     }
 }
 
+/// Parse a template.
+///
+/// Source longer or more deeply nested than [`Limits::default`] allows is
+/// rejected before parsing, since each level of nested options recurses.
 pub fn parse_template(src: &str) -> Result<Template, ParseError<'_>> {
+    parse_template_with_limits(src, Limits::default())
+}
+
+/// Reject `src` if it is longer or more deeply nested than `limits` allow.
+fn check_limits(src: &str, limits: Limits) -> Result<(), ParseError<'_>> {
+    if src.len() > limits.max_len {
+        return Err(ParseError::InputTooLarge {
+            len: src.len(),
+            max: limits.max_len,
+        });
+    }
+
+    let max_depth = limits.max_depth;
+    let mut depth = 0usize;
+    for (at, c) in src.char_indices() {
+        match c {
+            '{' => {
+                depth += 1;
+                if depth > max_depth {
+                    return Err(ParseError::TooDeeplyNested {
+                        max: max_depth,
+                        at,
+                    });
+                }
+            }
+            '}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Parse a template, first rejecting source longer or more deeply nested
+/// than `limits` allow.
+pub fn parse_template_with_limits(src: &str, limits: Limits) -> Result<Template, ParseError<'_>> {
+    check_limits(src, limits)?;
     let result = template_parser().parse(src);
 
     match result.into_result() {
@@ -40,6 +136,31 @@ pub fn parse_template(src: &str) -> Result<Template, ParseError<'_>> {
     }
 }
 
+/// Parse a template, recovering from syntax errors instead of stopping.
+///
+/// Malformed regions are skipped and parsing resumes after them, so the
+/// returned template holds every node that could be parsed. The source is
+/// valid only if the returned errors are empty, in which case the template
+/// is the same as [`parse_template`] would return. Source over the
+/// [`Limits::default`] gives no nodes and that one error.
+pub fn parse_template_recover(src: &str) -> (Template, Vec<DiagnosticError>) {
+    if let Err(err) = check_limits(src, Limits::default()) {
+        return (Template { nodes: Vec::new() }, err.diagnostics());
+    }
+    let (output, errs) = recovering_template_parser().parse(src).into_output_errors();
+    let template = output.unwrap_or(Template { nodes: Vec::new() });
+    (template, errs.iter().map(to_diagnostic).collect())
+}
+
+fn recovering_template_parser<'src>(
+) -> impl Parser<'src, &'src str, Template, extra::Err<Simple<'src, char>>> {
+    node_parser()
+        .recover_with(skip_then_retry_until(any().ignored(), end()))
+        .repeated()
+        .collect::<Vec<_>>()
+        .map(|nodes| Template { nodes })
+}
+
 fn template_parser<'src>() -> impl Parser<'src, &'src str, Template, extra::Err<Simple<'src, char>>>
 {
     node_parser()
@@ -48,35 +169,45 @@ fn template_parser<'src>() -> impl Parser<'src, &'src str, Template, extra::Err<
         .map(|nodes| Template { nodes })
 }
 
-/// Parser for a single node. Used both at the top level and for nested parsing in options.
+/// Parser for a single node at the top level of a template.
 fn node_parser<'src>(
 ) -> impl Parser<'src, &'src str, (Node, Span), extra::Err<Simple<'src, char>>> + Clone {
     // Order matters for precedence:
     // 1. {{ slot }} - must come before { to avoid confusion
     // 2. { inline options } - inline options with | separator
-    // 3. @"quoted" - quoted library ref
-    // 4. @identifier - simple library ref
-    // 5. # comment - line comment
-    // 6. text - everything else
+    // 3. @{Name | transform}, @"quoted", @identifier - library refs
+    // 4. # comment - line comment
+    // 5. text - everything else
+
+    // Nodes inside an option branch, where text and comments also stop at
+    // the `|` or `}` that ends the branch
+    let option_node = recursive(|option_node| {
+        choice((
+            slot_parser(),
+            inline_options_parser(option_node),
+            library_ref_parser(),
+            option_comment_parser(),
+            option_text_parser(),
+        ))
+    });
 
     let slot_node = slot_parser();
-    let inline_options_node = inline_options_parser();
-    let quoted_lib_ref_node = quoted_library_ref_parser();
-    let simple_lib_ref_node = simple_library_ref_parser();
+    let inline_options_node = inline_options_parser(option_node);
+    let lib_ref_node = library_ref_parser();
     let comment_node = comment_parser();
     let text_node = text_parser();
 
     choice((
         slot_node,
         inline_options_node,
-        quoted_lib_ref_node,
-        simple_lib_ref_node,
+        lib_ref_node,
         comment_node,
         text_node,
     ))
 }
 
-/// Parse `{{ slot name }}` - user-provided slot
+/// Parse `{{ slot name }}` - user-provided slot, required if the name ends with `!`,
+/// or `{{= slot name }}` - a reference to another slot's value
 fn slot_parser<'src>(
 ) -> impl Parser<'src, &'src str, (Node, Span), extra::Err<Simple<'src, char>>> + Clone {
     just("{{")
@@ -87,72 +218,208 @@ fn slot_parser<'src>(
                 .map(|s| s.trim().to_string()),
         )
         .then_ignore(just("}}"))
-        .map_with(|name, e| (Node::Slot(name), to_range(e.span())))
+        .map_with(|name, e| {
+            if let Some(target) = name.strip_prefix('=') {
+                return (Node::SlotRef(target.trim_start().to_string()), to_range(e.span()));
+            }
+            let slot = match name.strip_suffix('!') {
+                Some(name) => Slot::required(name.trim_end()),
+                None => Slot::new(name),
+            };
+            (Node::Slot(slot), to_range(e.span()))
+        })
 }
 
-/// Parse `{a|b|c}` - inline options
-/// Options can contain nested grammar (like @Hair)
-fn inline_options_parser<'src>(
-) -> impl Parser<'src, &'src str, (Node, Span), extra::Err<Simple<'src, char>>> + Clone {
+/// Parse `{a|b|c}` - inline options, or `{text?}` - a maybe-option.
+///
+/// Each branch is parsed with `option_node` as part of the template, so a
+/// branch with a reference or nested options holds nodes whose spans point
+/// into the whole template. Branches are trimmed, except one written in
+/// double quotes, which is kept exactly: `{"red "|blue}` gives "red " and
+/// "blue". A branch that doesn't parse stays text; rendering reads it again
+/// and reports the error then.
+fn inline_options_parser<'src, P>(
+    option_node: P,
+) -> impl Parser<'src, &'src str, (Node, Span), extra::Err<Simple<'src, char>>> + Clone
+where
+    P: Parser<'src, &'src str, (Node, Span), extra::Err<Simple<'src, char>>> + Clone,
+{
+    let whitespace = any().filter(|c: &char| c.is_whitespace()).repeated();
+    let branch_end = one_of("|}").rewind();
+
+    // A quote followed by anything but `|` or `}` is bare text
+    let quoted = quoted_string_parser()
+        .padded_by(whitespace)
+        .then_ignore(branch_end)
+        .map(OptionItem::Text);
+
+    let bare = option_node
+        .repeated()
+        .collect::<Vec<_>>()
+        .map_with(|nodes, e| bare_option(nodes, e.slice(), e.span().start))
+        .then_ignore(branch_end);
+
+    let unparsed = choice((none_of("{}|").ignored(), balanced_braces_parser()))
+        .repeated()
+        .to_slice()
+        .then_ignore(branch_end)
+        .map(|text: &str| OptionItem::Text(text.trim().to_string()));
+
+    // Empty branches are kept, so `{a|}` picks between "a" and nothing
+    let branches = choice((quoted, bare, unparsed))
+        .separated_by(just('|'))
+        .collect::<Vec<_>>();
+
     just('{')
-        .ignore_then(
-            // Parse content between braces, split by |
-            none_of("}").repeated().collect::<String>(),
-        )
+        .ignore_then(branches)
         .then_ignore(just('}'))
-        .map_with(|content, e| {
-            // Split by | and parse each option
-            let options: Vec<OptionItem> = content
-                .split('|')
-                .map(|opt| {
-                    let opt = opt.trim();
-                    // Check if option contains grammar (@ for lib refs)
-                    if opt.contains('@') {
-                        // For now, treat as text - nested parsing will be added later
-                        // TODO: Parse nested grammar in options
-                        OptionItem::Text(opt.to_string())
-                    } else {
-                        OptionItem::Text(opt.to_string())
-                    }
-                })
-                .collect();
-
-            (Node::InlineOptions(options), to_range(e.span()))
+        .map_with(|options, e| {
+            let source: &str = e.slice();
+            let span = to_range(e.span());
+            match parse_maybe_option(&source[1..source.len() - 1]) {
+                Some(maybe) => (Node::MaybeOption(maybe), span),
+                None => (Node::InlineOptions(options), span),
+            }
         })
 }
 
+/// Turn a bare branch, parsed into `nodes` from `source` at byte `start`,
+/// into an option item.
+///
+/// A branch without a reference or nested options stays text. Otherwise the
+/// nodes are kept, trimmed like the text would be.
+fn bare_option(mut nodes: Vec<Spanned<Node>>, source: &str, start: usize) -> OptionItem {
+    if !source.contains(['@', '{']) {
+        return OptionItem::Text(source.trim().to_string());
+    }
+
+    if let Some((Node::Text(text), span)) = nodes.first_mut() {
+        let trimmed = text.trim_start();
+        span.start += text.len() - trimmed.len();
+        *text = trimmed.to_string();
+    }
+    if let Some((node, span)) = nodes.last_mut() {
+        if let Node::Text(text) = node {
+            text.truncate(text.trim_end().len());
+        }
+        span.end = span.end.min(start + source.trim_end().len());
+    }
+    nodes.retain(|(node, _)| !matches!(node, Node::Text(text) if text.is_empty()));
+    OptionItem::Nested(nodes)
+}
+
+/// Recognize `{...}` with balanced braces inside.
+fn balanced_braces_parser<'src>(
+) -> impl Parser<'src, &'src str, (), extra::Err<Simple<'src, char>>> + Clone {
+    recursive(|balanced| {
+        just('{')
+            .then(choice((none_of("{}").ignored(), balanced)).repeated())
+            .then(just('}'))
+            .ignored()
+    })
+}
+
+/// Read `text?` or `text?75` between an inline option's braces.
+///
+/// Only a single bare option qualifies, and the number must be at most 100;
+/// anything else is left to be read as ordinary inline options.
+fn parse_maybe_option(content: &str) -> Option<MaybeOption> {
+    if content.contains('|') || content.trim_start().starts_with('"') {
+        return None;
+    }
+    let (text, chance) = content.trim().rsplit_once('?')?;
+    let chance = match chance {
+        "" => MaybeOption::DEFAULT_CHANCE,
+        digits if digits.bytes().all(|b| b.is_ascii_digit()) => {
+            digits.parse().ok().filter(|chance| *chance <= 100)?
+        }
+        _ => return None,
+    };
+
+    let text = text.trim();
+    (!text.is_empty()).then(|| MaybeOption {
+        text: text.to_string(),
+        chance,
+    })
+}
+
+/// Parse any library reference, with an optional `?? "fallback"` suffix.
+///
+/// The transformed form must come before the simple form, since both start with `@`.
+fn library_ref_parser<'src>(
+) -> impl Parser<'src, &'src str, (Node, Span), extra::Err<Simple<'src, char>>> + Clone {
+    choice((
+        transformed_library_ref_parser(),
+        quoted_library_ref_parser(),
+        simple_library_ref_parser(),
+    ))
+    .then(fallback_parser().or_not())
+    .map_with(|(lib_ref, fallback), e| {
+        let lib_ref = match fallback {
+            Some(fallback) => lib_ref.with_fallback(fallback),
+            None => lib_ref,
+        };
+        (Node::LibraryRef(lib_ref), to_range(e.span()))
+    })
+}
+
 /// Parse `@"Name"` or `@"Lib:Name"` - quoted library reference
 fn quoted_library_ref_parser<'src>(
-) -> impl Parser<'src, &'src str, (Node, Span), extra::Err<Simple<'src, char>>> + Clone {
-    just("@\"")
-        .ignore_then(none_of("\"").repeated().collect::<String>())
-        .then_ignore(just('"'))
-        .map_with(|name, e| {
-            let lib_ref = parse_library_ref_string(&name);
-            (Node::LibraryRef(lib_ref), to_range(e.span()))
-        })
+) -> impl Parser<'src, &'src str, LibraryRef, extra::Err<Simple<'src, char>>> + Clone {
+    just('@')
+        .ignore_then(quoted_string_parser())
+        .map(|name| parse_library_ref_string(&name))
+}
+
+/// Parse `@{Name | upper}` or `@{"Lib:Name" | lower}` - library reference with a case transform
+fn transformed_library_ref_parser<'src>(
+) -> impl Parser<'src, &'src str, LibraryRef, extra::Err<Simple<'src, char>>> + Clone {
+    let transform = choice((
+        just("upper").to(CaseKind::Upper),
+        just("lower").to(CaseKind::Lower),
+        just("title").to(CaseKind::Title),
+    ));
+
+    just("@{")
+        .ignore_then(choice((quoted_string_parser(), identifier_parser())).padded())
+        .then_ignore(just('|'))
+        .then(transform.padded())
+        .then_ignore(just('}'))
+        .map(|(name, transform)| parse_library_ref_string(&name).with_transform(transform))
 }
 
 /// Parse `@Name` - simple library reference (no spaces allowed in name)
 fn simple_library_ref_parser<'src>(
-) -> impl Parser<'src, &'src str, (Node, Span), extra::Err<Simple<'src, char>>> + Clone {
-    just('@')
-        .ignore_then(
-            // Identifier: starts with letter or underscore, followed by letters, digits, underscores, hyphens
+) -> impl Parser<'src, &'src str, LibraryRef, extra::Err<Simple<'src, char>>> + Clone {
+    just('@').ignore_then(identifier_parser()).map(LibraryRef::new)
+}
+
+/// Parse ` ?? "literal"` - text used when the referenced group is missing or empty
+fn fallback_parser<'src>(
+) -> impl Parser<'src, &'src str, String, extra::Err<Simple<'src, char>>> + Clone {
+    just("??").padded_by(just(' ').repeated()).ignore_then(quoted_string_parser())
+}
+
+/// Parse `"..."` - a double-quoted string without escapes
+fn quoted_string_parser<'src>(
+) -> impl Parser<'src, &'src str, String, extra::Err<Simple<'src, char>>> + Clone {
+    just('"')
+        .ignore_then(none_of("\"").repeated().collect::<String>())
+        .then_ignore(just('"'))
+}
+
+/// Identifier: starts with letter or underscore, followed by letters, digits, underscores, hyphens
+fn identifier_parser<'src>(
+) -> impl Parser<'src, &'src str, String, extra::Err<Simple<'src, char>>> + Clone {
+    any()
+        .filter(|c: &char| c.is_alphabetic() || *c == '_')
+        .then(
             any()
-                .filter(|c: &char| c.is_alphabetic() || *c == '_')
-                .then(
-                    any()
-                        .filter(|c: &char| c.is_alphanumeric() || *c == '_' || *c == '-')
-                        .repeated()
-                        .collect::<String>(),
-                )
-                .map(|(first, rest)| format!("{}{}", first, rest)),
+                .filter(|c: &char| c.is_alphanumeric() || *c == '_' || *c == '-')
+                .repeated()
+                .collect::<String>(),
         )
-        .map_with(|name, e| {
-            let lib_ref = LibraryRef::new(name);
-            (Node::LibraryRef(lib_ref), to_range(e.span()))
-        })
+        .map(|(first, rest)| format!("{}{}", first, rest))
 }
 
 /// Parse `# comment to end of line`
@@ -175,6 +442,29 @@ fn text_parser<'src>(
         .map_with(|value, e| (Node::Text(value), to_range(e.span())))
 }
 
+/// Parse `# comment` inside an option branch, up to the end of the line or
+/// of the branch
+fn option_comment_parser<'src>(
+) -> impl Parser<'src, &'src str, (Node, Span), extra::Err<Simple<'src, char>>> + Clone {
+    just('#')
+        .ignore_then(
+            choice((none_of("{}|\n").ignored(), balanced_braces_parser()))
+                .repeated()
+                .to_slice(),
+        )
+        .map_with(|text: &str, e| (Node::Comment(text.trim().to_string()), to_range(e.span())))
+}
+
+/// Parse plain text inside an option branch
+fn option_text_parser<'src>(
+) -> impl Parser<'src, &'src str, (Node, Span), extra::Err<Simple<'src, char>>> + Clone {
+    none_of("{@#}|")
+        .repeated()
+        .at_least(1)
+        .collect::<String>()
+        .map_with(|value, e| (Node::Text(value), to_range(e.span())))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -191,7 +481,7 @@ mod tests {
         assert_eq!(tmpl.nodes.len(), 1);
         let (node, _span) = &tmpl.nodes[0];
         match node {
-            Node::Slot(name) => assert_eq!(name, "scene description"),
+            Node::Slot(slot) => assert_eq!(slot.name, "scene description"),
             other => panic!("expected Slot, got {:?}", other),
         }
     }
@@ -204,11 +494,53 @@ mod tests {
         assert_eq!(tmpl.nodes.len(), 1);
         let (node, _span) = &tmpl.nodes[0];
         match node {
-            Node::Slot(name) => assert_eq!(name, "name"),
+            Node::Slot(slot) => assert_eq!(slot, &Slot::new("name")),
             other => panic!("expected Slot, got {:?}", other),
         }
     }
 
+    #[test]
+    fn parses_required_slot() {
+        let tmpl = parse_template("{{ name! }} {{subject!}}").expect("should parse");
+
+        assert_eq!(tmpl.nodes[0].0, Node::Slot(Slot::required("name")));
+        assert_eq!(tmpl.nodes[2].0, Node::Slot(Slot::required("subject")));
+    }
+
+    #[test]
+    fn parses_maybe_option() {
+        let tmpl = parse_template("{rainy?}, { misty ?75}, {foggy?0}").expect("should parse");
+        let maybe = |text: &str, chance| {
+            Node::MaybeOption(MaybeOption {
+                text: text.to_string(),
+                chance,
+            })
+        };
+
+        assert_eq!(tmpl.nodes[0].0, maybe("rainy", 50));
+        assert_eq!(tmpl.nodes[2].0, maybe("misty", 75));
+        assert_eq!(tmpl.nodes[4].0, maybe("foggy", 0));
+    }
+
+    #[test]
+    fn maybe_option_shorthand_needs_one_bare_option() {
+        for source in ["{rainy?|dry}", "{\"rainy?\"}", "{rainy?101}", "{rainy?x}", "{?}"] {
+            let tmpl = parse_template(source).expect("should parse");
+            assert!(
+                matches!(tmpl.nodes[0].0, Node::InlineOptions(_)),
+                "{source} should be inline options"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_slot_ref() {
+        let tmpl = parse_template("{{= hero name }} {{=other}}").expect("should parse");
+
+        assert_eq!(tmpl.nodes[0].0, Node::SlotRef("hero name".to_string()));
+        assert_eq!(tmpl.nodes[2].0, Node::SlotRef("other".to_string()));
+    }
+
     // =========================================================================
     // Inline options tests
     // =========================================================================
@@ -231,6 +563,35 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parses_quoted_inline_options_verbatim() {
+        let tmpl = parse_template(r#"{ "red " | blue |" a|b"}"#).expect("should parse");
+
+        match &tmpl.nodes[0].0 {
+            Node::InlineOptions(options) => {
+                assert_eq!(
+                    options,
+                    &vec![
+                        OptionItem::Text("red ".into()),
+                        OptionItem::Text("blue".into()),
+                        OptionItem::Text(" a|b".into()),
+                    ]
+                );
+            }
+            other => panic!("expected InlineOptions, got {:?}", other),
+        }
+
+        // Quotes that don't wrap the whole option are ordinary text
+        let tmpl = parse_template(r#"{"big" dog|cat}"#).expect("should parse");
+        assert_eq!(
+            tmpl.nodes[0].0,
+            Node::InlineOptions(vec![
+                OptionItem::Text(r#""big" dog"#.into()),
+                OptionItem::Text("cat".into()),
+            ])
+        );
+    }
+
     #[test]
     fn parses_inline_options_with_spaces() {
         let src = "{hot weather | cold weather}";
@@ -248,6 +609,140 @@ mod tests {
         }
     }
 
+    #[test]
+    fn keeps_empty_inline_option_branches() {
+        let options = |src: &str| {
+            let tmpl = parse_template(src).expect("should parse");
+            match tmpl.nodes[0].0.clone() {
+                Node::InlineOptions(options) => options,
+                other => panic!("expected InlineOptions, got {:?}", other),
+            }
+        };
+        let text = |t: &str| OptionItem::Text(t.to_string());
+
+        assert_eq!(options("{red|blue|}"), vec![text("red"), text("blue"), text("")]);
+        assert_eq!(options("{|red}"), vec![text(""), text("red")]);
+        assert_eq!(options("{red| |blue}"), vec![text("red"), text(""), text("blue")]);
+    }
+
+    #[test]
+    fn parses_nested_inline_options() {
+        let src = "a {@Hair|{red|blue} hair|plain}";
+        let tmpl = parse_template(src).expect("should parse");
+
+        let options = match &tmpl.nodes[1].0 {
+            Node::InlineOptions(options) => options,
+            other => panic!("expected InlineOptions, got {:?}", other),
+        };
+        assert_eq!(options.len(), 3);
+
+        let kinds = |item: &OptionItem| -> Vec<&'static str> {
+            match item {
+                OptionItem::Nested(nodes) => nodes
+                    .iter()
+                    .map(|(node, _)| match node {
+                        Node::LibraryRef(_) => "LibraryRef",
+                        Node::InlineOptions(_) => "InlineOptions",
+                        Node::Text(_) => "Text",
+                        other => panic!("unexpected {:?}", other),
+                    })
+                    .collect(),
+                OptionItem::Text(text) => panic!("expected Nested, got Text({text})"),
+            }
+        };
+        assert_eq!(kinds(&options[0]), vec!["LibraryRef"]);
+        assert_eq!(kinds(&options[1]), vec!["InlineOptions", "Text"]);
+        assert_eq!(options[2], OptionItem::Text("plain".into()));
+
+        // Nested spans point into the whole template
+        let OptionItem::Nested(nested) = &options[1] else { unreachable!() };
+        assert_eq!(&src[nested[0].1.clone()], "{red|blue}");
+        assert_eq!(&src[nested[1].1.clone()], " hair");
+        let OptionItem::Nested(inner) = &options[0] else { unreachable!() };
+        assert_eq!(&src[inner[0].1.clone()], "@Hair");
+    }
+
+    #[test]
+    fn quoted_and_unparseable_options_stay_text() {
+        let tmpl = parse_template(r#"{"@Hair"|@|x}"#).expect("should parse");
+        assert_eq!(
+            tmpl.nodes[0].0,
+            Node::InlineOptions(vec![
+                OptionItem::Text("@Hair".into()),
+                OptionItem::Text("@".into()),
+                OptionItem::Text("x".into()),
+            ])
+        );
+    }
+
+    // =========================================================================
+    // Limits tests
+    // =========================================================================
+
+    #[test]
+    fn limits_reject_deeply_nested_braces() {
+        let src = format!("{}x{}", "{".repeat(100), "}".repeat(100));
+        let err = parse_template_with_limits(&src, Limits::default()).unwrap_err();
+        assert!(matches!(err, ParseError::TooDeeplyNested { max: 64, at: 64 }));
+        assert_eq!(err.diagnostics()[0].span, 64..65);
+
+        // Unbalanced input is rejected before parsing too
+        let src = "{".repeat(10_000);
+        let limits = Limits { max_depth: 8, ..Limits::default() };
+        assert!(matches!(
+            parse_template_with_limits(&src, limits),
+            Err(ParseError::TooDeeplyNested { max: 8, at: 8 })
+        ));
+    }
+
+    #[test]
+    fn parse_template_rejects_deep_nesting() {
+        let src = "{a|".repeat(1000);
+        assert!(matches!(
+            parse_template(&src),
+            Err(ParseError::TooDeeplyNested { max: 64, at: 192 })
+        ));
+        let (template, errors) = parse_template_recover(&src);
+        assert!(template.nodes.is_empty());
+        assert_eq!(errors[0].span, 192..193);
+
+        // The deepest nesting allowed still parses
+        let src = format!("{}x{}", "{a|".repeat(64), "}".repeat(64));
+        assert!(parse_template(&src).is_ok());
+    }
+
+    #[test]
+    fn parse_template_rejects_long_input() {
+        let src = "a".repeat(Limits::default().max_len + 1);
+        assert!(matches!(parse_template(&src), Err(ParseError::InputTooLarge { .. })));
+        let (template, errors) = parse_template_recover(&src);
+        assert!(template.nodes.is_empty());
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn limits_allow_ordinary_templates() {
+        let src = "@Hair {{ Scene }} {a|{b|{c|d}}}";
+        let limits = Limits { max_depth: 3, ..Limits::default() };
+        assert_eq!(
+            parse_template_with_limits(src, limits).unwrap().nodes,
+            parse_template(src).unwrap().nodes
+        );
+
+        // Closed braces don't add up
+        let flat = Limits { max_depth: 1, ..limits };
+        assert!(parse_template_with_limits("{a} {b} {c} {d}", flat).is_ok());
+    }
+
+    #[test]
+    fn limits_reject_long_input() {
+        let limits = Limits { max_len: 10, ..Limits::default() };
+        let err = parse_template_with_limits("a fairly long prompt", limits).unwrap_err();
+        assert!(matches!(err, ParseError::InputTooLarge { len: 20, max: 10 }));
+        assert_eq!(err.to_string(), "input is 20 bytes, more than the limit of 10");
+        assert!(parse_template_with_limits("short", limits).is_ok());
+    }
+
     // =========================================================================
     // Library reference tests
     // =========================================================================
@@ -348,6 +843,100 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parses_transformed_library_ref() {
+        let src = "@{Hair | upper}";
+        let tmpl = parse_template(src).expect("should parse");
+
+        assert_eq!(tmpl.nodes.len(), 1);
+        let (node, span) = &tmpl.nodes[0];
+        match node {
+            Node::LibraryRef(lib_ref) => {
+                assert_eq!(lib_ref.library, None);
+                assert_eq!(lib_ref.group, "Hair");
+                assert_eq!(lib_ref.transform, Some(CaseKind::Upper));
+            }
+            other => panic!("expected LibraryRef, got {:?}", other),
+        }
+        assert_eq!(*span, 0..15);
+    }
+
+    #[test]
+    fn parses_transformed_qualified_library_ref() {
+        let src = r#"@{"My Library:Eye Color"|title}"#;
+        let tmpl = parse_template(src).expect("should parse");
+
+        assert_eq!(tmpl.nodes.len(), 1);
+        let (node, _span) = &tmpl.nodes[0];
+        match node {
+            Node::LibraryRef(lib_ref) => {
+                assert_eq!(lib_ref.library, Some("My Library".to_string()));
+                assert_eq!(lib_ref.group, "Eye Color");
+                assert_eq!(lib_ref.transform, Some(CaseKind::Title));
+            }
+            other => panic!("expected LibraryRef, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn plain_library_ref_has_no_transform() {
+        let tmpl = parse_template("@Hair").expect("should parse");
+        match &tmpl.nodes[0].0 {
+            Node::LibraryRef(lib_ref) => assert_eq!(lib_ref.transform, None),
+            other => panic!("expected LibraryRef, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_transform() {
+        assert!(parse_template("@{Hair | shout}").is_err());
+    }
+
+    #[test]
+    fn parses_library_ref_fallback() {
+        let tmpl = parse_template(r#"@Hair ?? "brown hair", eyes"#).expect("should parse");
+        assert_eq!(tmpl.nodes.len(), 2);
+
+        let (node, span) = &tmpl.nodes[0];
+        assert_eq!(
+            node,
+            &Node::LibraryRef(LibraryRef::new("Hair").with_fallback("brown hair"))
+        );
+        assert_eq!(span, &(0..21));
+        assert_eq!(tmpl.nodes[1].0, Node::Text(", eyes".to_string()));
+    }
+
+    #[test]
+    fn parses_fallback_on_quoted_and_transformed_refs() {
+        let tmpl = parse_template(r#"@"Eye Color"??"grey" @{Hair | title} ?? "bald""#)
+            .expect("should parse");
+        let refs: Vec<_> = tmpl
+            .nodes
+            .iter()
+            .filter_map(|(node, _)| match node {
+                Node::LibraryRef(lib_ref) => Some(lib_ref.clone()),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(
+            refs,
+            vec![
+                LibraryRef::new("Eye Color").with_fallback("grey"),
+                LibraryRef::new("Hair")
+                    .with_transform(CaseKind::Title)
+                    .with_fallback("bald"),
+            ]
+        );
+    }
+
+    #[test]
+    fn question_marks_without_literal_are_text() {
+        let tmpl = parse_template("@Hair ?? maybe").expect("should parse");
+        assert_eq!(tmpl.nodes[0].0, Node::LibraryRef(LibraryRef::new("Hair")));
+        assert_eq!(tmpl.nodes[1].0, Node::Text(" ?? maybe".to_string()));
+    }
+
     // =========================================================================
     // Comment tests
     // =========================================================================
@@ -398,8 +987,10 @@ mod tests {
             .map(|(node, _)| match node {
                 Node::Text(_) => "Text",
                 Node::InlineOptions(_) => "InlineOptions",
+                Node::MaybeOption(_) => "MaybeOption",
                 Node::LibraryRef(_) => "LibraryRef",
                 Node::Slot(_) => "Slot",
+                Node::SlotRef(_) => "SlotRef",
                 Node::Comment(_) => "Comment",
             })
             .collect();
@@ -420,8 +1011,10 @@ mod tests {
             .map(|(node, _)| match node {
                 Node::Text(_) => "Text",
                 Node::InlineOptions(_) => "InlineOptions",
+                Node::MaybeOption(_) => "MaybeOption",
                 Node::LibraryRef(_) => "LibraryRef",
                 Node::Slot(_) => "Slot",
+                Node::SlotRef(_) => "SlotRef",
                 Node::Comment(_) => "Comment",
             })
             .collect();
@@ -472,8 +1065,10 @@ A {big|small} {cat|dog}
             .map(|(node, _)| match node {
                 Node::Text(_) => "Text",
                 Node::InlineOptions(_) => "InlineOptions",
+                Node::MaybeOption(_) => "MaybeOption",
                 Node::LibraryRef(_) => "LibraryRef",
                 Node::Slot(_) => "Slot",
+                Node::SlotRef(_) => "SlotRef",
                 Node::Comment(_) => "Comment",
             })
             .collect();
@@ -500,6 +1095,17 @@ A {big|small} {cat|dog}
         assert_eq!(span.end, 5);
     }
 
+    #[test]
+    fn parse_error_diagnostics_have_spans() {
+        let src = "@Hair {red|blue";
+        let err = parse_template(src).expect_err("unclosed brace should fail");
+
+        let diagnostics = err.diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "unexpected end of input");
+        assert_eq!(diagnostics[0].span, 15..15);
+    }
+
     #[test]
     fn spans_are_correct_for_inline_options() {
         let src = "{a|b}";
@@ -510,4 +1116,44 @@ A {big|small} {cat|dog}
         assert_eq!(span.start, 0);
         assert_eq!(span.end, 5);
     }
+
+    // =========================================================================
+    // Recovery tests
+    // =========================================================================
+
+    #[test]
+    fn recover_matches_parse_template_for_valid_source() {
+        let src = "@Hair with {red|blue} {{ Scene }} # note";
+        let (tmpl, errors) = parse_template_recover(src);
+
+        assert!(errors.is_empty());
+        assert_eq!(tmpl.nodes, parse_template(src).unwrap().nodes);
+    }
+
+    #[test]
+    fn recover_keeps_nodes_around_malformed_slot() {
+        let (tmpl, errors) = parse_template_recover("@Hair {{ bad and @Eyes");
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span, 22..22);
+
+        let nodes: Vec<&Node> = tmpl.nodes.iter().map(|(node, _)| node).collect();
+        assert_eq!(nodes.first(), Some(&&Node::LibraryRef(LibraryRef::new("Hair"))));
+        assert_eq!(nodes.last(), Some(&&Node::LibraryRef(LibraryRef::new("Eyes"))));
+        assert!(nodes.contains(&&Node::Text(" bad and ".to_string())));
+    }
+
+    #[test]
+    fn recover_skips_stray_closing_brace() {
+        let (tmpl, errors) = parse_template_recover("@Hair } and {{ Scene }}");
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span, 6..7);
+        assert_eq!(
+            tmpl.nodes.iter().map(|(_, span)| span.clone()).collect::<Vec<_>>(),
+            vec![0..5, 5..6, 7..12, 12..23]
+        );
+        assert_eq!(tmpl.nodes[3].0, Node::Slot(Slot::new("Scene")));
+    }
+
 }
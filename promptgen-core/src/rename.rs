@@ -0,0 +1,357 @@
+//! Renaming a group together with the references to it.
+//!
+//! `@Name` can appear in templates, in other groups' options and inside
+//! inline options or maybe-options of either, so a rename walks all of them.
+//! Templates are written back through [`template_to_source`], and option
+//! text has just its references replaced. Either way the new name is quoted
+//! when it needs quotes.
+
+use crate::ast::{LibraryRef, Node, OptionItem, Spanned};
+use crate::library::Library;
+use crate::parser::parse_template;
+use crate::source::{library_ref_to_source, template_to_source};
+use crate::span::Span;
+
+/// How many references [`Library::rename_group`] rewrote.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RenameReport {
+    /// References in templates.
+    pub template_refs: usize,
+    /// References in group options.
+    pub option_refs: usize,
+}
+
+impl RenameReport {
+    /// All references rewritten.
+    pub fn total(&self) -> usize {
+        self.template_refs + self.option_refs
+    }
+}
+
+impl Library {
+    /// Rename the group `old` to `new` and rewrite every reference to it.
+    ///
+    /// References qualified with another library's name or id are left alone.
+    /// Option notes and aliases follow an option whose text changes. Returns
+    /// `None`, changing nothing, if no group is named `old`; whether `new`
+    /// is already taken is up to the caller.
+    pub fn rename_group(&mut self, old: &str, new: &str) -> Option<RenameReport> {
+        let group = self.groups.iter_mut().find(|g| g.name == old)?;
+        group.name = new.to_string();
+
+        let renamer = RefRenamer {
+            library: &self.name,
+            library_id: &self.id,
+            old,
+            new,
+        };
+        let mut report = RenameReport::default();
+
+        for template in &mut self.templates {
+            let count = renamer.nodes(&mut template.ast.nodes);
+            if count > 0 {
+                // Reparse so spans match the new text
+                if let Ok(ast) = parse_template(&template_to_source(&template.ast)) {
+                    template.ast = ast;
+                }
+                report.template_refs += count;
+            }
+        }
+
+        for group in &mut self.groups {
+            for i in 0..group.options.len() {
+                let mut option = group.options[i].clone();
+                let count = renamer.text(&mut option);
+                if count == 0 {
+                    continue;
+                }
+                let before = std::mem::replace(&mut group.options[i], option.clone());
+                if let Some(meta) = group.option_meta.remove(&before) {
+                    group.option_meta.insert(option.clone(), meta);
+                }
+                for canonical in group.aliases.values_mut() {
+                    if *canonical == before {
+                        *canonical = option.clone();
+                    }
+                }
+                report.option_refs += count;
+            }
+        }
+
+        Some(report)
+    }
+}
+
+/// Rewrites the references to one group of one library.
+struct RefRenamer<'a> {
+    library: &'a str,
+    library_id: &'a str,
+    old: &'a str,
+    new: &'a str,
+}
+
+impl RefRenamer<'_> {
+    /// Rename matching references in `nodes`, returning how many there were.
+    fn nodes(&self, nodes: &mut [Spanned<Node>]) -> usize {
+        let mut count = 0;
+        for (node, _span) in nodes {
+            match node {
+                Node::LibraryRef(lib_ref) => {
+                    if self.matches(lib_ref) {
+                        lib_ref.group = self.new.to_string();
+                        count += 1;
+                    }
+                }
+                Node::InlineOptions(options) => {
+                    for option in options {
+                        count += match option {
+                            OptionItem::Text(text) => self.text(text),
+                            OptionItem::Nested(nested) => self.nodes(nested),
+                        };
+                    }
+                }
+                Node::MaybeOption(maybe) => count += self.text(&mut maybe.text),
+                Node::Text(_) | Node::Slot(_) | Node::SlotRef(_) | Node::Comment(_) => {}
+            }
+        }
+        count
+    }
+
+    /// Whether `lib_ref` refers to the group being renamed.
+    fn matches(&self, lib_ref: &LibraryRef) -> bool {
+        let here = lib_ref
+            .library
+            .as_deref()
+            .is_none_or(|lib| lib == self.library || lib == self.library_id);
+        here && lib_ref.group == self.old
+    }
+
+    /// Rename matching references in text that is parsed at render time.
+    /// Only the references are replaced, so the rest of the text keeps its
+    /// spacing.
+    fn text(&self, text: &mut String) -> usize {
+        let Ok(ast) = parse_template(text) else {
+            return 0;
+        };
+        let mut edits = Vec::new();
+        let count = self.edits(text, &ast.nodes, &mut edits);
+        edits.sort_by_key(|(span, _)| span.start);
+
+        let mut output = String::new();
+        let mut end = 0;
+        for (span, replacement) in edits {
+            output.push_str(&text[end..span.start]);
+            output.push_str(&replacement);
+            end = span.end;
+        }
+        output.push_str(&text[end..]);
+        *text = output;
+        count
+    }
+
+    /// Collect the replacements for matching references in `nodes`, parsed
+    /// from `source`, returning how many references there were.
+    fn edits(
+        &self,
+        source: &str,
+        nodes: &[Spanned<Node>],
+        edits: &mut Vec<(Span, String)>,
+    ) -> usize {
+        let mut count = 0;
+        for (node, span) in nodes {
+            match node {
+                Node::LibraryRef(lib_ref) if self.matches(lib_ref) => {
+                    let mut renamed = lib_ref.clone();
+                    renamed.group = self.new.to_string();
+                    let mut replacement = String::new();
+                    library_ref_to_source(&renamed, &mut replacement);
+                    edits.push((span.clone(), replacement));
+                    count += 1;
+                }
+                Node::InlineOptions(options) => {
+                    // A text branch is written as-is, quoted or not, so it
+                    // is found after the branch before it
+                    let mut pos = span.start + 1;
+                    for option in options {
+                        match option {
+                            OptionItem::Text(text) => {
+                                count += self.text_edits(source, pos..span.end, text, edits);
+                                pos += source[pos..].find(text.as_str()).unwrap_or(0) + text.len();
+                            }
+                            OptionItem::Nested(nested) => {
+                                count += self.edits(source, nested, edits);
+                                pos = nested.last().map_or(pos, |(_, span)| span.end);
+                            }
+                        }
+                    }
+                }
+                Node::MaybeOption(maybe) => {
+                    count += self.text_edits(source, span.clone(), &maybe.text, edits);
+                }
+                Node::LibraryRef(_)
+                | Node::Text(_)
+                | Node::Slot(_)
+                | Node::SlotRef(_)
+                | Node::Comment(_) => {}
+            }
+        }
+        count
+    }
+
+    /// Collect the replacement for `text`, which is parsed at render time
+    /// and written first in `within` of `source`, if it has references.
+    fn text_edits(
+        &self,
+        source: &str,
+        within: Span,
+        text: &str,
+        edits: &mut Vec<(Span, String)>,
+    ) -> usize {
+        let mut renamed = text.to_string();
+        let count = self.text(&mut renamed);
+        if count > 0 {
+            let start = within.start + source[within].find(text).unwrap_or(0);
+            edits.push((start..start + text.len(), renamed));
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::{OptionMeta, PromptGroup, PromptTemplate};
+
+    fn library(templates: &[&str]) -> Library {
+        let mut lib = Library::new("Mine");
+        lib.groups.push(PromptGroup::with_options(
+            "Hair",
+            vec!["red hair", "blue hair"],
+        ));
+        lib.groups.push(PromptGroup::with_options(
+            "Look",
+            vec!["@Hair and eyes", "plain"],
+        ));
+        for (i, source) in templates.iter().enumerate() {
+            let ast = parse_template(source).unwrap();
+            lib.templates
+                .push(PromptTemplate::new(format!("T{i}"), ast));
+        }
+        lib
+    }
+
+    fn source(lib: &Library, i: usize) -> String {
+        template_to_source(&lib.templates[i].ast)
+    }
+
+    #[test]
+    fn test_rename_rewrites_template_refs() {
+        let mut lib = library(&[
+            "@Hair, @{Hair | upper}, @Hairs",
+            r#"@"Mine:Hair" and @"Other:Hair" ?? "none""#,
+        ]);
+
+        let report = lib.rename_group("Hair", "Hair Color").unwrap();
+        assert_eq!(report.template_refs, 3);
+        assert!(lib.find_group("Hair Color").is_some());
+        assert!(lib.find_group("Hair").is_none());
+        assert_eq!(
+            source(&lib, 0),
+            r#"@"Hair Color", @{"Hair Color" | upper}, @Hairs"#
+        );
+        assert_eq!(
+            source(&lib, 1),
+            r#"@"Mine:Hair Color" and @"Other:Hair" ?? "none""#
+        );
+
+        // Spans point into the new source
+        let (_node, span) = &lib.templates[0].ast.nodes[0];
+        assert_eq!(*span, 0..13);
+    }
+
+    #[test]
+    fn test_rename_rewrites_nested_option_refs() {
+        let mut lib = library(&["{a|@Hair cut} {@Hair?}"]);
+        lib.groups[1].options.push("{short|@Hair}".to_string());
+        lib.groups[1].option_meta.insert(
+            "@Hair and eyes".to_string(),
+            OptionMeta {
+                note: Some("both".to_string()),
+                source: None,
+            },
+        );
+        lib.groups[1]
+            .aliases
+            .insert("hair-eyes".to_string(), "@Hair and eyes".to_string());
+
+        let report = lib.rename_group("Hair", "Mane").unwrap();
+        assert_eq!(
+            report,
+            RenameReport {
+                template_refs: 2,
+                option_refs: 2
+            }
+        );
+        assert_eq!(report.total(), 4);
+        assert_eq!(source(&lib, 0), "{a|@Mane cut} {@Mane?}");
+
+        let look = &lib.groups[1];
+        assert_eq!(look.options, ["@Mane and eyes", "plain", "{short|@Mane}"]);
+        assert_eq!(
+            look.option_meta["@Mane and eyes"].note.as_deref(),
+            Some("both")
+        );
+        assert_eq!(look.aliases["hair-eyes"], "@Mane and eyes");
+    }
+
+    #[test]
+    fn test_rename_keeps_option_spacing() {
+        let mut lib = library(&[]);
+        lib.groups[1].options = vec![
+            "@Hair and { a | b }".to_string(),
+            "{ x | @Hair } or {@Hair ?}".to_string(),
+            "{\"@Hair\" | x}".to_string(),
+        ];
+
+        let report = lib.rename_group("Hair", "Mane").unwrap();
+        assert_eq!(report.option_refs, 4);
+        assert_eq!(
+            lib.groups[1].options,
+            [
+                "@Mane and { a | b }",
+                "{ x | @Mane } or {@Mane ?}",
+                "{\"@Mane\" | x}"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rename_matches_library_id() {
+        let mut lib = library(&[]);
+        let qualified = format!("@\"{}:Hair\" cut", lib.id);
+        lib.groups[1].options.push(qualified);
+        let ast = parse_template(&lib.groups[1].options[2]).unwrap();
+        lib.templates.push(PromptTemplate::new("T", ast));
+
+        let report = lib.rename_group("Hair", "Mane").unwrap();
+        assert_eq!(
+            report,
+            RenameReport {
+                template_refs: 1,
+                option_refs: 2
+            }
+        );
+        assert_eq!(
+            lib.groups[1].options[2],
+            format!("@\"{}:Mane\" cut", lib.id)
+        );
+        assert!(lib.analyze_template(&source(&lib, 0)).errors.is_empty());
+    }
+
+    #[test]
+    fn test_rename_missing_group_changes_nothing() {
+        let mut lib = library(&["@Hair"]);
+        assert_eq!(lib.rename_group("Nope", "Other"), None);
+        assert_eq!(source(&lib, 0), "@Hair");
+    }
+}
@@ -0,0 +1,139 @@
+//! A compact S-expression dump of a parsed template, for debugging the
+//! grammar.
+//!
+//! `@Hair, {a|b}` prints as `(template (lib-ref Hair) (text ", ")
+//! (inline-options a b))`. Names and option text are written bare when they
+//! are a single word and quoted otherwise. Spans are left out.
+
+use crate::ast::{LibraryRef, Node, OptionItem, Spanned, Template};
+
+impl Template {
+    /// The template's structure as one line of nested parentheses.
+    pub fn to_sexpr(&self) -> String {
+        let mut output = String::from("(template");
+        nodes_to_sexpr(&self.nodes, &mut output);
+        output.push(')');
+        output
+    }
+}
+
+/// Append each node, preceded by a space.
+fn nodes_to_sexpr(nodes: &[Spanned<Node>], output: &mut String) {
+    for (node, _span) in nodes {
+        output.push(' ');
+        node_to_sexpr(node, output);
+    }
+}
+
+fn node_to_sexpr(node: &Node, output: &mut String) {
+    match node {
+        Node::Text(text) => {
+            output.push_str("(text ");
+            push_quoted(text, output);
+            output.push(')');
+        }
+        Node::Comment(text) => {
+            output.push_str("(comment ");
+            push_quoted(text, output);
+            output.push(')');
+        }
+        Node::Slot(slot) => {
+            output.push_str("(slot ");
+            push_atom(&slot.name, output);
+            if slot.required {
+                output.push_str(" :required");
+            }
+            output.push(')');
+        }
+        Node::SlotRef(name) => {
+            output.push_str("(slot-ref ");
+            push_atom(name, output);
+            output.push(')');
+        }
+        Node::LibraryRef(lib_ref) => library_ref_to_sexpr(lib_ref, output),
+        Node::MaybeOption(maybe) => {
+            output.push_str("(maybe-option ");
+            push_atom(&maybe.text, output);
+            output.push_str(&format!(" :chance {})", maybe.chance));
+        }
+        Node::InlineOptions(options) => {
+            output.push_str("(inline-options");
+            for option in options {
+                output.push(' ');
+                match option {
+                    OptionItem::Text(text) => push_atom(text, output),
+                    OptionItem::Nested(nodes) => {
+                        output.push_str("(option");
+                        nodes_to_sexpr(nodes, output);
+                        output.push(')');
+                    }
+                }
+            }
+            output.push(')');
+        }
+    }
+}
+
+fn library_ref_to_sexpr(lib_ref: &LibraryRef, output: &mut String) {
+    output.push_str("(lib-ref ");
+    match &lib_ref.library {
+        Some(library) => push_atom(&format!("{}:{}", library, lib_ref.group), output),
+        None => push_atom(&lib_ref.group, output),
+    }
+    if let Some(transform) = lib_ref.transform {
+        output.push_str(" :");
+        output.push_str(transform.keyword());
+    }
+    if let Some(fallback) = &lib_ref.fallback {
+        output.push_str(" :fallback ");
+        push_quoted(fallback, output);
+    }
+    output.push(')');
+}
+
+/// Write `text` bare if it is a single word, otherwise quoted.
+fn push_atom(text: &str, output: &mut String) {
+    let bare = !text.is_empty()
+        && text
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'));
+    if bare {
+        output.push_str(text);
+    } else {
+        push_quoted(text, output);
+    }
+}
+
+/// Write `text` as a double-quoted string with Rust-style escapes.
+fn push_quoted(text: &str, output: &mut String) {
+    output.push_str(&format!("{:?}", text));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_template;
+
+    fn sexpr(source: &str) -> String {
+        parse_template(source).unwrap().to_sexpr()
+    }
+
+    #[test]
+    fn test_sexpr_mixed_template() {
+        assert_eq!(
+            sexpr("@Hair, {a|b}"),
+            r#"(template (lib-ref Hair) (text ", ") (inline-options a b))"#
+        );
+        assert_eq!(
+            sexpr("{{ Scene! }} {big dog|@Pet} # note"),
+            r#"(template (slot Scene :required) (text " ") (inline-options "big dog" (option (lib-ref Pet))) (text " ") (comment "note"))"#
+        );
+    }
+
+    #[test]
+    fn test_sexpr_library_ref_details() {
+        assert_eq!(
+            sexpr(r#"@{"MyLib:Eye Color" | upper} ?? "grey""#),
+            r#"(template (lib-ref "MyLib:Eye Color" :upper :fallback "grey"))"#
+        );
+    }
+}
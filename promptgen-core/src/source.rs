@@ -0,0 +1,269 @@
+//! Source reconstruction from a parsed template.
+//!
+//! This is the one place that turns an AST back into template text; the
+//! YAML writer and the desktop app both use it.
+
+use crate::ast::{LibraryRef, MaybeOption, Node, OptionItem, Template};
+
+/// Reconstruct source text from a parsed template AST.
+///
+/// Parsing the result gives back the same nodes. Spacing inside slots and
+/// transformed references is normalized.
+pub fn template_to_source(template: &Template) -> String {
+    let mut source = String::new();
+
+    for (node, _span) in &template.nodes {
+        node_to_source(node, &mut source);
+    }
+
+    source
+}
+
+/// Convert a single node to its source representation.
+pub(crate) fn node_to_source(node: &Node, output: &mut String) {
+    match node {
+        Node::Text(text) => output.push_str(text),
+
+        Node::Comment(text) => {
+            output.push_str("# ");
+            output.push_str(text);
+        }
+
+        Node::SlotRef(name) => {
+            output.push_str("{{= ");
+            output.push_str(name);
+            output.push_str(" }}");
+        }
+
+        Node::Slot(slot) => {
+            output.push_str("{{ ");
+            output.push_str(&slot.name);
+            if slot.required {
+                output.push('!');
+            }
+            output.push_str(" }}");
+        }
+
+        Node::LibraryRef(lib_ref) => {
+            library_ref_to_source(lib_ref, output);
+        }
+
+        Node::MaybeOption(maybe) => {
+            output.push('{');
+            output.push_str(&maybe.text);
+            output.push('?');
+            if maybe.chance != MaybeOption::DEFAULT_CHANCE {
+                output.push_str(&maybe.chance.to_string());
+            }
+            output.push('}');
+        }
+
+        Node::InlineOptions(options) => {
+            output.push('{');
+            for (i, option) in options.iter().enumerate() {
+                if i > 0 {
+                    output.push('|');
+                }
+                option_item_to_source(option, output);
+            }
+            output.push('}');
+        }
+    }
+}
+
+/// Convert a library reference to source.
+pub(crate) fn library_ref_to_source(lib_ref: &LibraryRef, output: &mut String) {
+    output.push('@');
+
+    let needs_quotes = lib_ref.library.is_some()
+        || lib_ref.group.contains(' ')
+        || lib_ref.group.contains(':');
+
+    // Transformed refs are wrapped: @{Name | upper}
+    if lib_ref.transform.is_some() {
+        output.push('{');
+    }
+
+    if needs_quotes {
+        output.push('"');
+        if let Some(lib) = &lib_ref.library {
+            output.push_str(lib);
+            output.push(':');
+        }
+        output.push_str(&lib_ref.group);
+        output.push('"');
+    } else {
+        output.push_str(&lib_ref.group);
+    }
+
+    if let Some(transform) = lib_ref.transform {
+        output.push_str(" | ");
+        output.push_str(transform.keyword());
+        output.push('}');
+    }
+
+    if let Some(fallback) = &lib_ref.fallback {
+        output.push_str(" ?? \"");
+        output.push_str(fallback);
+        output.push('"');
+    }
+}
+
+/// Convert an option item to source.
+fn option_item_to_source(item: &OptionItem, output: &mut String) {
+    match item {
+        OptionItem::Text(text) => {
+            // Spaces at either end and `|` only survive parsing inside quotes
+            let needs_quotes = text.trim() != text || text.contains('|');
+            if needs_quotes && !text.contains('"') {
+                output.push('"');
+                output.push_str(text);
+                output.push('"');
+            } else {
+                output.push_str(text);
+            }
+        }
+        OptionItem::Nested(nodes) => {
+            for (node, _span) in nodes {
+                node_to_source(node, output);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_template;
+    use crate::span::Span;
+
+    #[test]
+    fn test_template_source_reconstruction() {
+        let source = r#"@Hair with {{ EyeColor }} and {red|blue|green}"#;
+        let ast = parse_template(source).unwrap();
+        let reconstructed = template_to_source(&ast);
+
+        // Parse the reconstructed source and verify it works
+        let reparsed = parse_template(&reconstructed).unwrap();
+        assert_eq!(reparsed.nodes.len(), ast.nodes.len());
+    }
+
+    #[test]
+    fn test_template_source_reconstruction_qualified_ref() {
+        let source = r#"@"MyLib:Hair Color" with @Eyes"#;
+        let ast = parse_template(source).unwrap();
+        let reconstructed = template_to_source(&ast);
+
+        // Verify the qualified reference is preserved
+        assert!(reconstructed.contains(r#"@"MyLib:Hair Color""#));
+        assert!(reconstructed.contains("@Eyes"));
+    }
+
+    #[test]
+    fn test_template_source_reconstruction_transformed_ref() {
+        let source = r#"@{Hair | upper} and @{"MyLib:Eye Color" | title}"#;
+        let ast = parse_template(source).unwrap();
+        let reconstructed = template_to_source(&ast);
+
+        assert_eq!(reconstructed, source);
+    }
+
+    #[test]
+    fn test_template_source_reconstruction_fallback() {
+        let source = r#"@Hair ?? "brown hair" and @{"Eye Color" | upper} ?? "grey""#;
+        let ast = parse_template(source).unwrap();
+        let reconstructed = template_to_source(&ast);
+
+        assert_eq!(reconstructed, source);
+    }
+
+    #[test]
+    fn test_template_source_reconstruction_inline_options() {
+        let source = r#"A {big|small} {red|blue|green} car"#;
+        let ast = parse_template(source).unwrap();
+        let reconstructed = template_to_source(&ast);
+
+        assert_eq!(reconstructed, source);
+    }
+
+    #[test]
+    fn test_template_source_reconstruction_quoted_inline_options() {
+        let source = r#"{"red "|blue|" a|b"}"#;
+        let ast = parse_template(source).unwrap();
+        let reconstructed = template_to_source(&ast);
+
+        assert_eq!(reconstructed, source);
+    }
+
+    #[test]
+    fn test_template_source_reconstruction_slot() {
+        let source = r#"Hello {{ Name }}, welcome!"#;
+        let ast = parse_template(source).unwrap();
+        let reconstructed = template_to_source(&ast);
+
+        assert_eq!(reconstructed, source);
+    }
+
+    #[test]
+    fn test_template_source_reconstruction_required_slot() {
+        let source = r#"Hello {{ Name! }}, welcome to {{ Place }}"#;
+        let ast = parse_template(source).unwrap();
+        let reconstructed = template_to_source(&ast);
+
+        assert_eq!(reconstructed, source);
+    }
+
+    #[test]
+    fn test_template_source_reconstruction_maybe_option() {
+        let source = "{rainy?}, {misty?75}";
+        let ast = parse_template(source).unwrap();
+
+        assert_eq!(template_to_source(&ast), source);
+    }
+
+    #[test]
+    fn test_template_source_reconstruction_slot_ref() {
+        let source = "{{ Name }} also known as {{= Name }}";
+        let ast = parse_template(source).unwrap();
+        let reconstructed = template_to_source(&ast);
+
+        assert_eq!(reconstructed, source);
+    }
+
+    #[test]
+    fn test_template_source_reconstruction_comment() {
+        let source = "# This is a comment";
+        let ast = parse_template(source).unwrap();
+        let reconstructed = template_to_source(&ast);
+
+        assert_eq!(reconstructed, source);
+    }
+
+    #[test]
+    fn test_template_source_round_trip_preserves_comments() {
+        let source = "# standalone note\n@Hair # inline note\n{{ Scene }}";
+        let ast = parse_template(source).unwrap();
+        let reparsed = parse_template(&template_to_source(&ast)).unwrap();
+
+        let comments = |template: &Template| -> Vec<(String, Span)> {
+            template
+                .nodes
+                .iter()
+                .filter_map(|(node, span)| match node {
+                    Node::Comment(text) => Some((text.clone(), span.clone())),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            comments(&ast),
+            vec![
+                ("standalone note".to_string(), 0..17),
+                ("inline note".to_string(), 24..37),
+            ]
+        );
+        assert_eq!(comments(&reparsed), comments(&ast));
+        assert_eq!(reparsed.nodes.len(), ast.nodes.len());
+    }
+}
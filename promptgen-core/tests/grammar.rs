@@ -2,6 +2,7 @@
 //!
 //! Tests the new grammar syntax:
 //! - Library refs: `@Name` or `@"Name with spaces"` or `@"Lib:Name"`
+//! - Transformed library refs: `@{Name | upper}`
 //! - Inline options: `{a|b|c}`
 //! - Slots: `{{ slot name }}`
 //! - Comments: `# comment`
@@ -85,6 +86,20 @@ groups:
     assert_eq!(result.text, "blonde hair with blue eyes");
 }
 
+#[test]
+fn transformed_library_ref_renders() {
+    let lib = lib(r#"
+groups:
+  - name: Eye Color
+    options:
+      - deep blue
+"#);
+    let result = eval(&lib, r#"@{"Eye Color" | title} eyes, @{"Eye Color" | upper}"#, Some(42));
+
+    assert_eq!(result.text, "Deep Blue eyes, DEEP BLUE");
+    assert_eq!(result.chosen_options[0].option_text, "deep blue");
+}
+
 // ============================================================================
 // Inline Options Tests: {a|b|c}
 // ============================================================================
@@ -115,6 +130,17 @@ fn inline_options_with_text() {
     );
 }
 
+#[test]
+fn inline_options_empty_branch_is_a_real_choice() {
+    let lib = lib("groups: []");
+
+    let results: std::collections::HashSet<String> = (0..64)
+        .map(|seed| eval(&lib, "[{a|}]", Some(seed)).text)
+        .collect();
+
+    assert_eq!(results, ["[a]", "[]"].into_iter().map(String::from).collect());
+}
+
 #[test]
 fn inline_options_three_choices() {
     let lib = lib("groups: []");